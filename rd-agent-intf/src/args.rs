@@ -11,25 +11,33 @@ lazy_static::lazy_static! {
         "-d, --dir=[TOPDIR]     'Top-level dir for operation and scratch files (default: {dfl_dir})'
          -s, --scratch=[DIR]    'Scratch dir for workloads to use (default: $TOPDIR/scratch)'
          -D, --dev=[NAME]       'Override storage device autodetection (e.g. sda, nvme0n1)'
+             --iocost-devs=[NAMES] 'Additional devices to configure iocost on (e.g. sdb,nvme1n1)'
          -r, --rep-retention=[SECS]      '1s report retention in seconds (default: {dfl_rep_ret:.1}h)'
          -R, --rep-1min-retention=[SECS] '1m report retention in seconds (default: {dfl_rep_1m_ret:.1}h)'
              --systemd-timeout=[SECS] 'Systemd timeout (default: {dfl_systemd_timeout})'
+             --drilldown=[GLOBS] 'Report usages of matching cgroups too (e.g. system.slice/*)'
+             --drilldown-max=[NR] 'Maximum number of drill-down cgroups (default: {dfl_drilldown_max})'
              --passive=[SELS]   'Avoid system config changes (SELS=ALL/all/cpu/mem/io/fs/oomd/none)'
          -a, --args=[FILE]      'Load base command line arguments from FILE'
              --no-iolat         'Disable bpf-based io latency stat monitoring'
              --force            'Ignore startup check results and proceed'
              --force-running    'Ignore bench requirements and enter Running state'
              --prepare          'Prepare the files and directories and exit'
+             --dry-run          'Print system configuration changes without applying them and exit'
+             --restore          'Restore system settings from the restore journal and exit'
+             --save-profile=[NAME] 'Save current cmd, slices and oomd configs as profile NAME and exit'
              --linux-tar=[FILE] 'Path to linux source tarball for compile sideload (__SKIP__ to skip)'
              --bench-file=[FILE] 'Bench file name override'
              --reset            'Reset all states except for bench results, linux.tar and testfiles'
              --keep-reports     'Don't delete expired report files, also affects --reset'
              --bypass           'Skip startup and periodic health checks'
+             --delegated        'Operate inside a delegated cgroup subtree, skip host-level configs'
          -v...                  'Sets the level of verbosity'",
         dfl_dir = Args::default().dir,
         dfl_rep_ret = Args::default().rep_retention as f64 / 3600.0,
         dfl_rep_1m_ret = Args::default().rep_1min_retention as f64 / 3600.0,
         dfl_systemd_timeout = format_duration(Args::default().systemd_timeout),
+        dfl_drilldown_max = Args::default().drilldown_max,
     );
 
     static ref BANDIT_MEM_HOG_USAGE: String = format!(
@@ -38,6 +46,7 @@ lazy_static::lazy_static! {
          -R, --readers=[NR]           'Number of readers (default: 1)'
          -d, --debt=[DUR]             'Maximum debt accumulation (default, 10s)'
          -c, --compressibility=[FRAC] 'Content compressibility (default: 0)
+         -s, --seed=[SEED]            'Random seed for reproducible contents'
          -p, --report=[PATH]          'Report file path'"
     );
 
@@ -146,6 +155,7 @@ pub struct BanditMemHogArgs {
     pub max_debt: f64,
     pub nr_readers: u32,
     pub comp: f64,
+    pub seed: Option<u64>,
     pub report: Option<String>,
 }
 
@@ -157,6 +167,7 @@ impl Default for BanditMemHogArgs {
             max_debt: 10.0,
             nr_readers: 1,
             comp: 0.0,
+            seed: None,
             report: None,
         }
     }
@@ -173,9 +184,12 @@ pub struct Args {
     pub dir: String,
     pub scratch: Option<String>,
     pub dev: Option<String>,
+    pub iocost_devs: Vec<String>,
     pub rep_retention: u64,
     pub rep_1min_retention: u64,
     pub systemd_timeout: f64,
+    pub drilldown: Vec<String>,
+    pub drilldown_max: usize,
     pub enforce: EnforceConfig,
 
     #[serde(skip)]
@@ -187,6 +201,12 @@ pub struct Args {
     #[serde(skip)]
     pub prepare: bool,
     #[serde(skip)]
+    pub dry_run: bool,
+    #[serde(skip)]
+    pub restore: bool,
+    #[serde(skip)]
+    pub save_profile: Option<String>,
+    #[serde(skip)]
     pub linux_tar: Option<String>,
     #[serde(skip)]
     pub bench_file: Option<String>,
@@ -197,6 +217,8 @@ pub struct Args {
     #[serde(skip)]
     pub bypass: bool,
     #[serde(skip)]
+    pub delegated: bool,
+    #[serde(skip)]
     pub verbosity: u32,
 
     pub bandit: Option<Bandit>,
@@ -208,19 +230,26 @@ impl Default for Args {
             dir: "/var/lib/resctl-demo".into(),
             scratch: None,
             dev: None,
+            iocost_devs: vec![],
             rep_retention: 3600,
             rep_1min_retention: 24 * 3600,
             systemd_timeout: systemd::SYSTEMD_DFL_TIMEOUT,
+            drilldown: vec![],
+            drilldown_max: 32,
             enforce: Default::default(),
             no_iolat: false,
             force: false,
             force_running: false,
             prepare: false,
+            dry_run: false,
+            restore: false,
+            save_profile: None,
             linux_tar: None,
             bench_file: None,
             reset: false,
             keep_reports: false,
             bypass: false,
+            delegated: false,
             verbosity: 0,
             bandit: None,
         }
@@ -263,6 +292,14 @@ impl Args {
                     args.comp = parse_frac(v).unwrap();
                     updated_base = true;
                 }
+                if let Some(v) = subm.value_of("seed") {
+                    args.seed = if v.len() == 0 {
+                        None
+                    } else {
+                        Some(v.parse::<u64>().expect("failed to parse \"seed\""))
+                    };
+                    updated_base = true;
+                }
                 if let Some(v) = subm.value_of("report") {
                     args.report = if v.len() == 0 {
                         None
@@ -328,6 +365,14 @@ impl JsonArgs for Args {
             };
             updated_base = true;
         }
+        if let Some(v) = matches.value_of("iocost-devs") {
+            self.iocost_devs = v
+                .split(',')
+                .filter(|x| x.len() > 0)
+                .map(|x| x.to_string())
+                .collect();
+            updated_base = true;
+        }
 
         if let Some(v) = matches.value_of("rep-retention") {
             self.rep_retention = if v.len() > 0 {
@@ -356,16 +401,38 @@ impl JsonArgs for Args {
             updated_base = true;
         }
 
+        if let Some(v) = matches.value_of("drilldown") {
+            self.drilldown = v
+                .split(',')
+                .filter(|x| x.len() > 0)
+                .map(|x| x.trim_matches('/').to_string())
+                .collect();
+            updated_base = true;
+        }
+
+        if let Some(v) = matches.value_of("drilldown-max") {
+            self.drilldown_max = if v.len() > 0 {
+                v.parse::<usize>().unwrap()
+            } else {
+                dfl.drilldown_max
+            };
+            updated_base = true;
+        }
+
         self.no_iolat = matches.is_present("no-iolat");
         self.force = matches.is_present("force");
         self.force_running = matches.is_present("force-running");
         self.prepare = matches.is_present("prepare");
+        self.dry_run = matches.is_present("dry-run");
+        self.restore = matches.is_present("restore");
+        self.save_profile = matches.value_of("save-profile").map(|x| x.to_string());
         self.linux_tar = matches.value_of("linux-tar").map(|x| x.to_string());
         self.bench_file = matches.value_of("bench-file").map(|x| x.to_string());
         self.reset = matches.is_present("reset");
         self.keep_reports = matches.is_present("keep-reports");
         self.verbosity = Self::verbosity(&matches);
         self.bypass = matches.is_present("bypass");
+        self.delegated = matches.is_present("delegated");
 
         match matches.value_of("passive") {
             Some(passives) => self.enforce.parse_and_merge(passives).unwrap(),
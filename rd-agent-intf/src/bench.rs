@@ -4,6 +4,8 @@ use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
+use rd_util::anon_area::AnonMode;
+use rd_util::numa::NumaPolicy;
 use rd_util::*;
 
 pub const BENCH_FILENAME: &str = "bench.json";
@@ -21,9 +23,16 @@ const BENCH_DOC: &str = "\
 //  hashd[].mem_frac: Memory size is mem_size * mem_frac, tune this if needed
 //  hashd[].chunk_pages: Memory access chunk size in pages
 //  hashd[].fake_cpu_load: Bench was run with --bench-fake-cpu-load
+//  hashd[].anon_mode: Anon memory backing the bench was run with
+//  hashd[].numa: NUMA policy the bench was run with
+//  hashd[].seed: Random seed the bench was run with, null if random
 //  iocost.devnr: Storage device devnr
 //  iocost.model: Model parameters
 //  iocost.qos: QoS parameters
+//  iocost_devs[]: Parameters for additional devices specified with
+//                 --iocost-devs, matched by devnr. Benchmarked after the
+//                 scratch device if a btrfs, ext4 or xfs filesystem on the
+//                 device is mounted, otherwise left as-is.
 //
 ";
 
@@ -36,13 +45,16 @@ pub struct HashdKnobs {
     pub mem_frac: f64,
     pub chunk_pages: usize,
     pub fake_cpu_load: bool,
+    pub anon_mode: AnonMode,
+    pub numa: NumaPolicy,
+    pub seed: Option<u64>,
 }
 
 impl std::fmt::Display for HashdKnobs {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "hash_size={} rps_max={} mem_actual={} chunk_pages={}{}",
+            "hash_size={} rps_max={} mem_actual={} chunk_pages={}{}{}{}{}",
             format_size(self.hash_size),
             self.rps_max,
             format_size(self.actual_mem_size()),
@@ -51,6 +63,18 @@ impl std::fmt::Display for HashdKnobs {
                 " fake_cpu_load"
             } else {
                 ""
+            },
+            match self.anon_mode {
+                AnonMode::System => String::new(),
+                mode => format!(" anon_mode={:?}", mode),
+            },
+            match self.numa {
+                NumaPolicy::Default => String::new(),
+                numa => format!(" numa={:?}", numa),
+            },
+            match self.seed {
+                Some(seed) => format!(" seed={}", seed),
+                None => String::new(),
             }
         )
     }
@@ -76,6 +100,8 @@ pub struct BenchKnobs {
     pub iocost_seq: u64,
     pub hashd: HashdKnobs,
     pub iocost: IoCostKnobs,
+    #[serde(default)]
+    pub iocost_devs: Vec<IoCostKnobs>,
     pub iocost_dev_model: String,
     pub iocost_dev_fwrev: String,
     pub iocost_dev_size: u64,
@@ -89,6 +115,7 @@ impl Default for BenchKnobs {
             iocost_seq: 0,
             hashd: Default::default(),
             iocost: Default::default(),
+            iocost_devs: vec![],
             iocost_dev_model: String::new(),
             iocost_dev_fwrev: String::new(),
             iocost_dev_size: 0,
@@ -99,6 +126,9 @@ impl Default for BenchKnobs {
 impl JsonLoad for BenchKnobs {
     fn loaded(&mut self, _prev: Option<&mut Self>) -> Result<()> {
         self.iocost.qos.sanitize();
+        for iocost in self.iocost_devs.iter_mut() {
+            iocost.qos.sanitize();
+        }
         Ok(())
     }
 }
@@ -46,12 +46,23 @@ lazy_static::lazy_static! {
 //  hashd[].file_addr_stdev: Memory access stdev in ratio of mean, null to use ${dfl_file_addr_stdev}
 //  hashd[].anon_addr_stdev: Memory access stdev in ratio of mean, null to use ${dfl_anon_addr_stdev}
 //  hashd[].log_bps: IO write bandwidth, default ${dfl_log_bps}Mbps
+//  hashd[].log_mode: Log write durability - Buffered, Fdatasync, Direct or Dsync
 //  hashd[].weight: Relative weight between the two hashd instances
+//  hashd[].load_pattern: Periodic load pattern - Sine, Square or Sawtooth
+//  hashd[].load_amplitude: Load pattern amplitude relative to RPS target, 0 disables
+//  hashd[].load_period: Load pattern period, default ${dfl_load_period}s
+//  hashd[].spike_interval: Average interval between random load spikes, default ${dfl_spike_interval}s
+//  hashd[].spike_dur: Duration of each load spike, default ${dfl_spike_dur}s
+//  hashd[].spike_ratio: Load spike height relative to RPS target, 0 disables
+//  hashd[].seed: Random seed for reproducible runs, null for random
 //  sysloads{{}}: \"NAME\": \"DEF_ID\" pairs for active sysloads
 //  sideloads{{}}: \"NAME\": \"DEF_ID\" pairs for active sideloads
 //  swappiness: /proc/sys/vm/swappiness, null to leave as-is
 //  zswap_enabled: zswap enable flag, null to leave as-is
 //  balloon_ratio: Memory balloon size given as a ratio to total memory, default 0.0
+//  profile: Name of the profile in index::profiles to activate, null for none
+//  profile_seq: If > report::profile_seq, activate the profile replacing
+//               the rest of cmd.json, slices.json and oomd.json
 //
 ",
                 dfl_bench_balloon = Cmd::default().bench_hashd_balloon_size,
@@ -60,6 +71,9 @@ lazy_static::lazy_static! {
                 dfl_file_addr_stdev = rd_hashd_intf::Params::default().file_addr_stdev_ratio,
                 dfl_anon_addr_stdev = rd_hashd_intf::Params::default().anon_addr_stdev_ratio,
                 dfl_log_bps = to_mb(rd_hashd_intf::Params::default().log_bps),
+                dfl_load_period = rd_hashd_intf::Params::default().load_period,
+                dfl_spike_interval = rd_hashd_intf::Params::default().spike_interval,
+                dfl_spike_dur = rd_hashd_intf::Params::default().spike_dur,
         )
     };
 }
@@ -70,6 +84,7 @@ pub struct SideloaderCmd {
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct HashdCmd {
     pub active: bool,
     pub lat_target_pct: f64,
@@ -81,7 +96,15 @@ pub struct HashdCmd {
     pub file_ratio: f64,
     pub file_max_ratio: f64,
     pub log_bps: u64,
+    pub log_mode: rd_hashd_intf::LogMode,
     pub weight: f64,
+    pub load_pattern: rd_hashd_intf::LoadPattern,
+    pub load_amplitude: f64,
+    pub load_period: f64,
+    pub spike_interval: f64,
+    pub spike_dur: f64,
+    pub spike_ratio: f64,
+    pub seed: Option<u64>,
 }
 
 impl Default for HashdCmd {
@@ -97,7 +120,15 @@ impl Default for HashdCmd {
             file_ratio: rd_hashd_intf::Params::default().file_frac,
             file_max_ratio: rd_hashd_intf::Args::default().file_max_frac,
             log_bps: rd_hashd_intf::Params::default().log_bps,
+            log_mode: Default::default(),
             weight: 1.0,
+            load_pattern: Default::default(),
+            load_amplitude: 0.0,
+            load_period: rd_hashd_intf::Params::default().load_period,
+            spike_interval: rd_hashd_intf::Params::default().spike_interval,
+            spike_dur: rd_hashd_intf::Params::default().spike_dur,
+            spike_ratio: 0.0,
+            seed: None,
         }
     }
 }
@@ -117,6 +148,8 @@ pub struct Cmd {
     pub swappiness: Option<u32>,
     pub zswap_enabled: Option<bool>,
     pub balloon_ratio: f64,
+    pub profile: Option<String>,
+    pub profile_seq: u64,
 }
 
 impl Cmd {
@@ -140,6 +173,8 @@ impl Default for Cmd {
             swappiness: None,
             zswap_enabled: None,
             balloon_ratio: 0.0,
+            profile: None,
+            profile_seq: 0,
         }
     }
 }
@@ -22,6 +22,7 @@ const INDEX_DOC: &str = "\
 //  hashd[].params: rd-hashd runtime adjustable parameters
 //  hashd[].report: rd-hashd summary report
 //  sideload_defs: Side and sys workload definitions
+//  profiles: Configuration profile directory
 //
 ";
 
@@ -47,6 +48,8 @@ pub struct Index {
     pub sideloader_status: String,
     pub hashd: [HashdIndex; 2],
     pub sideload_defs: String,
+    #[serde(default)]
+    pub profiles: String,
 }
 
 impl JsonLoad for Index {}
@@ -12,6 +12,7 @@ pub mod cmd;
 pub mod cmd_ack;
 pub mod index;
 pub mod oomd;
+pub mod profile;
 pub mod report;
 pub mod side_defs;
 pub mod slices;
@@ -23,16 +24,20 @@ pub use bench::{BenchKnobs, HashdKnobs, IoCostKnobs, BENCH_FILENAME};
 pub use cmd::{Cmd, HashdCmd, SideloaderCmd};
 pub use cmd_ack::CmdAck;
 pub use index::Index;
-pub use oomd::{OomdKnobs, OomdSliceMemPressureKnobs, OomdSliceSenpaiKnobs};
+pub use oomd::{OomdKnobs, OomdSliceMemPressureKnobs, OomdSliceSenpaiKnobs, SenpaiMode};
+pub use profile::Profile;
 pub use report::{
     BenchHashdReport, BenchIoCostReport, HashdReport, IoCostModelReport, IoCostQoSReport,
-    IoCostReport, IoLatReport, OomdReport, Report, ReportIter, ReportPathIter, ResCtlReport,
-    SideloadReport, SideloaderReport, StatMap, SvcReport, SvcStateReport, SysloadReport,
-    UsageReport,
+    IoCostReport, IoLatReport, MemEventsReport, OomdKillReport, OomdReport, Report, ReportIter,
+    ReportPathIter, ResCtlReport, SenpaiReport, SideloadReport, SideloaderReport, StatMap,
+    SvcReport, SvcStateReport, SwapDevReport, SwapKind, SysloadReport, UsageReport, ZramReport,
 };
 pub use side_defs::{SideloadDefs, SideloadSpec};
-pub use slices::{DisableSeqKnobs, MemoryKnob, Slice, SliceConfig, SliceKnobs, ROOT_SLICE};
-pub use sysreqs::{MissedSysReqs, SysReq, SysReqsReport, ALL_SYSREQS_SET};
+pub use slices::{
+    cgrp_base, set_cgrp_base, DisableSeqKnobs, MemoryKnob, Slice, SliceConfig, SliceKnobs,
+    CGRP_ROOT, ROOT_SLICE,
+};
+pub use sysreqs::{MissedSysReqs, SysReq, SysReqsReport, ALL_SYSREQS_SET, HOST_SYSREQS_SET};
 
 lazy_static::lazy_static! {
     pub static ref VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -13,6 +13,10 @@ const OOMD_DOC: &str = "\
 //  workload.mem_pressure.threshold: Pressure threshold
 //  workload.mem_pressure.threshold: Pressure duration
 //  workload.senpai.enable: Enable senpai in workload.slice
+//  workload.senpai.mode: Oomd for the fb-oomd plugin, MemHigh or MemReclaim for
+//                        rd-agent's native memory.high or memory.reclaim senpai
+//  workload.senpai.stall_threshold: Memory stall ratio, i.e. stalled seconds
+//                                   per second, to converge on
 //  workload.senpai.*: Senpai parameters
 //  system.*: The same set of parameters for system.slice
 //  swap_enable: Enable swap depletion protection
@@ -27,12 +31,29 @@ pub struct OomdSliceMemPressureKnobs {
     pub duration: u32,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SenpaiMode {
+    Oomd,
+    MemHigh,
+    MemReclaim,
+}
+
+impl Default for SenpaiMode {
+    fn default() -> Self {
+        Self::Oomd
+    }
+}
+
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct OomdSliceSenpaiKnobs {
     pub enable: bool,
+    #[serde(default)]
+    pub mode: SenpaiMode,
     pub min_bytes_frac: f64,
     pub max_bytes_frac: f64,
     pub interval: u32,
+    /// Memory stall ratio (some stall time / elapsed time) which senpai
+    /// converges on. Independent of `interval`.
     pub stall_threshold: f64,
     pub max_probe: f64,
     pub max_backoff: f64,
@@ -44,6 +65,7 @@ impl Default for OomdSliceSenpaiKnobs {
     fn default() -> Self {
         Self {
             enable: false,
+            mode: Default::default(),
             min_bytes_frac: 0.0,
             max_bytes_frac: 1.0,
             interval: 2,
@@ -0,0 +1,169 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Cmd, OomdKnobs, SliceKnobs};
+use rd_util::*;
+
+const PROFILE_DOC: &str = "\
+//
+// rd-agent configuration profile
+//
+// A named snapshot of cmd.json, slices.json and oomd.json which can be
+// activated atomically by setting cmd::profile and bumping cmd::profile_seq.
+// Sequence fields are not part of the profile. Disable sequences are recorded
+// as 0 when enabled and 18446744073709551615 (u64::MAX) when disabled.
+//
+//  cmd: The same format as cmd.json
+//  slices: The same format as slices.json
+//  oomd: The same format as oomd.json
+//
+";
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub cmd: Cmd,
+    pub slices: SliceKnobs,
+    pub oomd: OomdKnobs,
+}
+
+impl Profile {
+    /// Profile file path for `name` in `dir`. Names which could escape
+    /// `dir` are rejected.
+    pub fn path(dir: &str, name: &str) -> Result<String> {
+        if name.len() == 0 || name.contains('/') || name.contains("..") {
+            bail!("invalid profile name {:?}", name);
+        }
+        Ok(format!("{}/{}.json", dir, name))
+    }
+
+    fn norm_disable_seq(seq: u64, instance_seq: u64) -> u64 {
+        if seq >= instance_seq {
+            std::u64::MAX
+        } else {
+            0
+        }
+    }
+
+    /// Snapshot the current configurations. `instance_seq` is used to
+    /// resolve the disable sequences.
+    pub fn snapshot(cmd: &Cmd, slices: &SliceKnobs, oomd: &OomdKnobs, instance_seq: u64) -> Self {
+        let mut prof = Self {
+            cmd: cmd.clone(),
+            slices: slices.clone(),
+            oomd: oomd.clone(),
+        };
+
+        let pc = &mut prof.cmd;
+        pc.cmd_seq = 0;
+        pc.bench_hashd_seq = 0;
+        pc.bench_iocost_seq = 0;
+        pc.profile = None;
+        pc.profile_seq = 0;
+
+        let ds = &mut prof.slices.disable_seqs;
+        ds.cpu = Self::norm_disable_seq(ds.cpu, instance_seq);
+        ds.mem = Self::norm_disable_seq(ds.mem, instance_seq);
+        ds.io = Self::norm_disable_seq(ds.io, instance_seq);
+
+        let po = &mut prof.oomd;
+        po.disable_seq = Self::norm_disable_seq(po.disable_seq, instance_seq);
+        for sk in [&mut po.workload, &mut po.system].iter_mut() {
+            sk.mem_pressure.disable_seq =
+                Self::norm_disable_seq(sk.mem_pressure.disable_seq, instance_seq);
+        }
+
+        prof
+    }
+
+    /// Overwrite the configurations with the profile. The sequence fields
+    /// of `cmd` are preserved.
+    pub fn apply_to(&self, cmd: &mut Cmd, slices: &mut SliceKnobs, oomd: &mut OomdKnobs) {
+        let mut new_cmd = self.cmd.clone();
+        new_cmd.cmd_seq = cmd.cmd_seq;
+        new_cmd.bench_hashd_seq = cmd.bench_hashd_seq;
+        new_cmd.bench_iocost_seq = cmd.bench_iocost_seq;
+        new_cmd.profile = cmd.profile.take();
+        new_cmd.profile_seq = cmd.profile_seq;
+
+        *cmd = new_cmd;
+        *slices = self.slices.clone();
+        *oomd = self.oomd.clone();
+    }
+}
+
+impl JsonLoad for Profile {}
+
+impl JsonSave for Profile {
+    fn preamble() -> Option<String> {
+        Some(PROFILE_DOC.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profile;
+    use crate::{Cmd, OomdKnobs, SliceKnobs};
+
+    #[test]
+    fn test_profile_path() {
+        assert_eq!(Profile::path("/p", "fast").unwrap(), "/p/fast.json");
+        assert!(Profile::path("/p", "").is_err());
+        assert!(Profile::path("/p", "a/b").is_err());
+        assert!(Profile::path("/p", "..").is_err());
+        assert!(Profile::path("/p", "..fast").is_err());
+    }
+
+    #[test]
+    fn test_snapshot_and_apply() {
+        let mut cmd = Cmd::default();
+        cmd.cmd_seq = 5;
+        cmd.bench_hashd_seq = 3;
+        cmd.bench_iocost_seq = 2;
+        cmd.profile = Some("old".into());
+        cmd.profile_seq = 7;
+        cmd.swappiness = Some(10);
+
+        let mut slices = SliceKnobs::default();
+        slices.disable_seqs.cpu = 42;
+        slices.disable_seqs.mem = 3;
+        let mut oomd = OomdKnobs::default();
+        oomd.disable_seq = 50;
+        oomd.workload.mem_pressure.disable_seq = 1;
+
+        // Sequences are dropped and disable seqs are normalized against
+        // the instance seq.
+        let prof = Profile::snapshot(&cmd, &slices, &oomd, 42);
+        assert_eq!(prof.cmd.cmd_seq, 0);
+        assert_eq!(prof.cmd.bench_hashd_seq, 0);
+        assert_eq!(prof.cmd.bench_iocost_seq, 0);
+        assert!(prof.cmd.profile.is_none());
+        assert_eq!(prof.cmd.profile_seq, 0);
+        assert_eq!(prof.cmd.swappiness, Some(10));
+        assert_eq!(prof.slices.disable_seqs.cpu, std::u64::MAX);
+        assert_eq!(prof.slices.disable_seqs.mem, 0);
+        assert_eq!(prof.oomd.disable_seq, std::u64::MAX);
+        assert_eq!(prof.oomd.workload.mem_pressure.disable_seq, 0);
+
+        // Applying replaces the configs but keeps cmd's sequence fields.
+        let mut cur_cmd = Cmd::default();
+        cur_cmd.cmd_seq = 9;
+        cur_cmd.bench_hashd_seq = 4;
+        cur_cmd.bench_iocost_seq = 6;
+        cur_cmd.profile = Some("new".into());
+        cur_cmd.profile_seq = 8;
+        let mut cur_slices = SliceKnobs::default();
+        let mut cur_oomd = OomdKnobs::default();
+        prof.apply_to(&mut cur_cmd, &mut cur_slices, &mut cur_oomd);
+
+        assert_eq!(cur_cmd.cmd_seq, 9);
+        assert_eq!(cur_cmd.bench_hashd_seq, 4);
+        assert_eq!(cur_cmd.bench_iocost_seq, 6);
+        assert_eq!(cur_cmd.profile.as_deref(), Some("new"));
+        assert_eq!(cur_cmd.profile_seq, 8);
+        assert_eq!(cur_cmd.swappiness, Some(10));
+        assert_eq!(cur_slices.disable_seqs.cpu, std::u64::MAX);
+        assert_eq!(cur_oomd.disable_seq, std::u64::MAX);
+    }
+}
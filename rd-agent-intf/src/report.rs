@@ -5,9 +5,10 @@ use log::trace;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::ops;
+use std::path::Path;
 use std::time::UNIX_EPOCH;
 
-use super::RunnerState;
+use super::{RunnerState, SenpaiMode};
 use rd_util::*;
 
 const REPORT_DOC: &str = "\
@@ -20,12 +21,19 @@ const REPORT_DOC: &str = "\
 //  timestamp: When this report was generated
 //  seq: Incremented on each execution, used for temporary settings
 //  state: Idle, Running, BenchHashd or BenchIoCost
+//  profile: The last activated configuration profile, null if none
+//  profile_seq: cmd::profile_seq of the last profile activation
 //  oomd.svc.name: OOMD systemd service name
 //  oomd.svc.state: OOMD systemd service state
 //  oomd.work_mem_pressure: Memory pressure based kill enabled in workload.slice
 //  oomd.work_senpai: Senpai enabled on workload.slice
 //  oomd.sys_mem_pressure: Memory pressure based kill enabled in system.slice
 //  oomd.sys_senpai: Senpai enabled on system.slice
+//  oomd.nr_kills: Number of oomd kills since rd-agent started
+//  oomd.kills[]: Recent oomd kills - timestamp, daemon, target cgroup and reason
+//  oomd.senpai{}: Native senpai per slice - target, current, pressure (stall
+//                 ratio) over the last interval, last adjustment and the
+//                 numbers of probes and backoffs
 //  sideloader.svc.name: sideloader systemd service name
 //  sideloader.svc.state: sideloader systemd service state
 //  sideloader.sysconf_warnings: sideloader system configuration warnings
@@ -46,16 +54,33 @@ const REPORT_DOC: &str = "\
 //  hashd[].rps: Current rps
 //  hashd[].lat_pct: Current control percentile
 //  hashd[].lat: Current control percentile latency
+//  hashd[].classes{}: Per request class rps, nr_done, lat targets, latencies and histogram
+//  hashd[].lat_hist: Mergeable latency histogram of the reporting period
+//  hashd[].lat_breakdown: Mean per-request time spent in each phase
+//  hashd[].log_write_lat: Mergeable hash log write latency histogram
+//  hashd[].anon_huge_size: Bytes of the anon area backed by huge pages
+//  hashd[].anon_node_size{}: Bytes of the anon area on each NUMA node
+//  hashd[].seed: Random seed in use, null if random
 //  sysloads{}.svc.name: Sysload systemd service name
 //  sysloads{}.svc.state: Sysload systemd service state
 //  sideloads{}.svc.name: Sideload systemd service name
 //  sideloads{}.svc.state: Sideload systemd service state
 //  iocost.model: iocost model parameters currently in effect
 //  iocost.qos: iocost QoS parameters currently in effect
+//  usages{}: Keyed by slice, service or drill-down cgroup path (--drilldown)
+//  usages{}.cpu_throttled: Fraction of cpu.max periods which got throttled
+//  usages{}.cpu_runq_lat: Average run-queue wait per timeslice (root only)
+//  usages{}.mem_events: memory.events counters (root uses /proc/vmstat oom_kill)
 //  iolat.{read|write|discard|flush}.p*: IO latency distributions
 //  iolat_cum.{read|write|discard|flush}.p*: Cumulative IO latency distributions
+//  iocost_devs{}: iocost reports of the additional devices from --iocost-devs
+//  iolat_devs{}: IO latency distributions of the additional devices
 //  swappiness: vm.swappiness
 //  zswap_enabled: zswap enabled
+//  swap_devs[]: Swap devices in priority order - path, kind (Partition, File
+//               or Zram), backing device, priority, size and used bytes
+//  swap_devs[].zram: zram compression algorithm, disksize, mem_limit and
+//                    original, compressed and total memory used bytes
 //
 //
 ";
@@ -87,6 +112,61 @@ pub struct ResCtlReport {
     pub io: bool,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OomdKillReport {
+    pub at: DateTime<Local>,
+    pub daemon: String,
+    pub cgroup: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct SenpaiReport {
+    pub mode: SenpaiMode,
+    pub target: u64,
+    pub current: u64,
+    pub pressure: f64,
+    pub adj: f64,
+    pub nr_probes: u64,
+    pub nr_backoffs: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SwapKind {
+    Partition,
+    File,
+    Zram,
+}
+
+impl Default for SwapKind {
+    fn default() -> Self {
+        Self::Partition
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ZramReport {
+    pub comp_algorithm: String,
+    pub disksize: u64,
+    pub mem_limit: u64,
+    pub orig_data_size: u64,
+    pub compr_data_size: u64,
+    pub mem_used_total: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwapDevReport {
+    pub path: String,
+    pub kind: SwapKind,
+    pub dev: String,
+    pub priority: i64,
+    pub size: u64,
+    pub used: u64,
+    pub zram: Option<ZramReport>,
+}
+
 #[derive(Clone, Serialize, Deserialize, Default)]
 pub struct OomdReport {
     pub svc: SvcReport,
@@ -94,6 +174,12 @@ pub struct OomdReport {
     pub work_senpai: bool,
     pub sys_mem_pressure: bool,
     pub sys_senpai: bool,
+    #[serde(default)]
+    pub nr_kills: u64,
+    #[serde(default)]
+    pub kills: Vec<OomdKillReport>,
+    #[serde(default)]
+    pub senpai: BTreeMap<String, SenpaiReport>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -144,6 +230,20 @@ pub struct HashdReport {
     pub nr_idle_workers: usize,
     pub mem_probe_size: usize,
     pub mem_probe_at: DateTime<Local>,
+    #[serde(default)]
+    pub classes: BTreeMap<String, rd_hashd_intf::ClassStat>,
+    #[serde(default)]
+    pub lat_hist: rd_hashd_intf::LatHist,
+    #[serde(default)]
+    pub lat_breakdown: rd_hashd_intf::LatBreakdown,
+    #[serde(default)]
+    pub log_write_lat: rd_hashd_intf::LatHist,
+    #[serde(default)]
+    pub anon_huge_size: usize,
+    #[serde(default)]
+    pub anon_node_size: BTreeMap<u32, usize>,
+    #[serde(default)]
+    pub seed: Option<u64>,
 }
 
 impl Default for HashdReport {
@@ -161,6 +261,13 @@ impl Default for HashdReport {
             nr_idle_workers: 0,
             mem_probe_size: 0,
             mem_probe_at: DateTime::from(UNIX_EPOCH),
+            classes: Default::default(),
+            lat_hist: Default::default(),
+            lat_breakdown: Default::default(),
+            log_write_lat: Default::default(),
+            anon_huge_size: 0,
+            anon_node_size: Default::default(),
+            seed: None,
         }
     }
 }
@@ -175,6 +282,17 @@ impl ops::AddAssign<&HashdReport> for HashdReport {
         self.nr_done += rhs.nr_done;
         self.nr_workers += rhs.nr_workers;
         self.nr_idle_workers += rhs.nr_idle_workers;
+        for (name, cs) in rhs.classes.iter() {
+            *self.classes.entry(name.clone()).or_default() += cs;
+        }
+        self.lat_hist += &rhs.lat_hist;
+        self.lat_breakdown += &rhs.lat_breakdown;
+        self.log_write_lat += &rhs.log_write_lat;
+        self.anon_huge_size += rhs.anon_huge_size;
+        for (node, size) in rhs.anon_node_size.iter() {
+            *self.anon_node_size.entry(*node).or_default() += size;
+        }
+        self.seed = rhs.seed;
     }
 }
 
@@ -189,6 +307,14 @@ impl<T: Into<f64>> ops::DivAssign<T> for HashdReport {
         self.nr_done = ((self.nr_done as f64) / div).round() as u64;
         self.nr_workers = ((self.nr_workers as f64) / div).round() as usize;
         self.nr_idle_workers = ((self.nr_idle_workers as f64) / div).round() as usize;
+        for cs in self.classes.values_mut() {
+            *cs /= div;
+        }
+        self.lat_breakdown /= div;
+        self.anon_huge_size = ((self.anon_huge_size as f64) / div).round() as usize;
+        for size in self.anon_node_size.values_mut() {
+            *size = ((*size as f64) / div).round() as usize;
+        }
     }
 }
 
@@ -204,6 +330,40 @@ pub struct SideloadReport {
     pub scr_path: String,
 }
 
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MemEventsReport {
+    pub low: u64,
+    pub high: u64,
+    pub max: u64,
+    pub oom: u64,
+    pub oom_kill: u64,
+    pub oom_group_kill: u64,
+}
+
+impl ops::AddAssign<&MemEventsReport> for MemEventsReport {
+    fn add_assign(&mut self, rhs: &MemEventsReport) {
+        self.low += rhs.low;
+        self.high += rhs.high;
+        self.max += rhs.max;
+        self.oom += rhs.oom;
+        self.oom_kill += rhs.oom_kill;
+        self.oom_group_kill += rhs.oom_group_kill;
+    }
+}
+
+impl<T: Into<f64>> ops::DivAssign<T> for MemEventsReport {
+    fn div_assign(&mut self, rhs: T) {
+        let div = rhs.into();
+        let div_u64 = |v: &mut u64| *v = (*v as f64 / div).round() as u64;
+        div_u64(&mut self.low);
+        div_u64(&mut self.high);
+        div_u64(&mut self.max);
+        div_u64(&mut self.oom);
+        div_u64(&mut self.oom_kill);
+        div_u64(&mut self.oom_group_kill);
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct UsageReport {
     pub cpu_util: f64,
@@ -226,6 +386,26 @@ pub struct UsageReport {
     pub cpu_pressures: (f64, f64),
     pub mem_pressures: (f64, f64),
     pub io_pressures: (f64, f64),
+    #[serde(default)]
+    pub cpu_nr_periods: u64,
+    #[serde(default)]
+    pub cpu_nr_throttled: u64,
+    #[serde(default)]
+    pub cpu_throttled_usage: f64,
+    #[serde(default)]
+    pub cpu_throttled: f64,
+    #[serde(default)]
+    pub cpu_nr_bursts: u64,
+    #[serde(default)]
+    pub cpu_burst_usage: f64,
+    #[serde(default)]
+    pub cpu_runq_delay: f64,
+    #[serde(default)]
+    pub cpu_nr_timeslices: u64,
+    #[serde(default)]
+    pub cpu_runq_lat: f64,
+    #[serde(default)]
+    pub mem_events: MemEventsReport,
 }
 
 impl ops::AddAssign<&UsageReport> for UsageReport {
@@ -234,6 +414,15 @@ impl ops::AddAssign<&UsageReport> for UsageReport {
         self.cpu_sys += rhs.cpu_sys;
         self.cpu_usage += rhs.cpu_usage;
         self.cpu_usage_sys += rhs.cpu_usage_sys;
+        self.cpu_nr_periods += rhs.cpu_nr_periods;
+        self.cpu_nr_throttled += rhs.cpu_nr_throttled;
+        self.cpu_throttled_usage += rhs.cpu_throttled_usage;
+        self.cpu_throttled += rhs.cpu_throttled;
+        self.cpu_nr_bursts += rhs.cpu_nr_bursts;
+        self.cpu_burst_usage += rhs.cpu_burst_usage;
+        self.cpu_runq_delay += rhs.cpu_runq_delay;
+        self.cpu_nr_timeslices += rhs.cpu_nr_timeslices;
+        self.cpu_runq_lat += rhs.cpu_runq_lat;
         self.mem_bytes += rhs.mem_bytes;
         self.swap_bytes += rhs.swap_bytes;
         self.swap_free += rhs.swap_free;
@@ -255,6 +444,7 @@ impl ops::AddAssign<&UsageReport> for UsageReport {
         self.mem_pressures.1 += rhs.mem_pressures.1;
         self.io_pressures.0 += rhs.io_pressures.0;
         self.io_pressures.1 += rhs.io_pressures.1;
+        self.mem_events += &rhs.mem_events;
     }
 }
 
@@ -266,6 +456,15 @@ impl<T: Into<f64>> ops::DivAssign<T> for UsageReport {
         self.cpu_sys /= div;
         self.cpu_usage /= div;
         self.cpu_usage_sys /= div;
+        div_u64(&mut self.cpu_nr_periods);
+        div_u64(&mut self.cpu_nr_throttled);
+        self.cpu_throttled_usage /= div;
+        self.cpu_throttled /= div;
+        div_u64(&mut self.cpu_nr_bursts);
+        self.cpu_burst_usage /= div;
+        self.cpu_runq_delay /= div;
+        div_u64(&mut self.cpu_nr_timeslices);
+        self.cpu_runq_lat /= div;
         div_u64(&mut self.mem_bytes);
         div_u64(&mut self.swap_bytes);
         div_u64(&mut self.swap_free);
@@ -287,6 +486,7 @@ impl<T: Into<f64>> ops::DivAssign<T> for UsageReport {
         self.mem_pressures.1 /= div;
         self.io_pressures.0 /= div;
         self.io_pressures.1 /= div;
+        self.mem_events /= div;
     }
 }
 
@@ -352,6 +552,10 @@ impl Default for IoCostModelReport {
 
 impl IoCostModelReport {
     pub fn read(devnr: (u32, u32)) -> Result<Self> {
+        // Only visible in the root cgroup, not from a delegated subtree.
+        if !Path::new("/sys/fs/cgroup/io.cost.model").exists() {
+            return Ok(Default::default());
+        }
         let kf = read_cgroup_nested_keyed_file("/sys/fs/cgroup/io.cost.model")?;
         let map = match kf.get(&format!("{}:{}", devnr.0, devnr.1)) {
             Some(v) => v,
@@ -383,6 +587,10 @@ pub struct IoCostQoSReport {
 
 impl IoCostQoSReport {
     pub fn read(devnr: (u32, u32)) -> Result<Self> {
+        // Only visible in the root cgroup, not from a delegated subtree.
+        if !Path::new("/sys/fs/cgroup/io.cost.qos").exists() {
+            return Ok(Default::default());
+        }
         let kf = read_cgroup_nested_keyed_file("/sys/fs/cgroup/io.cost.qos")?;
         let map = match kf.get(&format!("{}:{}", devnr.0, devnr.1)) {
             Some(v) => v,
@@ -462,6 +670,10 @@ pub struct Report {
     pub timestamp: DateTime<Local>,
     pub seq: u64,
     pub state: RunnerState,
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub profile_seq: u64,
     pub resctl: ResCtlReport,
     pub oomd: OomdReport,
     pub sideloader: SideloaderReport,
@@ -477,8 +689,14 @@ pub struct Report {
     pub iolat: IoLatReport,
     pub iolat_cum: IoLatReport,
     pub iocost: IoCostReport,
+    #[serde(default)]
+    pub iocost_devs: BTreeMap<String, IoCostReport>,
+    #[serde(default)]
+    pub iolat_devs: BTreeMap<String, IoLatReport>,
     pub swappiness: u32,
     pub zswap_enabled: bool,
+    #[serde(default)]
+    pub swap_devs: Vec<SwapDevReport>,
 }
 
 impl Default for Report {
@@ -487,6 +705,8 @@ impl Default for Report {
             timestamp: DateTime::from(UNIX_EPOCH),
             seq: 1,
             state: RunnerState::Idle,
+            profile: None,
+            profile_seq: 0,
             resctl: Default::default(),
             oomd: Default::default(),
             sideloader: Default::default(),
@@ -502,8 +722,11 @@ impl Default for Report {
             iolat: Default::default(),
             iolat_cum: Default::default(),
             iocost: Default::default(),
+            iocost_devs: Default::default(),
+            iolat_devs: Default::default(),
             swappiness: 60,
             zswap_enabled: false,
+            swap_devs: vec![],
         }
     }
 }
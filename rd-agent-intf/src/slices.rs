@@ -4,10 +4,26 @@ use enum_iterator::IntoEnumIterator;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::ops::{Index, IndexMut};
+use std::sync::RwLock;
 
 use rd_util::*;
 
 pub const ROOT_SLICE: &'static str = "-.slice";
+pub const CGRP_ROOT: &'static str = "/sys/fs/cgroup";
+
+lazy_static::lazy_static! {
+    static ref CGRP_BASE: RwLock<String> = RwLock::new(CGRP_ROOT.into());
+}
+
+/// The cgroup directory the slices live under. [`CGRP_ROOT`] unless
+/// rd-agent is running in a delegated subtree.
+pub fn cgrp_base() -> String {
+    CGRP_BASE.read().unwrap().clone()
+}
+
+pub fn set_cgrp_base(base: &str) {
+    *CGRP_BASE.write().unwrap() = base.trim_end_matches('/').to_string();
+}
 
 const SLICE_DOC: &str = "\
 //
@@ -48,15 +64,8 @@ impl Slice {
         }
     }
 
-    pub fn cgrp(&self) -> &'static str {
-        match self {
-            Slice::Init => "/sys/fs/cgroup/init.scope",
-            Slice::Host => "/sys/fs/cgroup/hostcritical.slice",
-            Slice::User => "/sys/fs/cgroup/user.slice",
-            Slice::Sys => "/sys/fs/cgroup/system.slice",
-            Slice::Work => "/sys/fs/cgroup/workload.slice",
-            Slice::Side => "/sys/fs/cgroup/sideload.slice",
-        }
+    pub fn cgrp(&self) -> String {
+        format!("{}/{}", cgrp_base(), self.name())
     }
 }
 
@@ -16,12 +16,36 @@ const SYSREQ_DOC: &str = "\
 // scr_dev_model: Scratch storage device model string
 // scr_dev_fwrev: Scratch storage device firmware revision string
 // scr_dev_size: Scratch storage device size
+// scr_fstype: Filesystem type of the scratch directory (btrfs, ext4 or xfs)
 // swap_size: Swap size
+// swap_devs: Swap devices, see swap_devs in report.json
+// delegated: Running in a delegated cgroup subtree, host-level requirements
+//            are neither checked nor included in satisfied
+// cgrp_base: Cgroup directory the slices are under, the delegated subtree
 //
 ";
 
 lazy_static::lazy_static! {
     pub static ref ALL_SYSREQS_SET: BTreeSet<SysReq> = SysReq::into_enum_iter().collect();
+
+    // Requirements on host-wide configurations which can't be checked or
+    // changed from inside a delegated cgroup subtree.
+    pub static ref HOST_SYSREQS_SET: BTreeSet<SysReq> = [
+        SysReq::MemCgRecursiveProt,
+        SysReq::IoCost,
+        SysReq::IoCostVer,
+        SysReq::SupportedFs,
+        SysReq::FsMountOpts,
+        SysReq::NoCompositeStorage,
+        SysReq::IoSched,
+        SysReq::NoWbt,
+        SysReq::SwapOnScratch,
+        SysReq::Swap,
+        SysReq::HostCriticalServices,
+    ]
+    .iter()
+    .copied()
+    .collect();
 }
 
 #[derive(
@@ -46,8 +70,10 @@ pub enum SysReq {
     IoCostVer,
     NoOtherIoControllers,
     AnonBalance,
-    Btrfs,
-    BtrfsAsyncDiscard,
+    #[serde(alias = "Btrfs")]
+    SupportedFs, // btrfs, ext4 or xfs
+    #[serde(alias = "BtrfsAsyncDiscard")]
+    FsMountOpts, // Filesystem mount options
     NoCompositeStorage,
     IoSched,
     NoWbt,
@@ -120,6 +146,16 @@ pub struct SysReqsReport {
     pub scr_dev_fwrev: String,
     pub scr_dev_size: u64,
     pub scr_dev_iosched: String,
+    #[serde(default)]
+    pub scr_fstype: String,
+    #[serde(default)]
+    pub iocost_devs: Vec<String>,
+    #[serde(default)]
+    pub swap_devs: Vec<super::SwapDevReport>,
+    #[serde(default)]
+    pub delegated: bool,
+    #[serde(default)]
+    pub cgrp_base: String,
     pub enforce: super::EnforceConfig,
 }
 
@@ -7,7 +7,8 @@ use std::sync::{Arc, RwLock};
 use std::thread::spawn;
 use std::time::{Duration, SystemTime};
 
-use rd_util::anon_area::AnonArea;
+use rd_util::anon_area::{AnonArea, AnonMode};
+use rd_util::numa::NumaPolicy;
 use rd_util::*;
 
 const ANON_SIZE_CLICK: usize = 1 << 30;
@@ -227,8 +228,16 @@ fn reader(
 }
 
 pub fn bandit_mem_hog(args: &BanditMemHogArgs) {
+    let mut aa = AnonArea::new(
+        ANON_SIZE_CLICK,
+        args.comp,
+        AnonMode::System,
+        NumaPolicy::Default,
+    );
+    aa.set_seed(args.seed);
+
     let state = Arc::new(RwLock::new(State {
-        aa: AnonArea::new(ANON_SIZE_CLICK, args.comp),
+        aa,
         wpage_pos: AtomicUsize::new(0),
     }));
 
@@ -1,9 +1,11 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use chrono::prelude::*;
 use log::{debug, info, warn};
+use proc_mounts::MountList;
 use scan_fmt::scan_fmt;
 use std::fs;
+use std::path::Path;
 use std::time::SystemTime;
 
 use rd_agent_intf::{BenchKnobs, IoCostKnobs};
@@ -38,14 +40,50 @@ pub fn start_hashd_bench(
     Ok(svc)
 }
 
-pub fn start_iocost_bench(cfg: &Config) -> Result<TransientService> {
-    let paths = &cfg.iocost_paths;
+/// Returns the (working dir, result path) pair for benchmarking the
+/// `idx`'th entry of `cfg.iocost_devs`. The scratch device uses the scratch
+/// dir. The other devices need a mounted filesystem to hold the testfile.
+fn iocost_bench_paths(cfg: &Config, idx: usize) -> Result<(String, String)> {
+    let (dev, _) = &cfg.iocost_devs[idx];
+    if idx == 0 {
+        return Ok((
+            cfg.iocost_paths.working.clone(),
+            cfg.iocost_paths.result.clone(),
+        ));
+    }
+
+    for mi in MountList::new()?.0.iter() {
+        match mi.fstype.as_str() {
+            "btrfs" | "ext4" | "xfs" => {}
+            _ => continue,
+        }
+        match path_to_devname(&mi.dest) {
+            Ok(name) if name.to_str() == Some(dev.as_str()) => {}
+            _ => continue,
+        }
+        let working = format!("{}/.rd-agent-iocost-coef", mi.dest.display());
+        let result = working.clone() + "/iocost-coef.json";
+        return Ok((working, result));
+    }
+    bail!("no btrfs, ext4 or xfs filesystem mounted on {:?}", dev)
+}
+
+fn start_iocost_bench(cfg: &Config, idx: usize) -> Result<TransientService> {
+    let dev = &cfg.iocost_devs[idx].0;
+    let (working, result) = iocost_bench_paths(cfg, idx)?;
+    if !Path::new(&working).exists() {
+        super::plan::act(&format!("Create {:?}", &working), || {
+            Ok(fs::create_dir_all(&working)?)
+        })?;
+    }
+    let _ = fs::remove_file(&result);
+
     let args: Vec<String> = vec![
-        paths.bin.clone(),
+        cfg.iocost_paths.bin.clone(),
         "--json".into(),
-        paths.result.clone(),
+        result,
         "--testfile-dev".into(),
-        cfg.scr_dev.clone(),
+        dev.clone(),
         "--duration".into(),
         "60".into(),
     ];
@@ -54,14 +92,13 @@ pub fn start_iocost_bench(cfg: &Config) -> Result<TransientService> {
     if let Err(e) = iocost_on_off(false, cfg) {
         warn!(
             "bench: Failed to turn off iocost for benchmark on {:?} ({:?})",
-            &cfg.scr_dev, &e
+            dev, &e
         );
     }
 
     let mut svc =
         TransientService::new_sys(IOCOST_BENCH_SVC_NAME.into(), args, Vec::new(), Some(0o002))?;
-    svc.set_slice(Slice::Work.name())
-        .set_working_dir(&paths.working);
+    svc.set_slice(Slice::Work.name()).set_working_dir(&working);
 
     match svc.start() {
         Ok(()) => Ok(svc),
@@ -72,6 +109,26 @@ pub fn start_iocost_bench(cfg: &Config) -> Result<TransientService> {
     }
 }
 
+/// Start the iocost benchmark on the first device in `cfg.iocost_devs`
+/// starting from `from`. Devices which can't be benchmarked are skipped
+/// with a warning and keep whatever parameters bench.json already has.
+pub fn start_next_iocost_bench(
+    cfg: &Config,
+    from: usize,
+) -> Result<Option<(usize, TransientService)>> {
+    for idx in from..cfg.iocost_devs.len() {
+        match start_iocost_bench(cfg, idx) {
+            Ok(svc) => return Ok(Some((idx, svc))),
+            Err(e) if idx == 0 => return Err(e),
+            Err(e) => warn!(
+                "bench: Skipping iocost benchmark on {:?} ({:#})",
+                &cfg.iocost_devs[idx].0, &e
+            ),
+        }
+    }
+    Ok(None)
+}
+
 pub fn update_hashd(knobs: &mut BenchKnobs, cfg: &Config, hashd_seq: u64) -> Result<()> {
     let args = rd_hashd_intf::Args::load(&cfg.hashd_paths(HashdSel::A).args)?;
     let params = rd_hashd_intf::Params::load(&cfg.hashd_paths(HashdSel::A).params)?;
@@ -82,6 +139,11 @@ pub fn update_hashd(knobs: &mut BenchKnobs, cfg: &Config, hashd_seq: u64) -> Res
     knobs.hashd.mem_frac = params.mem_frac;
     knobs.hashd.chunk_pages = params.chunk_pages;
     knobs.hashd.fake_cpu_load = params.fake_cpu_load;
+    // rd-hashd bench records the mode its AnonArea ended up using, which
+    // may differ from the requested one after hugetlb -> THP fallback.
+    knobs.hashd.anon_mode = args.anon_mode;
+    knobs.hashd.numa = args.numa;
+    knobs.hashd.seed = params.seed;
 
     if hashd_seq == std::u64::MAX {
         knobs.hashd_seq += 1;
@@ -101,10 +163,10 @@ pub fn update_hashd(knobs: &mut BenchKnobs, cfg: &Config, hashd_seq: u64) -> Res
     Ok(())
 }
 
-pub fn update_iocost(knobs: &mut BenchKnobs, cfg: &Config, iocost_seq: u64) -> Result<()> {
-    let f = fs::OpenOptions::new()
-        .read(true)
-        .open(&cfg.iocost_paths.result)?;
+pub fn update_iocost(knobs: &mut BenchKnobs, cfg: &Config, idx: usize) -> Result<()> {
+    let (dev, expected) = &cfg.iocost_devs[idx];
+    let (_, result) = iocost_bench_paths(cfg, idx)?;
+    let f = fs::OpenOptions::new().read(true).open(&result)?;
 
     let mut iocost: IoCostKnobs = serde_json::from_reader(f)?;
     iocost.qos.sanitize();
@@ -113,56 +175,71 @@ pub fn update_iocost(knobs: &mut BenchKnobs, cfg: &Config, iocost_seq: u64) -> R
         Ok(v) => v,
         Err(_) => bail!("iocost bench reported invalid devnr {:?}", &iocost.devnr),
     };
-    if devnr != cfg.scr_devnr {
+    if devnr != *expected {
         bail!(
             "iocost bench result is on the wrong device {}:{}, expected {}:{}",
             devnr.0,
             devnr.1,
-            cfg.scr_devnr.0,
-            cfg.scr_devnr.1
+            expected.0,
+            expected.1
         );
     }
 
-    let (dev_model, dev_fwrev, dev_size) = devname_to_model_fwrev_size(&cfg.scr_dev)?;
+    if idx > 0 {
+        match knobs
+            .iocost_devs
+            .iter_mut()
+            .find(|x| x.devnr == iocost.devnr)
+        {
+            Some(slot) => *slot = iocost,
+            None => knobs.iocost_devs.push(iocost),
+        }
+        return Ok(());
+    }
+
+    let (dev_model, dev_fwrev, dev_size) = devname_to_model_fwrev_size(dev)?;
 
     knobs.iocost = iocost;
     knobs.iocost_dev_model = dev_model;
     knobs.iocost_dev_fwrev = dev_fwrev;
     knobs.iocost_dev_size = dev_size;
+    Ok(())
+}
+
+pub fn finish_iocost(knobs: &mut BenchKnobs, iocost_seq: u64) {
     knobs.iocost_seq = iocost_seq;
     knobs.timestamp = DateTime::from(SystemTime::now());
-    Ok(())
 }
 
 pub fn iocost_on_off(enable: bool, cfg: &Config) -> Result<()> {
-    if !cfg.enforce.io {
+    if !cfg.enforce.io || cfg.delegated {
         return Ok(());
     }
-    write_one_line(
-        IOCOST_QOS_PATH,
-        &format!(
-            "{}:{} enable={}",
-            cfg.scr_devnr.0,
-            cfg.scr_devnr.1,
-            if enable { 1 } else { 0 },
-        ),
-    )
+    for (_, devnr) in cfg.iocost_devs.iter() {
+        super::plan::write_knob(
+            IOCOST_QOS_PATH,
+            &format!(
+                "{}:{} enable={}",
+                devnr.0,
+                devnr.1,
+                if enable { 1 } else { 0 },
+            ),
+        )?;
+    }
+    Ok(())
 }
 
-pub fn apply_iocost(knobs: &BenchKnobs, cfg: &Config) -> Result<()> {
-    if !cfg.enforce.io {
-        return Ok(());
-    }
-    if knobs.iocost_seq == 0 {
-        info!(
-            "iocost: Enabling on {:?} with default parameters",
-            &cfg.scr_dev
-        );
-        return iocost_on_off(true, cfg);
-    }
+fn apply_iocost_dev(dev: &str, devnr: (u32, u32), iocost: Option<&IoCostKnobs>) -> Result<()> {
+    let (maj, min) = devnr;
+    let iocost = match iocost {
+        Some(v) => v,
+        None => {
+            info!("iocost: Enabling on {:?} with default parameters", dev);
+            return super::plan::write_knob(IOCOST_QOS_PATH, &format!("{}:{} enable=1", maj, min));
+        }
+    };
 
-    let (maj, min) = cfg.scr_devnr;
-    let model = &knobs.iocost.model;
+    let model = &iocost.model;
     let model_line = format!(
         "{}:{} model=linear rbps={} rseqiops={} rrandiops={} wbps={} wseqiops={} wrandiops={}",
         maj,
@@ -174,18 +251,39 @@ pub fn apply_iocost(knobs: &BenchKnobs, cfg: &Config) -> Result<()> {
         model.wseqiops,
         model.wrandiops
     );
-    info!(
-        "iocost: Enabling on {:?} with benchmarked parameters",
-        &cfg.scr_dev
-    );
+    info!("iocost: Enabling on {:?} with benchmarked parameters", dev);
     debug!("iocost.model: {}", &model_line);
-    write_one_line(IOCOST_MODEL_PATH, &model_line)?;
+    super::plan::write_knob(IOCOST_MODEL_PATH, &model_line)?;
 
-    let qos = &knobs.iocost.qos;
+    let qos = &iocost.qos;
     let qos_line = format!(
         "{}:{} rpct={:.2} rlat={} wpct={:.2} wlat={} min={:.2} max={:.2}",
         maj, min, qos.rpct, qos.rlat, qos.wpct, qos.wlat, qos.min, qos.max
     );
     debug!("iocost.qos: {}", &qos_line);
-    write_one_line(IOCOST_QOS_PATH, &qos_line)
+    super::plan::write_knob(IOCOST_QOS_PATH, &qos_line)
+}
+
+pub fn apply_iocost(knobs: &BenchKnobs, cfg: &Config) -> Result<()> {
+    if !cfg.enforce.io || cfg.delegated {
+        return Ok(());
+    }
+
+    for (dev, devnr) in cfg.iocost_devs.iter() {
+        // The scratch device always comes first and is tracked by
+        // knobs.iocost. The rest are benchmarked after it, or filled in
+        // bench.json by the user or resctl-bench.
+        let iocost = if *devnr == cfg.scr_devnr {
+            match knobs.iocost_seq {
+                0 => None,
+                _ => Some(&knobs.iocost),
+            }
+        } else {
+            let devnr_str = format!("{}:{}", devnr.0, devnr.1);
+            knobs.iocost_devs.iter().find(|x| x.devnr == devnr_str)
+        };
+        apply_iocost_dev(dev, *devnr, iocost)
+            .with_context(|| format!("Failed to configure iocost on {:?}", dev))?;
+    }
+    Ok(())
 }
@@ -2,12 +2,14 @@
 use anyhow::{Context, Result};
 use log::{debug, error, info, warn};
 use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 use systemd::UnitState as US;
 
-use rd_agent_intf::{RunnerState, Slice, HASHD_BENCH_SVC_NAME, IOCOST_BENCH_SVC_NAME};
+use rd_agent_intf::{Profile, RunnerState, Slice, HASHD_BENCH_SVC_NAME, IOCOST_BENCH_SVC_NAME};
 use rd_util::*;
 
 use super::hashd::HashdSet;
@@ -29,6 +31,7 @@ pub struct RunnerData {
 
     pub bench_hashd: Option<TransientService>,
     pub bench_iocost: Option<TransientService>,
+    bench_iocost_idx: usize,
 
     pub hashd_set: HashdSet,
     pub side_runner: SideRunner,
@@ -46,6 +49,7 @@ impl RunnerData {
             force_apply: false,
             bench_hashd: None,
             bench_iocost: None,
+            bench_iocost_idx: 0,
             hashd_set: HashdSet::new(&cfg),
             side_runner: SideRunner::new(cfg.clone()),
             balloon: Balloon::new(cfg.clone()),
@@ -101,7 +105,80 @@ impl RunnerData {
         }
     }
 
+    fn activate_profile(&mut self, name: &str) -> Result<()> {
+        let path = Profile::path(&self.cfg.profiles_path, name)?;
+        let prof = Profile::load(&path).with_context(|| format!("Loading {:?}", &path))?;
+
+        let sobjs = &mut self.sobjs;
+        let mut cmd = sobjs.cmd_file.data.clone();
+        let mut slices = sobjs.slice_file.data.clone();
+        let mut oomd = sobjs.oomd.file.data.clone();
+        prof.apply_to(&mut cmd, &mut slices, &mut oomd);
+        slices.loaded(None)?;
+
+        // Stage all three files before moving any into place and swap the
+        // in-memory state last so that a failure can't leave the profile
+        // partially applied.
+        let mut staged: Vec<(PathBuf, PathBuf)> = vec![];
+        for (path, json) in [
+            (&sobjs.cmd_file.path, cmd.as_json()?),
+            (&sobjs.slice_file.path, slices.as_json()?),
+            (&sobjs.oomd.file.path, oomd.as_json()?),
+        ] {
+            let path = match path {
+                Some(v) => v.clone(),
+                None => continue,
+            };
+            let staging = PathBuf::from(format!("{}.profile-staging", path.display()));
+            if let Err(e) = fs::write(&staging, json) {
+                for (staging, _) in staged.iter() {
+                    let _ = fs::remove_file(staging);
+                }
+                return Err(e).with_context(|| format!("Writing {:?}", &staging));
+            }
+            staged.push((staging, path));
+        }
+        for (staging, path) in staged.iter() {
+            fs::rename(staging, path)
+                .with_context(|| format!("Moving {:?} to {:?}", staging, path))?;
+        }
+
+        sobjs.cmd_file.data = cmd;
+        sobjs.slice_file.data = slices;
+        sobjs.oomd.file.data = oomd;
+        Ok(())
+    }
+
+    fn maybe_activate_profile(&mut self) {
+        let cmd = &self.sobjs.cmd_file.data;
+        if cmd.profile_seq <= self.sobjs.profile_seq {
+            return;
+        }
+        let (profile, profile_seq) = (cmd.profile.clone(), cmd.profile_seq);
+
+        if let Some(name) = profile.as_ref() {
+            match self.activate_profile(name) {
+                Ok(()) => {
+                    info!("cmd: Activated profile {:?}", name);
+                    self.force_apply = true;
+                }
+                Err(e) => {
+                    warn!("cmd: Failed to activate profile {:?} ({:#})", name, &e);
+                    return;
+                }
+            }
+        }
+        self.sobjs.profile = profile;
+        self.sobjs.profile_seq = profile_seq;
+    }
+
     fn maybe_reload(&mut self) -> bool {
+        // Profiles replace configs which benchmarks control while running.
+        match self.state {
+            BenchIoCost | BenchHashd => {}
+            _ => self.maybe_activate_profile(),
+        }
+
         let sobjs = &mut self.sobjs;
         let last_cpu_headroom = sobjs.cmd_file.data.sideloader.cpu_headroom;
 
@@ -127,8 +204,8 @@ impl RunnerData {
         if re_bench {
             if let Err(e) = bench::apply_iocost(&mut sobjs.bench_file.data, &self.cfg) {
                 warn!(
-                    "cmd: Failed to apply changed iocost configuration on {:?} ({:?})",
-                    self.cfg.scr_dev, &e
+                    "cmd: Failed to apply changed iocost configuration ({:?})",
+                    &e
                 );
             }
         }
@@ -187,12 +264,12 @@ impl RunnerData {
     }
 
     fn apply_swappiness(&self, swappiness: Option<u32>) -> Result<()> {
-        if !self.cfg.enforce.mem {
+        if !self.cfg.enforce.mem || self.cfg.delegated {
             return Ok(());
         }
         let cur = read_swappiness()?;
         let target = swappiness
-            .unwrap_or(self.cfg.sr_swappiness.unwrap())
+            .unwrap_or(self.cfg.sr_journal.swappiness.unwrap())
             .min(200);
         if cur != target {
             if target >= 60 {
@@ -207,11 +284,11 @@ impl RunnerData {
     }
 
     fn apply_zswap_enabled(&self, enabled: Option<bool>) -> Result<()> {
-        if !self.cfg.enforce.mem {
+        if !self.cfg.enforce.mem || self.cfg.delegated {
             return Ok(());
         }
         let cur = read_zswap_enabled()?;
-        let target = enabled.unwrap_or(self.cfg.sr_zswap_enabled.unwrap());
+        let target = enabled.unwrap_or(self.cfg.sr_journal.zswap_enabled.unwrap());
         if cur != target {
             write_one_line(ZSWAP_ENABLED_PATH, if target { "Y" } else { "N" })
                 .context("Updating zswap enable")?;
@@ -253,7 +330,9 @@ impl RunnerData {
         match self.state {
             Idle => {
                 if cmd.bench_iocost_seq > bench.iocost_seq {
-                    self.bench_iocost = Some(bench::start_iocost_bench(&*self.cfg)?);
+                    let (idx, svc) = bench::start_next_iocost_bench(&*self.cfg, 0)?.unwrap();
+                    self.bench_iocost = Some(svc);
+                    self.bench_iocost_idx = idx;
                     self.state = BenchIoCost;
                     self.force_apply = true;
                 } else if cmd.bench_hashd_seq > bench.hashd_seq {
@@ -374,7 +453,17 @@ impl RunnerData {
                             bench::update_hashd(&mut bf.data, &self.cfg, cmd.bench_hashd_seq)?;
                             bf.save()?;
                         } else {
-                            bench::update_iocost(&mut bf.data, &self.cfg, cmd.bench_iocost_seq)?;
+                            let idx = self.bench_iocost_idx;
+                            bench::update_iocost(&mut bf.data, &self.cfg, idx)?;
+                            if let Some((idx, svc)) =
+                                bench::start_next_iocost_bench(&self.cfg, idx + 1)?
+                            {
+                                bf.save()?;
+                                self.bench_iocost = Some(svc);
+                                self.bench_iocost_idx = idx;
+                                return Ok(());
+                            }
+                            bench::finish_iocost(&mut bf.data, cmd.bench_iocost_seq);
                             bf.save()?;
                             bench::apply_iocost(&bf.data, &self.cfg)?;
                         }
@@ -471,12 +560,11 @@ impl Runner {
                     warn!("cmd: Health check failed ({:?})", &e);
                 }
 
-                if data.cfg.enforce.io {
-                    if let Err(e) = super::set_iosched(&data.cfg.scr_dev, "none") {
-                        error!(
-                            "cfg: Failed to set none iosched on {:?} ({})",
-                            &data.cfg.scr_dev, &e
-                        );
+                if data.cfg.enforce.io && !data.cfg.delegated {
+                    for (dev, _) in data.cfg.iocost_devs.iter() {
+                        if let Err(e) = super::set_iosched(dev, "none") {
+                            error!("cfg: Failed to set none iosched on {:?} ({})", dev, &e);
+                        }
                     }
                 }
 
@@ -1,7 +1,7 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 use anyhow::{bail, Result};
 use log::{debug, info, warn};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::io;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -46,6 +46,9 @@ pub struct Hashd {
     file_max_ratio: f64,
     svc: Option<TransientService>,
     started_at: Option<SystemTime>,
+    lat_hist_cum: rd_hashd_intf::LatHist,
+    log_write_lat_cum: rd_hashd_intf::LatHist,
+    class_lat_hist_cum: BTreeMap<String, rd_hashd_intf::LatHist>,
 }
 
 impl Hashd {
@@ -139,10 +142,42 @@ impl Hashd {
             params.log_bps = cmd.log_bps;
             changed = true;
         }
+        if params.log_mode != cmd.log_mode {
+            params.log_mode = cmd.log_mode;
+            changed = true;
+        }
+        if params.seed != cmd.seed {
+            params.seed = cmd.seed;
+            changed = true;
+        }
         if params.fake_cpu_load != knobs.fake_cpu_load {
             params.fake_cpu_load = knobs.fake_cpu_load;
             changed = true;
         }
+        if params.load_pattern != cmd.load_pattern {
+            params.load_pattern = cmd.load_pattern;
+            changed = true;
+        }
+        if params.load_amplitude != cmd.load_amplitude {
+            params.load_amplitude = cmd.load_amplitude;
+            changed = true;
+        }
+        if params.load_period != cmd.load_period {
+            params.load_period = cmd.load_period;
+            changed = true;
+        }
+        if params.spike_interval != cmd.spike_interval {
+            params.spike_interval = cmd.spike_interval;
+            changed = true;
+        }
+        if params.spike_dur != cmd.spike_dur {
+            params.spike_dur = cmd.spike_dur;
+            changed = true;
+        }
+        if params.spike_ratio != cmd.spike_ratio {
+            params.spike_ratio = cmd.spike_ratio;
+            changed = true;
+        }
 
         if changed {
             info!(
@@ -210,6 +245,28 @@ impl Hashd {
             },
         };
 
+        // Diff the cumulative histograms against the last seen ones so that
+        // each completion is counted exactly once even if a report is missed
+        // or re-read. Expired reports carry empty ones and are skipped.
+        let diff_cum = |cum: rd_hashd_intf::LatHist, last: &mut rd_hashd_intf::LatHist| {
+            if cum.is_empty() {
+                return Default::default();
+            }
+            let delta = cum.since(last);
+            *last = cum;
+            delta
+        };
+        let lat_hist = diff_cum(hashd_r.hasher.lat_hist_cum, &mut self.lat_hist_cum);
+        let log_write_lat = diff_cum(
+            hashd_r.hasher.log_write_lat_cum,
+            &mut self.log_write_lat_cum,
+        );
+        let mut classes = hashd_r.hasher.classes;
+        for (name, cs) in classes.iter_mut() {
+            let last = self.class_lat_hist_cum.entry(name.clone()).or_default();
+            cs.lat_hist = diff_cum(std::mem::take(&mut cs.lat_hist_cum), last);
+        }
+
         Ok(HashdReport {
             svc: svc_r,
             phase: hashd_r.phase,
@@ -223,6 +280,13 @@ impl Hashd {
             nr_idle_workers: hashd_r.hasher.nr_idle_workers,
             mem_probe_size: hashd_r.mem_probe_size,
             mem_probe_at: hashd_r.mem_probe_at,
+            classes,
+            lat_hist,
+            lat_breakdown: hashd_r.hasher.lat_breakdown,
+            log_write_lat,
+            anon_huge_size: hashd_r.hasher.anon_huge_size,
+            anon_node_size: hashd_r.hasher.anon_node_size,
+            seed: hashd_r.seed,
         })
     }
 }
@@ -245,6 +309,9 @@ impl HashdSet {
                     file_max_ratio: rd_hashd_intf::Args::default().file_max_frac,
                     svc: None,
                     started_at: None,
+                    lat_hist_cum: Default::default(),
+                    log_write_lat_cum: Default::default(),
+                    class_lat_hist_cum: Default::default(),
                 },
                 Hashd {
                     name: HASHD_B_SVC_NAME.into(),
@@ -256,6 +323,9 @@ impl HashdSet {
                     file_max_ratio: rd_hashd_intf::Args::default().file_max_frac,
                     svc: None,
                     started_at: None,
+                    lat_hist_cum: Default::default(),
+                    log_write_lat_cum: Default::default(),
+                    class_lat_hist_cum: Default::default(),
                 },
             ],
         }
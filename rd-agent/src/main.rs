@@ -1,5 +1,5 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use log::{debug, error, info, trace, warn};
 use proc_mounts::MountInfo;
 use scan_fmt::scan_fmt;
@@ -20,17 +20,23 @@ mod cmd;
 mod hashd;
 mod misc;
 mod oomd;
+mod plan;
 mod report;
+mod restore;
+mod senpai;
 mod side;
 mod sideloader;
 mod slices;
+mod swap;
 
 use rd_agent_intf::{
-    Args, BenchKnobs, Cmd, CmdAck, EnforceConfig, MissedSysReqs, Report, SideloadDefs, SliceKnobs,
-    SvcReport, SvcStateReport, SysReq, SysReqsReport, ALL_SYSREQS_SET, OOMD_SVC_NAME,
+    cgrp_base, set_cgrp_base, Args, BenchKnobs, Cmd, CmdAck, EnforceConfig, MissedSysReqs,
+    OomdKnobs, Profile, Report, SideloadDefs, Slice, SliceKnobs, SvcReport, SvcStateReport,
+    SwapKind, SysReq, SysReqsReport, ALL_SYSREQS_SET, CGRP_ROOT, HOST_SYSREQS_SET, OOMD_SVC_NAME,
 };
 use rd_util::*;
 use report::clear_old_report_files;
+use restore::RestoreJournal;
 
 lazy_static::lazy_static! {
     pub static ref VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -52,13 +58,10 @@ fn unit_configlet_path(unit_name: &str, tag: &str) -> String {
 
 fn write_unit_configlet(unit_name: &str, tag: &str, config: &str) -> Result<()> {
     let path = unit_configlet_path(unit_name, tag);
-    fs::create_dir_all(Path::new(&path).parent().unwrap())?;
-    let mut f = fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&path)?;
-    Ok(f.write_all(config.as_ref())?)
+    if !plan::dry_run() {
+        fs::create_dir_all(Path::new(&path).parent().unwrap())?;
+    }
+    plan::write_file(&path, config)
 }
 
 fn prepare_bin_file(path: &str, body: &[u8]) -> Result<()> {
@@ -109,11 +112,42 @@ fn read_iosched(dev: &str) -> Result<String> {
 fn set_iosched(dev: &str, iosched: &str) -> Result<()> {
     if read_iosched(dev)? != iosched {
         info!("cfg: fixing iosched of {:?} to {:?}", dev, iosched);
-        write_one_line(&iosched_path(dev), iosched)?;
+        plan::write_knob(&iosched_path(dev), iosched)?;
     }
     Ok(())
 }
 
+// Per-filesystem mount option requirements for IO isolation. Btrfs needs
+// free space tree and async discard. ext4 and xfs shouldn't use online
+// discard which issues discards synchronously in bulk. ext4 data=journal
+// funnels all data writes through the journal and can't be changed on
+// remount.
+struct FsReqs {
+    fstype: &'static str,
+    // Options which should be present, the bool indicates SSD-only.
+    want: &'static [(&'static str, bool)],
+    // Options which shouldn't be present and their remount negations.
+    unwant: &'static [(&'static str, Option<&'static str>)],
+}
+
+const FS_REQS: [FsReqs; 3] = [
+    FsReqs {
+        fstype: "btrfs",
+        want: &[("space_cache=v2", false), ("discard=async", true)],
+        unwant: &[],
+    },
+    FsReqs {
+        fstype: "ext4",
+        want: &[],
+        unwant: &[("data=journal", None), ("discard", Some("nodiscard"))],
+    },
+    FsReqs {
+        fstype: "xfs",
+        want: &[],
+        unwant: &[("discard", Some("nodiscard"))],
+    },
+];
+
 #[derive(Copy, Clone, Debug)]
 pub enum HashdSel {
     A = 0,
@@ -144,6 +178,7 @@ pub struct Config {
     pub scr_dev: String,
     pub scr_devnr: (u32, u32),
     pub scr_dev_forced: bool,
+    pub iocost_devs: Vec<(String, (u32, u32))>,
     pub index_path: String,
     pub sysreqs_path: String,
     pub cmd_path: String,
@@ -154,6 +189,8 @@ pub struct Config {
     pub report_1min_d_path: String,
     pub bench_path: String,
     pub slices_path: String,
+    pub profiles_path: String,
+    pub restore_path: String,
     pub agent_bin: String,
     pub hashd_paths: [HashdPaths; 2],
     pub misc_bin_path: String,
@@ -176,33 +213,50 @@ pub struct Config {
 
     pub rep_retention: Option<u64>,
     pub rep_1min_retention: Option<u64>,
+    pub drilldown: Vec<String>,
+    pub drilldown_max: usize,
     pub force_running: bool,
     pub bypass: bool,
+    pub delegated: bool,
     pub verbosity: u32,
     pub enforce: EnforceConfig,
 
     pub sr_failed: MissedSysReqs,
-    sr_iosched: Option<String>,
-    sr_wbt: Option<u64>,
-    sr_wbt_path: Option<String>,
-    sr_swappiness: Option<u32>,
-    sr_zswap_enabled: Option<bool>,
-    sr_oomd_sys_svc: Option<systemd::Unit>,
+    pub sr_skipped: MissedSysReqs,
+    pub sr_journal: RestoreJournal,
 }
 
 impl Config {
     fn prep_dir(path: &str) -> String {
-        debug!("creating dir {:?}", &path);
+        if !Path::new(path).is_dir() {
+            debug!("creating dir {:?}", &path);
+            let res = plan::act(&format!("Create directory {:?}", path), || {
+                Ok(fs::create_dir_all(&path)?)
+            });
+            if let Err(e) = res {
+                error!("cfg: Failed to create directory {:?} ({:#})", &path, &e);
+                panic!();
+            }
+        }
 
-        if let Err(e) = fs::create_dir_all(&path) {
-            error!("cfg: Failed to create directory {:?} ({:#})", &path, &e);
-            panic!();
+        // With --dry-run, the directory may not exist.
+        match fs::canonicalize(path) {
+            Ok(v) => v.to_str().unwrap().to_string(),
+            Err(_) if plan::dry_run() => path.to_string(),
+            Err(e) => {
+                error!("cfg: Failed to canonicalize {:?} ({:#})", &path, &e);
+                panic!();
+            }
         }
-        fs::canonicalize(path)
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
+    }
+
+    // Directories aren't created with --dry-run. Use the closest existing
+    // ancestor for device lookups.
+    fn existing_ancestor(path: &str) -> &Path {
+        Path::new(path)
+            .ancestors()
+            .find(|p| p.exists())
+            .unwrap_or(Path::new("."))
     }
 
     fn sgid_top<P: AsRef<Path>>(top_path: &str, args_path: Option<&P>) -> Result<()> {
@@ -276,10 +330,97 @@ impl Config {
         Ok((bin, "oomd.service".to_string()))
     }
 
+    fn resolve_iocost_devs(
+        scr_dev: &str,
+        scr_devnr: (u32, u32),
+        devs: &[String],
+    ) -> Result<Vec<(String, (u32, u32))>> {
+        let mut iocost_devs = vec![(scr_dev.to_string(), scr_devnr)];
+        for dev in devs.iter() {
+            let devnr = storage_info::devname_to_devnr(dev)
+                .with_context(|| format!("failed to lookup devnr for {:?}", dev))?;
+            if iocost_devs.iter().all(|(_, x)| *x != devnr) {
+                iocost_devs.push((dev.clone(), devnr));
+            }
+        }
+        Ok(iocost_devs)
+    }
+
+    /// The delegated subtree is the topmost ancestor of our own cgroup whose
+    /// controllers we're allowed to configure. Inside a cgroup namespace,
+    /// that's the namespace root.
+    fn resolve_delegated_cgrp() -> Result<String> {
+        let own = fs::read_to_string("/proc/self/cgroup")?
+            .lines()
+            .find_map(|line| line.strip_prefix("0::").map(|x| x.to_string()))
+            .ok_or(anyhow!("no cgroup2 entry in /proc/self/cgroup"))?;
+
+        let root = Path::new(CGRP_ROOT);
+        let mut path = root.join(own.trim_start_matches('/'));
+        if !path.exists() {
+            bail!(
+                "{:?} doesn't exist, is {:?} from another cgroup namespace?",
+                &path,
+                CGRP_ROOT
+            );
+        }
+
+        let writable = |path: &Path| {
+            nix::unistd::access(
+                &path.join("cgroup.subtree_control"),
+                nix::unistd::AccessFlags::W_OK,
+            )
+            .is_ok()
+        };
+        if !writable(&path) {
+            bail!("own cgroup {:?} isn't delegated", &path);
+        }
+        while path != root && writable(path.parent().unwrap()) {
+            path.pop();
+        }
+        let base = path.to_str().unwrap().to_string();
+
+        // Units are created through the system instance of systemd and
+        // placed relative to its root slice, which thus must be the subtree.
+        let sd_root = systemd::Unit::new_sys("-.slice".into())
+            .ok()
+            .and_then(|unit| {
+                unit.props
+                    .string("ControlGroup")
+                    .map(|cg| format!("{}{}", CGRP_ROOT, cg.trim_end_matches('/')))
+            });
+        if sd_root.as_ref() != Some(&base) {
+            bail!(
+                "systemd manages {:?}, not the delegated subtree {:?}",
+                sd_root.unwrap_or_default(),
+                &base
+            );
+        }
+        Ok(base)
+    }
+
     fn new(args_file: &JsonConfigFile<Args>) -> Self {
         let args = &args_file.data;
+        if args.delegated {
+            match Self::resolve_delegated_cgrp() {
+                Ok(base) => {
+                    info!("cfg: Operating in delegated cgroup subtree {:?}", &base);
+                    set_cgrp_base(&base);
+                }
+                Err(e) => {
+                    error!(
+                        "cfg: Failed to resolve the delegated cgroup subtree ({:#})",
+                        &e
+                    );
+                    panic!();
+                }
+            }
+        }
+
         let top_path = Self::prep_dir(&args.dir);
-        if let Err(e) = Self::sgid_top(&top_path, args_file.path.as_ref()) {
+        if plan::dry_run() {
+            debug!("cfg: Skipping SGID setup on {:?} (--dry-run)", &top_path);
+        } else if let Err(e) = Self::sgid_top(&top_path, args_file.path.as_ref()) {
             info!(
                 "cfg: Failed to set group ownership on {:?} ({:#})",
                 &top_path, &e
@@ -293,7 +434,7 @@ impl Config {
 
         let scr_dev = match &args.dev {
             Some(dev) => dev.clone(),
-            None => path_to_devname(&scr_path)
+            None => path_to_devname(Self::existing_ancestor(&scr_path))
                 .expect(&format!(
                     "Failed to lookup device name for {:?}, specify with --dev",
                     &scr_path
@@ -344,6 +485,7 @@ impl Config {
         Self::prep_dir(&report_d_path);
         Self::prep_dir(&report_1min_d_path);
 
+        let restore_path = top_path.clone() + "/restore-journal.json";
         let bench_path = top_path.clone()
             + "/"
             + match args.bench_file.as_ref() {
@@ -361,7 +503,10 @@ impl Config {
             .unwrap()
             .filter_map(Result::ok)
         {
-            if let Err(e) = fs::remove_file(&path) {
+            let res = plan::act(&format!("Remove stale sideloader job {:?}", &path), || {
+                Ok(fs::remove_file(&path)?)
+            });
+            if let Err(e) = res {
                 error!(
                     "cfg: Failed to remove stale sideloader job {:?} ({:#})",
                     &path, &e
@@ -372,10 +517,18 @@ impl Config {
             }
         }
 
+        let scr_devnr = storage_info::devname_to_devnr(&scr_dev).unwrap();
+        let iocost_devs = Self::resolve_iocost_devs(&scr_dev, scr_devnr, &args.iocost_devs)
+            .unwrap_or_else(|e| {
+                error!("cfg: Invalid --iocost-devs ({:#})", &e);
+                panic!();
+            });
+
         Self {
-            scr_devnr: storage_info::devname_to_devnr(&scr_dev).unwrap(),
+            scr_devnr,
             scr_dev,
             scr_dev_forced: args.dev.is_some(),
+            iocost_devs,
             index_path: top_path.clone() + "/index.json",
             sysreqs_path: top_path.clone() + "/sysreqs.json",
             cmd_path: top_path.clone() + "/cmd.json",
@@ -386,6 +539,8 @@ impl Config {
             report_1min_d_path,
             bench_path,
             slices_path: top_path.clone() + "/slices.json",
+            profiles_path: Self::prep_dir(&(top_path.clone() + "/profiles")),
+            restore_path: restore_path.clone(),
             agent_bin,
             hashd_paths: [
                 HashdPaths {
@@ -439,18 +594,17 @@ impl Config {
             } else {
                 Some(args.rep_1min_retention)
             },
+            drilldown: args.drilldown.clone(),
+            drilldown_max: args.drilldown_max,
             force_running: args.force_running,
             bypass: args.bypass,
+            delegated: args.delegated,
             verbosity: args.verbosity,
             enforce: args.enforce.clone(),
 
             sr_failed: Default::default(),
-            sr_iosched: None,
-            sr_wbt: None,
-            sr_wbt_path: None,
-            sr_swappiness: None,
-            sr_zswap_enabled: None,
-            sr_oomd_sys_svc: None,
+            sr_skipped: Default::default(),
+            sr_journal: RestoreJournal::new(&restore_path),
         }
     }
 
@@ -461,9 +615,12 @@ impl Config {
             return;
         }
 
-        if !Path::new("/sys/fs/cgroup/io.stat").exists() {
-            self.sr_failed
-                .add(SysReq::IoCostVer, "/sys/fs/cgroup/io.stat doesn't exist");
+        let io_stat_path = format!("{}/io.stat", cgrp_base());
+        if !Path::new(&io_stat_path).exists() {
+            self.sr_failed.add(
+                SysReq::IoCostVer,
+                &format!("{} doesn't exist", &io_stat_path),
+            );
             return;
         }
 
@@ -480,24 +637,91 @@ impl Config {
             return;
         }
 
-        match read_cgroup_nested_keyed_file("/sys/fs/cgroup/io.stat") {
+        match read_cgroup_nested_keyed_file(&io_stat_path) {
             Ok(is) => {
-                if let Some(stat) = is.get(&format!("{}:{}", self.scr_devnr.0, self.scr_devnr.1)) {
-                    if let None = stat.get("cost.usage") {
-                        self.sr_failed.add(
-                            SysReq::IoCostVer,
-                            "/sys/fs/cgroup/io.stat doesn't contain cost.usage",
-                        );
+                for (dev, devnr) in self.iocost_devs.iter() {
+                    if let Some(stat) = is.get(&format!("{}:{}", devnr.0, devnr.1)) {
+                        if let None = stat.get("cost.usage") {
+                            self.sr_failed.add(
+                                SysReq::IoCostVer,
+                                &format!(
+                                    "{} doesn't contain cost.usage for {:?}",
+                                    &io_stat_path, dev
+                                ),
+                            );
+                        }
                     }
                 }
             }
             Err(e) => {
                 self.sr_failed.add(
                     SysReq::IoCostVer,
-                    &format!("failed to read /sys/fs/cgroup/io.stat ({:#})", &e),
+                    &format!("failed to read {} ({:#})", &io_stat_path, &e),
+                );
+            }
+        }
+    }
+
+    fn check_iosched_and_wbt(&mut self, dev: &str) {
+        let enforce = self.enforce.io && !self.delegated;
+        if enforce {
+            if let Ok(v) = read_iosched(dev) {
+                self.sr_journal.record_iosched(dev, &v);
+            }
+            if let Err(e) = set_iosched(dev, "none") {
+                self.sr_failed.add(
+                    SysReq::IoSched,
+                    &format!("Failed to set none iosched on {:?} ({})", dev, &e),
+                );
+            }
+        }
+
+        match read_iosched(dev) {
+            Ok(v) => {
+                if v != "none" {
+                    self.sr_failed.add(
+                        SysReq::IoSched,
+                        &format!("cfg: iosched on {:?} is {} instead of none", dev, v),
+                    );
+                }
+            }
+            Err(e) => {
+                self.sr_failed.add(
+                    SysReq::IoSched,
+                    &format!("Failed to read iosched for {:?} ({})", dev, &e),
                 );
             }
         }
+
+        // wbt should be disabled
+        let wbt_path = format!("/sys/block/{}/queue/wbt_lat_usec", dev);
+        if let Ok(line) = read_one_line(&wbt_path) {
+            let wbt = match line.trim().parse::<u64>() {
+                Ok(v) => v,
+                Err(e) => {
+                    self.sr_failed.add(
+                        SysReq::NoWbt,
+                        &format!("Failed to parse {:?} ({})", &wbt_path, &e),
+                    );
+                    return;
+                }
+            };
+            if wbt != 0 {
+                if enforce {
+                    info!("cfg: wbt is enabled on {:?}, disabling", dev);
+                    self.sr_journal.record_wbt(&wbt_path, wbt);
+                    if let Err(e) = plan::write_knob(&wbt_path, "0") {
+                        self.sr_failed.add(
+                            SysReq::NoWbt,
+                            &format!("Failed to disable wbt on {:?} ({})", dev, &e),
+                        );
+                    }
+                } else {
+                    self.sr_failed
+                        .add(SysReq::NoWbt, &format!("wbt is enabled on {:?}", dev));
+                }
+            }
+        }
     }
 
     fn check_one_fs(&mut self, path: &str, prefix: &str, enforce: bool) -> Option<MountInfo> {
@@ -505,7 +729,7 @@ impl Config {
             Ok(v) => v,
             Err(e) => {
                 self.sr_failed.add(
-                    SysReq::Btrfs,
+                    SysReq::SupportedFs,
                     &format!(
                         "{}: Failed to map {:?} to mountpoint ({})",
                         prefix, path, &e
@@ -515,48 +739,90 @@ impl Config {
             }
         };
         let rot = is_path_rotational(path);
-        if mi.fstype != "btrfs" {
-            self.sr_failed.add(
-                SysReq::Btrfs,
-                &format!("{}: {:?} is not on btrfs", prefix, path),
-            );
-            return None;
-        }
-        if mi.options.contains(&"space_cache=v2".into())
-            && (rot || mi.options.contains(&"discard=async".into()))
-        {
+        let fsreq = match FS_REQS.iter().find(|req| req.fstype == mi.fstype) {
+            Some(v) => v,
+            None => {
+                self.sr_failed.add(
+                    SysReq::SupportedFs,
+                    &format!(
+                        "{}: {:?} is on {}, not one of {}",
+                        prefix,
+                        path,
+                        &mi.fstype,
+                        FS_REQS
+                            .iter()
+                            .map(|req| req.fstype)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                );
+                return None;
+            }
+        };
+
+        let has_opt = |opt: &str| mi.options.iter().any(|x| x == opt);
+        let missing: Vec<&str> = fsreq
+            .want
+            .iter()
+            .filter(|(opt, ssd_only)| !(*ssd_only && rot) && !has_opt(opt))
+            .map(|(opt, _)| *opt)
+            .collect();
+        let present: Vec<(&str, Option<&str>)> = fsreq
+            .unwant
+            .iter()
+            .filter(|(opt, _)| has_opt(opt))
+            .map(|(opt, neg)| (*opt, *neg))
+            .collect();
+        if missing.is_empty() && present.is_empty() {
             return Some(mi);
         }
 
-        let mut opts = String::from("remount,space_cache=v2");
-        if !rot {
-            opts += ",discard=async";
-        }
+        let mut problems: Vec<String> = missing.iter().map(|x| format!("{:?}", x)).collect();
+        problems.extend(present.iter().map(|(opt, _)| format!("no {:?}", opt)));
+        let problems = problems.join(" and ");
 
-        if !enforce {
+        if let Some((opt, _)) = present.iter().find(|(_, neg)| neg.is_none()) {
             self.sr_failed.add(
-                SysReq::BtrfsAsyncDiscard,
+                SysReq::FsMountOpts,
                 &format!(
-                    "{}: {:?} doesn't have \"space_cache=v2\" and/or \"discard=async\"",
-                    prefix, path
+                    "{}: {:?} has {:?} which can't be changed by remounting",
+                    prefix, path, opt
                 ),
             );
             return None;
         }
 
+        if !enforce {
+            self.sr_failed.add(
+                SysReq::FsMountOpts,
+                &format!("{}: {:?} doesn't have {}", prefix, path, &problems),
+            );
+            return None;
+        }
+
+        let mut opts = String::from("remount");
+        for opt in missing
+            .iter()
+            .chain(present.iter().map(|(_, neg)| neg.as_ref().unwrap()))
+        {
+            opts += ",";
+            opts += opt;
+        }
+
         // enforcing, try remounting w/ the needed options
-        if let Err(e) = run_command(
-            Command::new("mount").arg("-o").arg(&opts).arg(&mi.dest),
-            "failed to enable space_cache=v2 and discard=async",
-        ) {
-            self.sr_failed
-                .add(SysReq::BtrfsAsyncDiscard, &format!("{}", &e));
+        if let Err(e) = plan::act(&format!("Remount {:?} with {:?}", &mi.dest, &opts), || {
+            run_command(
+                Command::new("mount").arg("-o").arg(&opts).arg(&mi.dest),
+                &format!("failed to remount with {:?}", &opts),
+            )
+        }) {
+            self.sr_failed.add(SysReq::FsMountOpts, &format!("{}", &e));
             return None;
         }
 
         info!(
-            "cfg: {:?} didn't have \"space_cache=v2\" and/or \"discard=async\", remounted",
-            path
+            "cfg: {:?} didn't have {}, remounted with {:?}",
+            path, &problems, &opts
         );
         Some(mi)
     }
@@ -598,7 +864,8 @@ impl Config {
         }
 
         if may_restart {
-            if let Ok(()) = systemd::daemon_reload().and(svc.restart()) {
+            let desc = format!("Reload systemd and restart {:?}", svc_name);
+            if let Ok(()) = plan::act(&desc, || systemd::daemon_reload().and(svc.restart())) {
                 sleep(Duration::from_secs(1));
                 let _ = svc.refresh();
                 if let Some(cgrp) = svc.props.string("ControlGroup") {
@@ -620,6 +887,9 @@ impl Config {
     fn startup_checks(&mut self) -> Result<()> {
         let sys = sysinfo::System::new();
 
+        // Host-wide configurations are left alone in a delegated subtree.
+        let host_enforce = !self.delegated;
+
         // Obtain rd-hashd version.
         let output = Command::new(&self.hashd_paths[0].bin)
             .arg("--version")
@@ -642,35 +912,21 @@ impl Config {
                 }
 
                 if !mi.options.contains(&"memory_recursiveprot".to_string()) {
-                    if self.enforce.mem {
-                        match Command::new("mount")
-                            .arg("-o")
-                            .arg("remount,memory_recursiveprot")
-                            .arg(&mi.dest)
-                            .spawn()
-                            .and_then(|mut x| x.wait())
-                        {
-                            Ok(rc) if rc.success() => {
-                                info!("cfg: enabled memcg recursive protection")
-                            }
-                            Ok(rc) => {
-                                self.sr_failed.add(
-                                    SysReq::MemCgRecursiveProt,
-                                    &format!(
-                                        "failed to enable memcg recursive protection ({:?})",
-                                        &rc
-                                    ),
-                                );
-                            }
-                            Err(e) => {
-                                self.sr_failed.add(
-                                    SysReq::MemCgRecursiveProt,
-                                    &format!(
-                                        "failed to enable memcg recursive protection ({:?})",
-                                        &e
-                                    ),
-                                );
-                            }
+                    if self.enforce.mem && host_enforce {
+                        let desc = format!("Remount {:?} with memory_recursiveprot", &mi.dest);
+                        if let Err(e) = plan::act(&desc, || {
+                            run_command(
+                                Command::new("mount")
+                                    .arg("-o")
+                                    .arg("remount,memory_recursiveprot")
+                                    .arg(&mi.dest),
+                                "failed to enable memcg recursive protection",
+                            )?;
+                            info!("cfg: enabled memcg recursive protection");
+                            Ok(())
+                        }) {
+                            self.sr_failed
+                                .add(SysReq::MemCgRecursiveProt, &format!("{:#}", &e));
                         }
                     } else {
                         self.sr_failed.add(
@@ -689,7 +945,7 @@ impl Config {
         }
 
         let mut buf = String::new();
-        fs::File::open("/sys/fs/cgroup/cgroup.controllers")
+        fs::File::open(format!("{}/cgroup.controllers", cgrp_base()))
             .and_then(|mut f| f.read_to_string(&mut buf))?;
         for ctrl in ["cpu", "memory", "io"].iter() {
             if !buf.contains(ctrl) {
@@ -700,13 +956,13 @@ impl Config {
             }
         }
 
-        if !Path::new("/sys/fs/cgroup/system.slice/cgroup.freeze").exists() {
+        if !Path::new(&format!("{}/cgroup.freeze", Slice::Sys.cgrp())).exists() {
             self.sr_failed
                 .add(SysReq::Freezer, "cgroup2 freezer not available");
         }
 
         // IO controllers
-        self.check_iocost(self.enforce.io);
+        self.check_iocost(self.enforce.io && host_enforce);
         slices::check_other_io_controllers(&mut self.sr_failed);
 
         // anon memory balance
@@ -728,10 +984,17 @@ impl Config {
         }
 
         // scratch and root filesystems
-        let mi = self.check_one_fs(&self.scr_path.clone(), "Scratch dir", self.enforce.fs);
+        let scr_fstype = path_to_mountpoint(&self.scr_path)
+            .map(|mi| mi.fstype)
+            .unwrap_or_default();
+        let mi = self.check_one_fs(
+            &self.scr_path.clone(),
+            "Scratch dir",
+            self.enforce.fs && host_enforce,
+        );
 
         if mi.is_none() || mi.unwrap().dest != AsRef::<Path>::as_ref("/") {
-            self.check_one_fs("/", "Root fs", self.enforce.fs);
+            self.check_one_fs("/", "Root fs", self.enforce.fs && host_enforce);
         }
 
         if self.scr_dev.starts_with("md") || self.scr_dev.starts_with("dm") {
@@ -751,83 +1014,59 @@ impl Config {
             }
         }
 
-        // none scheduler
-        if self.enforce.io {
-            if let Ok(v) = read_iosched(&self.scr_dev) {
-                self.sr_iosched = Some(v);
-            }
-            if let Err(e) = set_iosched(&self.scr_dev, "none") {
-                self.sr_failed.add(
-                    SysReq::IoSched,
-                    &format!("Failed to set none iosched on {:?} ({})", &self.scr_dev, &e),
-                );
-            }
+        // none scheduler and wbt disabled on all iocost devices
+        for dev in self
+            .iocost_devs
+            .iter()
+            .map(|(dev, _)| dev.clone())
+            .collect::<Vec<String>>()
+        {
+            self.check_iosched_and_wbt(&dev);
         }
 
-        let scr_dev_iosched = match read_iosched(&self.scr_dev) {
-            Ok(v) => {
-                if v != "none" {
-                    self.sr_failed.add(
-                        SysReq::IoSched,
-                        &format!(
-                            "cfg: iosched on {:?} is {} instead of none",
-                            &self.scr_dev, v
-                        ),
-                    );
-                }
-                v
-            }
-            Err(e) => {
-                self.sr_failed.add(
-                    SysReq::IoSched,
-                    &format!("Failed to read iosched for {:?} ({})", &self.scr_dev, &e),
-                );
-                "UNKNOWN".into()
-            }
-        };
+        let scr_dev_iosched = read_iosched(&self.scr_dev).unwrap_or("UNKNOWN".into());
 
-        // wbt should be disabled
-        let wbt_path = format!("/sys/block/{}/queue/wbt_lat_usec", &self.scr_dev);
-        if let Ok(line) = read_one_line(&wbt_path) {
-            let wbt = line.trim().parse::<u64>()?;
-            if wbt != 0 {
-                if self.enforce.io {
-                    info!("cfg: wbt is enabled on {:?}, disabling", &self.scr_dev);
-                    if let Err(e) = write_one_line(&wbt_path, "0") {
-                        self.sr_failed.add(
-                            SysReq::NoWbt,
-                            &format!("Failed to disable wbt on {:?} ({})", &self.scr_dev, &e),
-                        );
-                    }
-                    self.sr_wbt = Some(wbt);
-                    self.sr_wbt_path = Some(wbt_path);
-                } else {
-                    self.sr_failed.add(
-                        SysReq::NoWbt,
-                        &format!("wbt is enabled on {:?}", &self.scr_dev),
-                    );
+        // swap IOs should be on the scratch device or one of the iocost
+        // devices, zram doesn't issue IOs
+        let swap_devs = swap::swap_devs()?;
+        for sd in swap_devs.iter() {
+            info!(
+                "cfg: Swap {:?} kind={:?} dev={:?} prio={} size={:.2}G{}",
+                &sd.path,
+                sd.kind,
+                &sd.dev,
+                sd.priority,
+                to_gb(sd.size),
+                match sd.zram.as_ref() {
+                    Some(zram) => format!(
+                        " comp={} mem_limit={:.2}G",
+                        &zram.comp_algorithm,
+                        to_gb(zram.mem_limit)
+                    ),
+                    None => "".into(),
                 }
-            }
+            );
         }
-
-        // swap should be on the same device as scratch
-        for swap_dev in swap_devnames()?.iter() {
-            let dev = swap_dev.to_str().unwrap_or_default().to_string();
-            if dev != self.scr_dev {
+        for sd in swap_devs.iter() {
+            let (swap_dev, dev) = (&sd.path, &sd.dev);
+            if sd.kind == SwapKind::Zram || self.iocost_devs.iter().any(|(idev, _)| idev == dev) {
+                continue;
+            }
+            if *dev != self.scr_dev {
                 if self.scr_dev_forced {
                     let det_scr_dev = path_to_devname(&self.scr_path).unwrap_or_default();
                     if dev != det_scr_dev.to_str().unwrap_or_default() {
                         warn!(
-                            "cfg: Swap backing dev {:?} is different from forced scratch dev {:?}",
-                            &swap_dev, &self.scr_dev
+                            "cfg: Swap {:?} backing dev {:?} is different from forced scratch dev {:?}",
+                            swap_dev, dev, &self.scr_dev
                         );
                     }
                 } else {
                     self.sr_failed.add(
                         SysReq::SwapOnScratch,
                         &format!(
-                            "Swap backing dev {:?} is different from scratch backing dev {:?}",
-                            &swap_dev, self.scr_dev
+                            "Swap {:?} backing dev {:?} is different from scratch backing dev {:?}",
+                            swap_dev, dev, self.scr_dev
                         ),
                     );
                 }
@@ -860,16 +1099,16 @@ impl Config {
         }
 
         if let Ok(swappiness) = read_swappiness() {
-            if self.enforce.mem {
-                self.sr_swappiness = Some(swappiness);
+            if self.enforce.mem && host_enforce {
+                self.sr_journal.record_swappiness(swappiness);
             }
             if swappiness < 60 {
-                if self.enforce.mem {
+                if self.enforce.mem && host_enforce {
                     info!(
                         "cfg: Swappiness {} is smaller than default 60, updating to 60",
                         swappiness
                     );
-                    if let Err(e) = write_one_line(SWAPPINESS_PATH, "60") {
+                    if let Err(e) = plan::write_knob(SWAPPINESS_PATH, "60") {
                         self.sr_failed.add(
                             SysReq::Swap,
                             &format!("Failed to update swappiness ({})", &e),
@@ -885,8 +1124,8 @@ impl Config {
         }
 
         if let Ok(zswap_enabled) = read_zswap_enabled() {
-            if self.enforce.mem {
-                self.sr_zswap_enabled = Some(zswap_enabled);
+            if self.enforce.mem && host_enforce {
+                self.sr_journal.record_zswap_enabled(zswap_enabled);
             }
         }
 
@@ -903,18 +1142,17 @@ impl Config {
 
         // make sure oomd or earlyoom isn't gonna interfere
         if let Some(oomd_sys_svc) = &self.oomd_sys_svc {
-            if let Ok(svc) = systemd::Unit::new_sys(oomd_sys_svc.clone()) {
+            if let Ok(mut svc) = systemd::Unit::new_sys(oomd_sys_svc.clone()) {
                 if svc.state == systemd::UnitState::Running && self.enforce.oomd {
-                    self.sr_oomd_sys_svc = Some(svc);
-                    let svc = self.sr_oomd_sys_svc.as_mut().unwrap();
+                    self.sr_journal.record_oomd_sys_svc(&svc.name);
                     info!("cfg: Stopping {:?} while resctl-demo is running", &svc.name);
-                    let _ = svc.stop();
+                    let _ = plan::act(&format!("Stop {:?}", &svc.name), || svc.stop());
                 }
             }
         }
 
         if let Ok(mut svc) = systemd::Unit::new_sys(OOMD_SVC_NAME.into()) {
-            let _ = svc.stop();
+            let _ = plan::act(&format!("Stop {:?}", OOMD_SVC_NAME), || svc.stop());
         }
 
         // Gotta re-read sysinfo to avoid reading cached oomd pid from
@@ -961,9 +1199,11 @@ impl Config {
 
         // hostcriticals - ones which can be restarted for relocation
         for svc_name in ["systemd-journald.service", "sshd.service", "sssd.service"].iter() {
-            if let Err(e) =
-                Self::check_one_hostcritical_service(svc_name, true, self.enforce.crit_mem_prot)
-            {
+            if let Err(e) = Self::check_one_hostcritical_service(
+                svc_name,
+                true,
+                self.enforce.crit_mem_prot && host_enforce,
+            ) {
                 self.sr_failed
                     .add(SysReq::HostCriticalServices, &format!("{}", &e));
             }
@@ -971,9 +1211,11 @@ impl Config {
 
         // and the ones which can't
         for svc_name in ["dbus.service", "dbus-broker.service"].iter() {
-            if let Err(e) =
-                Self::check_one_hostcritical_service(svc_name, false, self.enforce.crit_mem_prot)
-            {
+            if let Err(e) = Self::check_one_hostcritical_service(
+                svc_name,
+                false,
+                self.enforce.crit_mem_prot && host_enforce,
+            ) {
                 self.sr_failed
                     .add(SysReq::HostCriticalServices, &format!("{}", &e));
             }
@@ -992,8 +1234,25 @@ impl Config {
                 ),
             };
 
-        SysReqsReport {
-            satisfied: &*ALL_SYSREQS_SET ^ &self.sr_failed.map.keys().copied().collect(),
+        // Host-level requirements are outside our control when delegated.
+        if self.delegated {
+            for req in HOST_SYSREQS_SET.iter() {
+                if let Some(msgs) = self.sr_failed.map.remove(req) {
+                    for msg in msgs.iter() {
+                        info!("cfg: Skipping host-level {:?} ({})", req, msg);
+                    }
+                    self.sr_skipped.map.insert(*req, msgs);
+                }
+            }
+        }
+
+        let mut satisfied = &*ALL_SYSREQS_SET ^ &self.sr_failed.map.keys().copied().collect();
+        if self.delegated {
+            satisfied = &satisfied - &*HOST_SYSREQS_SET;
+        }
+
+        let sysreqs = SysReqsReport {
+            satisfied,
             missed: self.sr_failed.clone(),
             kernel_version: sys.kernel_version().expect("Failed to read kernel version"),
             agent_version: FULL_VERSION.to_string(),
@@ -1007,9 +1266,20 @@ impl Config {
             scr_dev_fwrev,
             scr_dev_size,
             scr_dev_iosched,
+            scr_fstype,
+            iocost_devs: self
+                .iocost_devs
+                .iter()
+                .map(|(dev, _)| dev.clone())
+                .collect(),
+            swap_devs,
+            delegated: self.delegated,
+            cgrp_base: cgrp_base(),
             enforce: self.enforce.clone(),
+        };
+        if !plan::dry_run() {
+            sysreqs.save(&self.sysreqs_path)?;
         }
-        .save(&self.sysreqs_path)?;
 
         if self.sr_failed.map.is_empty() {
             Ok(())
@@ -1027,43 +1297,17 @@ impl Config {
 
     pub fn memcg_recursive_prot(&self) -> bool {
         !self.sr_failed.map.contains_key(&SysReq::MemCgRecursiveProt)
+            && !self
+                .sr_skipped
+                .map
+                .contains_key(&SysReq::MemCgRecursiveProt)
     }
 }
 
 impl Drop for Config {
     fn drop(&mut self) {
-        if let Some(iosched) = self.sr_iosched.as_ref() {
-            if let Err(e) = set_iosched(&self.scr_dev, iosched) {
-                error!("cfg: Failed to restore iosched to {:?} ({:#})", iosched, &e);
-            }
-        }
-        if let Some(wbt) = self.sr_wbt {
-            let path = self.sr_wbt_path.as_ref().unwrap();
-            info!("cfg: Restoring {:?} to {}", path, wbt);
-            if let Err(e) = write_one_line(path, &format!("{}", wbt)) {
-                error!("cfg: Failed to restore {:?} ({:#})", &path, &e);
-            }
-        }
-        if let Some(swappiness) = self.sr_swappiness {
-            info!("cfg: Restoring swappiness to {}", swappiness);
-            if let Err(e) = write_one_line(SWAPPINESS_PATH, &format!("{}", swappiness)) {
-                error!("cfg: Failed to restore swappiness ({:#})", &e);
-            }
-        }
-        if let Some(enabled) = self.sr_zswap_enabled {
-            info!("cfg: Restoring zswap enabled to {}", enabled);
-            if let Err(e) = write_one_line(
-                ZSWAP_ENABLED_PATH,
-                &format!("{}", if enabled { "Y" } else { "N" }),
-            ) {
-                error!("cfg: Failed to restore zswap enabled ({:#})", &e);
-            }
-        }
-        if let Some(svc) = &mut self.sr_oomd_sys_svc {
-            info!("cfg: Restoring {:?}", &svc.name);
-            if let Err(e) = svc.try_start() {
-                error!("cfg: Failed to restore {:?} ({:#})", &svc.name, &e);
-            }
+        if !plan::dry_run() {
+            self.sr_journal.replay();
         }
     }
 }
@@ -1155,25 +1399,28 @@ pub struct SysObjs {
     pub sideloader: sideloader::Sideloader,
     pub cmd_file: JsonConfigFile<Cmd>,
     pub cmd_ack_file: JsonReportFile<CmdAck>,
-    enforce_cfg: EnforceConfig,
+    pub profile: Option<String>,
+    pub profile_seq: u64,
 }
 
 impl SysObjs {
     fn new(cfg: &Config) -> Self {
-        let bench_file = JsonConfigFile::load_or_create(Some(&cfg.bench_path)).unwrap();
+        let bench_file = plan::load_config_file(&cfg.bench_path).unwrap();
 
-        let slice_file = JsonConfigFile::load_or_create(Some(&cfg.slices_path)).unwrap();
+        let slice_file = plan::load_config_file(&cfg.slices_path).unwrap();
 
-        let side_def_file = JsonConfigFile::load_or_create(Some(&cfg.side_defs_path)).unwrap();
+        let side_def_file = plan::load_config_file(&cfg.side_defs_path).unwrap();
 
-        let cmd_file = JsonConfigFile::load_or_create(Some(&cfg.cmd_path)).unwrap();
+        let cmd_file = plan::load_config_file(&cfg.cmd_path).unwrap();
 
         let cmd_ack_file = JsonReportFile::new(Some(&cfg.cmd_ack_path));
-        cmd_ack_file.commit().unwrap();
+        if !plan::dry_run() {
+            cmd_ack_file.commit().unwrap();
+        }
 
-        let rep_seq = match Report::load(&cfg.report_path) {
-            Ok(rep) => rep.seq + 1,
-            Err(_) => 1,
+        let (rep_seq, profile, profile_seq) = match Report::load(&cfg.report_path) {
+            Ok(rep) => (rep.seq + 1, rep.profile, rep.profile_seq),
+            Err(_) => (1, None, 0),
         };
         INSTANCE_SEQ.store(rep_seq, Ordering::Relaxed);
 
@@ -1185,18 +1432,25 @@ impl SysObjs {
             sideloader: sideloader::Sideloader::new(&cfg).unwrap(),
             cmd_file,
             cmd_ack_file,
-            enforce_cfg: cfg.enforce.clone(),
+            profile,
+            profile_seq,
         }
     }
 }
 
-impl Drop for SysObjs {
-    fn drop(&mut self) {
-        debug!("cfg: Clearing slice configurations");
-        if let Err(e) = slices::clear_slices(&self.enforce_cfg) {
-            warn!("cfg: Failed to clear slice configurations ({:#})", &e);
-        }
-    }
+fn save_profile(cfg: &Config, name: &str) -> Result<()> {
+    let path = Profile::path(&cfg.profiles_path, name)?;
+    let cmd = plan::load_config_file::<Cmd>(&cfg.cmd_path)?;
+    let slices = plan::load_config_file::<SliceKnobs>(&cfg.slices_path)?;
+    let oomd = plan::load_config_file::<OomdKnobs>(&cfg.oomd_cfg_path)?;
+    let seq = match Report::load(&cfg.report_path) {
+        Ok(rep) => rep.seq,
+        Err(_) => 1,
+    };
+
+    Profile::snapshot(&cmd.data, &slices.data, &oomd.data, seq).save(&path)?;
+    info!("cfg: Saved profile {:?} to {:?}", name, &path);
+    Ok(())
 }
 
 fn update_index(cfg: &Config) -> Result<()> {
@@ -1225,6 +1479,7 @@ fn update_index(cfg: &Config) -> Result<()> {
             },
         ],
         sideload_defs: cfg.side_defs_path.clone(),
+        profiles: cfg.profiles_path.clone(),
     };
 
     index.save(&cfg.index_path)
@@ -1251,42 +1506,78 @@ fn main() {
 
     systemd::set_systemd_timeout(args_file.data.systemd_timeout);
 
+    if args_file.data.dry_run {
+        plan::set_dry_run();
+    }
+
     let mut cfg = Config::new(&args_file);
 
-    if args_file.data.reset {
+    if args_file.data.reset && !plan::dry_run() {
         reset_agent_states(&cfg);
     }
 
-    if let Err(e) = update_index(&cfg) {
-        error!("cfg: Failed to update {:?} ({:#})", &cfg.index_path, &e);
-        panic!();
+    if !plan::dry_run() {
+        if let Err(e) = update_index(&cfg) {
+            error!("cfg: Failed to update {:?} ({:#})", &cfg.index_path, &e);
+            panic!();
+        }
     }
 
-    if let Err(e) = misc::prepare_misc_bins(&cfg, args_file.data.prepare) {
-        error!("cfg: Failed to prepare misc support binaries ({:#})", &e);
-        panic!();
+    if let Some(name) = args_file.data.save_profile.as_ref() {
+        if let Err(e) = save_profile(&cfg, name) {
+            error!("cfg: Failed to save profile {:?} ({:#})", name, &e);
+            panic!();
+        }
+        return;
     }
 
-    if let Err(e) = side::prepare_side_bins(&cfg) {
-        error!("cfg: Failed to prepare sideload binaries ({:#})", &e);
-        panic!();
-    }
+    if !plan::dry_run() {
+        if let Err(e) = misc::prepare_misc_bins(&cfg, args_file.data.prepare) {
+            error!("cfg: Failed to prepare misc support binaries ({:#})", &e);
+            panic!();
+        }
 
-    match cfg.side_linux_tar_path.as_deref() {
-        Some("__SKIP__") => {}
-        _ => {
-            if let Err(e) = side::prepare_linux_tar(&cfg) {
-                error!("cfg: Failed to prepare linux tarball ({:#})", &e);
-                panic!();
+        if let Err(e) = side::prepare_side_bins(&cfg) {
+            error!("cfg: Failed to prepare sideload binaries ({:#})", &e);
+            panic!();
+        }
+
+        match cfg.side_linux_tar_path.as_deref() {
+            Some("__SKIP__") => {}
+            _ => {
+                if let Err(e) = side::prepare_linux_tar(&cfg) {
+                    error!("cfg: Failed to prepare linux tarball ({:#})", &e);
+                    panic!();
+                }
             }
         }
     }
 
-    let mut _iocost_sys_save = None;
+    if args_file.data.restore {
+        if let Err(e) = RestoreJournal::replay_file(&cfg.restore_path) {
+            error!("cfg: Failed to replay {:?} ({:#})", &cfg.restore_path, &e);
+            panic!();
+        }
+        return;
+    }
+
     if !cfg.bypass {
-        _iocost_sys_save = Some(IoCostSysSave::read_from_sys(cfg.scr_devnr));
+        if let Err(e) = RestoreJournal::replay_file(&cfg.restore_path) {
+            error!("cfg: Failed to replay {:?} ({:#})", &cfg.restore_path, &e);
+            panic!();
+        }
+        for (_, devnr) in cfg.iocost_devs.clone().iter() {
+            if let Err(e) = cfg.sr_journal.record_iocost(*devnr) {
+                warn!(
+                    "cfg: Failed to save io.cost.model,qos of {}:{} ({:#})",
+                    devnr.0, devnr.1, &e
+                );
+            }
+        }
         if let Err(e) = cfg.startup_checks() {
-            if args_file.data.force {
+            if plan::dry_run() {
+                warn!("cfg: {} (--dry-run)", &e);
+            } else if args_file.data.force {
                 warn!(
                     "cfg: Ignoring startup check failures as per --force ({})",
                     &e
@@ -1323,16 +1614,14 @@ fn main() {
     trace!("{:#?}", &cfg);
 
     if let Err(e) = bench::apply_iocost(&sobjs.bench_file.data, &cfg) {
-        error!(
-            "cfg: Failed to configure iocost controller on {:?} ({:#})",
-            cfg.scr_dev, &e
-        );
+        error!("cfg: Failed to configure iocost controller ({:#})", &e);
         panic!();
     }
 
     let mem_size = sobjs.bench_file.data.hashd.actual_mem_size();
     let workload_senpai = sobjs.oomd.workload_senpai_enabled();
 
+    cfg.sr_journal.record_slices(&cfg.enforce.clone());
     if let Err(e) = slices::apply_slices(&mut sobjs.slice_file.data, mem_size, &cfg) {
         error!("cfg: Failed to apply slice configurations ({:#})", &e);
         panic!();
@@ -1364,5 +1653,11 @@ fn main() {
         }
     }
 
+    if plan::dry_run() {
+        sobjs.sideloader.svc.keep = true;
+        plan::print();
+        return;
+    }
+
     cmd::Runner::new(cfg, sobjs).run();
 }
@@ -65,9 +65,15 @@ parser.add_argument(
     action="store_true",
     help="Warn configuration issues but don't try to fix them",
 )
+parser.add_argument(
+    "--cgroup-fs",
+    default=CGRP_BASE,
+    help="Cgroup directory the slices are under (default: %(default)s)",
+)
 parser.add_argument("--verbose", "-v", action="count")
 
 args = parser.parse_args()
+CGRP_BASE = args.cgroup_fs
 
 #
 # Utility functions
@@ -1,15 +1,19 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 use anyhow::Result;
-use log::{debug, warn};
-use std::fs;
-use std::io::prelude::*;
+use chrono::prelude::*;
+use log::{debug, info, warn};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 use rd_util::*;
 
 use rd_agent_intf::{
-    OomdKnobs, OomdReport, OomdSliceMemPressureKnobs, OomdSliceSenpaiKnobs, Slice, OOMD_SVC_NAME,
+    OomdKillReport, OomdKnobs, OomdReport, OomdSliceMemPressureKnobs, OomdSliceSenpaiKnobs,
+    SenpaiMode, Slice, OOMD_SVC_NAME,
 };
 
+use super::senpai::Senpai;
 use super::Config;
 
 const OOMD_RULE_HEAD: &str = r#"{
@@ -182,7 +186,7 @@ fn oomd_cfg_slice_mem_pressure(knobs: &OomdSliceMemPressureKnobs, slice: Slice)
 
 fn oomd_cfg_slice_senpai(knobs: &OomdSliceSenpaiKnobs, slice: Slice, mem_size: u64) -> String {
     let mut oomd_cfg = String::new();
-    if !knobs.enable {
+    if !knobs.enable || knobs.mode != SenpaiMode::Oomd {
         return oomd_cfg;
     }
     oomd_cfg += &oomd_rule_senpai(
@@ -199,40 +203,107 @@ fn oomd_cfg_slice_senpai(knobs: &OomdSliceSenpaiKnobs, slice: Slice, mem_size: u
     oomd_cfg
 }
 
+const SYSTEMD_OOMD_SVC_NAME: &str = "systemd-oomd.service";
+const OOMD_KILLS_RETENTION: usize = 16;
+
+// oomd logs "oomd kill: P10 P60 P300 CGROUP USAGE ruleset:[..] detectorgroup:[..]
+// killer:NAME v2" while systemd-oomd logs "Killed CGROUP due to REASON".
+fn parse_oomd_kill(msg: &str) -> Option<(String, String)> {
+    if let Some(rest) = msg.trim().strip_prefix("oomd kill: ") {
+        let toks: Vec<&str> = rest.splitn(6, ' ').collect();
+        if toks.len() < 6 {
+            return None;
+        }
+        let reason = match (toks[5].find("ruleset:["), toks[5].find("] ")) {
+            (Some(start), Some(end)) if start + 9 <= end => &toks[5][start + 9..end],
+            _ => toks[5],
+        };
+        return Some((toks[3].to_string(), reason.to_string()));
+    }
+    if let Some(rest) = msg.trim().strip_prefix("Killed ") {
+        if let Some(pos) = rest.find(" due to ") {
+            return Some((rest[..pos].to_string(), rest[pos + 8..].to_string()));
+        }
+    }
+    None
+}
+
+#[derive(Default)]
+struct OomdKills {
+    nr_kills: u64,
+    kills: VecDeque<OomdKillReport>,
+}
+
+fn oomd_kill_tailer(kills: Arc<Mutex<OomdKills>>) -> JournalTailer {
+    let started_at = SystemTime::now();
+    JournalTailer::new(
+        &[OOMD_SVC_NAME, SYSTEMD_OOMD_SVC_NAME],
+        OOMD_KILLS_RETENTION,
+        Box::new(move |msgs, _flush| {
+            let msg = match msgs.front() {
+                Some(v) if v.at >= started_at => v,
+                _ => return,
+            };
+            if let Some((cgroup, reason)) = parse_oomd_kill(&msg.msg) {
+                info!("oomd: {} killed {:?} ({})", &msg.unit, &cgroup, &reason);
+                let mut kills = kills.lock().unwrap();
+                kills.nr_kills += 1;
+                kills.kills.push_front(OomdKillReport {
+                    at: DateTime::from(msg.at),
+                    daemon: msg.unit.clone(),
+                    cgroup,
+                    reason,
+                });
+                kills.kills.truncate(OOMD_KILLS_RETENTION);
+            }
+        }),
+    )
+}
+
 pub struct Oomd {
     bin: Option<String>,
     daemon_cfg_path: String,
     svc: Option<TransientService>,
+    senpai: BTreeMap<String, Senpai>,
+    kills: Arc<Mutex<OomdKills>>,
+    _kill_tailer: JournalTailer,
 
     pub file: JsonConfigFile<OomdKnobs>,
 }
 
 impl Oomd {
     pub fn new(cfg: &Config) -> Result<Self> {
-        let file = JsonConfigFile::<OomdKnobs>::load_or_create(Some(&cfg.oomd_cfg_path.clone()))?;
+        let file = super::plan::load_config_file::<OomdKnobs>(&cfg.oomd_cfg_path)?;
 
         let bin = match cfg.oomd_bin.as_ref() {
             Ok(v) => Some(v.clone()),
             Err(_) => None,
         };
 
+        let kills = Arc::new(Mutex::new(OomdKills::default()));
+        let kill_tailer = oomd_kill_tailer(kills.clone());
+
         Ok(Self {
             bin,
             daemon_cfg_path: cfg.oomd_daemon_cfg_path.clone(),
             file,
             svc: None,
+            senpai: BTreeMap::new(),
+            kills,
+            _kill_tailer: kill_tailer,
         })
     }
 
     pub fn stop(&mut self) {
         debug!("oomd: Stoppping");
         self.svc = None;
+        self.senpai.clear();
 
         // clean up after senpai
         for slice in &[Slice::Work, Slice::Sys] {
-            let path = format!("/sys/fs/cgroup/{}/memory.high", slice.name());
+            let path = format!("{}/memory.high", slice.cgrp());
             debug!("oomd: clearing {:?}", &path);
-            if let Err(e) = write_one_line(&path, "max") {
+            if let Err(e) = super::plan::write_knob(&path, "max") {
                 warn!(
                     "oomd: Failed to clear {:?} after shutdown ({:?})",
                     &path, &e
@@ -241,16 +312,47 @@ impl Oomd {
         }
     }
 
+    fn start_native_senpai(&mut self) {
+        let knobs = &self.file.data;
+        if knobs.disable_seq >= super::instance_seq() {
+            return;
+        }
+
+        for (slice, sk) in [
+            (Slice::Work, &knobs.workload.senpai),
+            (Slice::Sys, &knobs.system.senpai),
+        ]
+        .iter()
+        {
+            if !sk.enable || sk.mode == SenpaiMode::Oomd {
+                continue;
+            }
+            let desc = format!("Start native {:?} senpai on {:?}", sk.mode, slice.name());
+            let senpai = &mut self.senpai;
+            let _ = super::plan::act(&desc, || {
+                info!("oomd: {}", &desc);
+                senpai.insert(
+                    slice.name().into(),
+                    Senpai::new(*slice, sk, total_memory() as u64),
+                );
+                Ok(())
+            });
+        }
+    }
+
     pub fn apply(&mut self) -> Result<()> {
+        if self.svc.is_some() || !self.senpai.is_empty() {
+            self.stop();
+        }
+
+        // Native senpai doesn't depend on oomd.
+        self.start_native_senpai();
+
         if self.bin.is_none() {
             warn!("oomd: Configuration update requested but oomd is not available");
             return Ok(());
         }
 
-        if self.svc.is_some() {
-            self.stop();
-        }
-
         let knobs = &self.file.data;
 
         let mut oomd_cfg = OOMD_RULE_HEAD.to_string();
@@ -288,33 +390,32 @@ impl Oomd {
         oomd_cfg += OOMD_RULE_TAIL;
 
         debug!("oomd: Updating {:?}", &self.daemon_cfg_path);
-        let mut f = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.daemon_cfg_path)?;
-        f.write_all(oomd_cfg.as_ref())?;
+        super::plan::write_file(&self.daemon_cfg_path, &oomd_cfg)?;
 
         if knobs.disable_seq >= super::instance_seq() {
             return Ok(());
         }
 
-        let mut svc = TransientService::new_sys(
-            OOMD_SVC_NAME.into(),
-            vec![
-                self.bin.as_ref().unwrap().clone(),
-                "--config".into(),
-                self.daemon_cfg_path.clone(),
-                "--interval".into(),
-                "1".into(),
-            ],
-            vec![],
-            Some(0o002),
-        )?;
-        svc.set_slice(Slice::Host.name())
-            .set_restart_always()
-            .start()?;
-        self.svc = Some(svc);
+        self.svc = super::plan::act(&format!("Start {:?}", OOMD_SVC_NAME), || {
+            let mut svc = TransientService::new_sys(
+                OOMD_SVC_NAME.into(),
+                vec![
+                    self.bin.as_ref().unwrap().clone(),
+                    "--config".into(),
+                    self.daemon_cfg_path.clone(),
+                    "--interval".into(),
+                    "1".into(),
+                    "--cgroup-fs".into(),
+                    rd_agent_intf::cgrp_base(),
+                ],
+                vec![],
+                Some(0o002),
+            )?;
+            svc.set_slice(Slice::Host.name())
+                .set_restart_always()
+                .start()?;
+            Ok(Some(svc))
+        })?;
         Ok(())
     }
 
@@ -331,6 +432,7 @@ impl Oomd {
 
         let seq = super::instance_seq();
         let knobs = &self.file.data;
+        let kills = self.kills.lock().unwrap();
 
         Ok(OomdReport {
             svc: svc_r,
@@ -338,6 +440,51 @@ impl Oomd {
             work_senpai: knobs.workload.senpai.enable,
             sys_mem_pressure: knobs.system.mem_pressure.disable_seq < seq,
             sys_senpai: knobs.system.senpai.enable,
+            nr_kills: kills.nr_kills,
+            kills: kills.kills.iter().cloned().collect(),
+            senpai: self
+                .senpai
+                .iter()
+                .map(|(slice, senpai)| (slice.clone(), senpai.report.lock().unwrap().clone()))
+                .collect(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse_oomd_kill() {
+        assert_eq!(
+            super::parse_oomd_kill(
+                "oomd kill: 61.37 44.98 19.85 /workload.slice/workload-tenant.slice 8438022144 \
+                 ruleset:[protection against high memory pressure] \
+                 detectorgroup:[workload memory pressure] killer:kill_by_memory_size_or_growth v2"
+            ),
+            Some((
+                "/workload.slice/workload-tenant.slice".to_string(),
+                "protection against high memory pressure".to_string()
+            ))
+        );
+        assert_eq!(
+            super::parse_oomd_kill(
+                "Killed /user.slice/user-1000.slice/user@1000.service/app.slice/app-firefox.scope \
+                 due to memory pressure for /user.slice/user-1000.slice/user@1000.service \
+                 being 57.39% > 50.00% for > 20s with reclaim activity"
+            ),
+            Some((
+                "/user.slice/user-1000.slice/user@1000.service/app.slice/app-firefox.scope"
+                    .to_string(),
+                "memory pressure for /user.slice/user-1000.slice/user@1000.service \
+                 being 57.39% > 50.00% for > 20s with reclaim activity"
+                    .to_string()
+            ))
+        );
+        assert_eq!(
+            super::parse_oomd_kill(
+                "Swap is currently not detected; memory pressure usage will be degraded"
+            ),
+            None
+        );
+    }
+}
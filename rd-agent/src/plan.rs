@@ -0,0 +1,127 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// Dry-run support. With --dry-run, system configuration changes are recorded
+// instead of being applied and printed against the current state on exit.
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use rd_util::*;
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    static ref PLAN: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+pub fn set_dry_run() {
+    DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+fn record(item: String) {
+    PLAN.lock().unwrap().push(item);
+}
+
+// Keyed files such as io.cost.model have one line per key. Pick the line
+// which matches the first token of the new value if there is one.
+fn current_value(path: &str, val: &str) -> String {
+    let body = match fs::read_to_string(path) {
+        Ok(v) => v,
+        Err(e) => return format!("<{}>", &e),
+    };
+    let key = val.split_whitespace().next().unwrap_or("");
+    body.lines()
+        .find(|line| line.split_whitespace().next() == Some(key))
+        .unwrap_or(body.trim())
+        .to_string()
+}
+
+/// Write a single line knob file such as sysfs and procfs files.
+pub fn write_knob(path: &str, val: &str) -> Result<()> {
+    if !dry_run() {
+        return write_one_line(path, val);
+    }
+    let cur = current_value(path, val);
+    if cur != val {
+        record(format!("{}\n  - {}\n  + {}", path, &cur, val));
+    }
+    Ok(())
+}
+
+// Minimal LCS based line diff, configuration files are short.
+fn diff_lines(old: &str, new: &str) -> Vec<String> {
+    let (a, b): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = match a[i] == b[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut out = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push(format!("    {}", a[i]));
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push(format!("  + {}", b[j]));
+            j += 1;
+        } else {
+            out.push(format!("  - {}", a[i]));
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Write out a whole file such as systemd configlets and daemon configs.
+pub fn write_file(path: &str, body: &str) -> Result<()> {
+    if !dry_run() {
+        return Ok(fs::write(path, body)?);
+    }
+    let cur = fs::read_to_string(path).unwrap_or_default();
+    if cur != body {
+        record(format!("{}\n{}", path, diff_lines(&cur, body).join("\n")));
+    }
+    Ok(())
+}
+
+/// JsonConfigFile::load_or_create() which uses the defaults instead of
+/// creating a missing file with --dry-run.
+pub fn load_config_file<T: JsonLoad + JsonSave>(path: &str) -> Result<JsonConfigFile<T>> {
+    if dry_run() && !Path::new(path).exists() {
+        return JsonConfigFile::load_or_create(None::<&str>);
+    }
+    JsonConfigFile::load_or_create(Some(path))
+}
+
+/// Perform an action which changes system state, e.g. starting a service.
+pub fn act<T: Default, F: FnOnce() -> Result<T>>(desc: &str, action: F) -> Result<T> {
+    if !dry_run() {
+        return action();
+    }
+    record(format!("* {}", desc));
+    Ok(Default::default())
+}
+
+pub fn print() {
+    let plan = PLAN.lock().unwrap();
+    if plan.is_empty() {
+        println!("No changes planned");
+        return;
+    }
+    for item in plan.iter() {
+        println!("{}\n", item);
+    }
+}
@@ -21,8 +21,8 @@ use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use super::cmd::Runner;
 use super::Config;
 use rd_agent_intf::{
-    report::StatMap, BenchHashdReport, BenchIoCostReport, HashdReport, IoCostReport, IoLatReport,
-    Report, ResCtlReport, Slice, UsageReport, ROOT_SLICE,
+    cgrp_base, report::StatMap, BenchHashdReport, BenchIoCostReport, HashdReport, IoCostReport,
+    IoLatReport, MemEventsReport, Report, ResCtlReport, Slice, UsageReport, ROOT_SLICE,
 };
 use rd_util::*;
 
@@ -30,6 +30,13 @@ use rd_util::*;
 struct Usage {
     cpu_busy: f64,
     cpu_sys: f64,
+    cpu_nr_periods: u64,
+    cpu_nr_throttled: u64,
+    cpu_throttled: f64,
+    cpu_nr_bursts: u64,
+    cpu_burst: f64,
+    cpu_runq_delay: f64,
+    cpu_nr_timeslices: u64,
     mem_bytes: u64,
     swap_bytes: u64,
     swap_free: u64,
@@ -41,9 +48,10 @@ struct Usage {
     io_stalls: (f64, f64),
     mem_stat: StatMap,
     io_stat: StatMap,
+    mem_events: MemEventsReport,
 }
 
-fn read_stalls(path: &str) -> Result<(f64, f64)> {
+pub fn read_stalls(path: &str) -> Result<(f64, f64)> {
     let f = fs::OpenOptions::new().read(true).open(path)?;
     let r = BufReader::new(f);
     let (mut some, mut full) = (None, None);
@@ -71,6 +79,39 @@ fn read_stat_file(path: &str) -> Result<StatMap> {
     Ok(map.iter().map(|(k, v)| (k.clone(), *v as f64)).collect())
 }
 
+fn read_mem_events(path: &str) -> Result<MemEventsReport> {
+    let map = read_cgroup_flat_keyed_file(path)?;
+    let get = |key: &str| map.get(key).cloned().unwrap_or(0);
+    Ok(MemEventsReport {
+        low: get("low"),
+        high: get("high"),
+        max: get("max"),
+        oom: get("oom"),
+        oom_kill: get("oom_kill"),
+        oom_group_kill: get("oom_group_kill"),
+    })
+}
+
+// Sum run_delay (ns) and pcount (# of timeslices) across all cpus from
+// /proc/schedstat, see Documentation/scheduler/sched-stats.rst.
+fn read_schedstat() -> Result<(f64, u64)> {
+    let f = fs::OpenOptions::new().read(true).open("/proc/schedstat")?;
+    let r = BufReader::new(f);
+    let (mut delay, mut nr) = (0, 0);
+
+    for line in r.lines() {
+        let line = line?;
+        let toks: Vec<&str> = line.split_whitespace().collect();
+        if toks.len() < 10 || !toks[0].starts_with("cpu") {
+            continue;
+        }
+        delay += toks[8].parse::<u64>()?;
+        nr += toks[9].parse::<u64>()?;
+    }
+
+    Ok((delay as f64 / 1_000_000_000.0, nr))
+}
+
 fn read_system_usage(devnr: (u32, u32)) -> Result<(Usage, f64)> {
     let kstat = procfs::KernelStats::new()?;
     let cpu = &kstat.total;
@@ -104,7 +145,7 @@ fn read_system_usage(devnr: (u32, u32)) -> Result<(Usage, f64)> {
         }
     }
 
-    let mem_stat_path = "/sys/fs/cgroup/memory.stat";
+    let mem_stat_path = format!("{}/memory.stat", cgrp_base());
     let mem_stat = match read_stat_file(&mem_stat_path) {
         Ok(v) => v,
         Err(e) => {
@@ -113,9 +154,27 @@ fn read_system_usage(devnr: (u32, u32)) -> Result<(Usage, f64)> {
         }
     };
 
+    // The root cgroup doesn't have memory.events. Use the system-wide OOM
+    // kill count from vmstat.
+    let mem_events = MemEventsReport {
+        oom_kill: read_cgroup_flat_keyed_file("/proc/vmstat")
+            .ok()
+            .and_then(|map| map.get("oom_kill").cloned())
+            .unwrap_or(0),
+        ..Default::default()
+    };
+
+    let (cpu_runq_delay, cpu_nr_timeslices) = match read_schedstat() {
+        Ok(v) => v,
+        Err(e) => {
+            debug!("report: Failed to read /proc/schedstat ({:?})", &e);
+            (0.0, 0)
+        }
+    };
+
     let mut io_usage = 0;
     let mut io_stat = Default::default();
-    if let Ok(mut is) = read_cgroup_nested_keyed_file("/sys/fs/cgroup/io.stat") {
+    if let Ok(mut is) = read_cgroup_nested_keyed_file(&format!("{}/io.stat", cgrp_base())) {
         if let Some(is) = is.remove(&format!("{}:{}", devnr.0, devnr.1)) {
             if let Some(val) = is.get("cost.usage") {
                 io_usage = scan_fmt!(&val, "{}", u64).unwrap_or(0);
@@ -131,6 +190,8 @@ fn read_system_usage(devnr: (u32, u32)) -> Result<(Usage, f64)> {
         Usage {
             cpu_busy,
             cpu_sys,
+            cpu_runq_delay,
+            cpu_nr_timeslices,
             mem_bytes,
             swap_bytes,
             swap_free: mstat.swap_free,
@@ -139,9 +200,11 @@ fn read_system_usage(devnr: (u32, u32)) -> Result<(Usage, f64)> {
             io_usage,
             mem_stat,
             io_stat,
+            mem_events,
             cpu_stalls: read_stalls("/proc/pressure/cpu")?,
             mem_stalls: read_stalls("/proc/pressure/memory")?,
             io_stalls: read_stalls("/proc/pressure/io")?,
+            ..Default::default()
         },
         cpu_total,
     ))
@@ -188,6 +251,21 @@ fn read_cgroup_usage(cgrp: &str, devnr: (u32, u32)) -> Usage {
         if let Some(v) = cs.get("system_usec") {
             usage.cpu_sys = *v as f64 / 1_000_000.0;
         }
+        if let Some(v) = cs.get("nr_periods") {
+            usage.cpu_nr_periods = *v;
+        }
+        if let Some(v) = cs.get("nr_throttled") {
+            usage.cpu_nr_throttled = *v;
+        }
+        if let Some(v) = cs.get("throttled_usec") {
+            usage.cpu_throttled = *v as f64 / 1_000_000.0;
+        }
+        if let Some(v) = cs.get("nr_bursts") {
+            usage.cpu_nr_bursts = *v;
+        }
+        if let Some(v) = cs.get("burst_usec") {
+            usage.cpu_burst = *v as f64 / 1_000_000.0;
+        }
     }
 
     if let Ok(line) = read_one_line(&(cgrp.to_string() + "/memory.current")) {
@@ -215,6 +293,10 @@ fn read_cgroup_usage(cgrp: &str, devnr: (u32, u32)) -> Usage {
         }
     };
 
+    if let Ok(v) = read_mem_events(&(cgrp.to_string() + "/memory.events")) {
+        usage.mem_events = v;
+    }
+
     if let Ok(mut is) = read_cgroup_nested_keyed_file(&(cgrp.to_string() + "/io.stat")) {
         if let Some(is) = is.remove(&format!("{}:{}", devnr.0, devnr.1)) {
             if let Some(val) = is.get("rbytes") {
@@ -246,8 +328,46 @@ fn read_cgroup_usage(cgrp: &str, devnr: (u32, u32)) -> Usage {
     usage
 }
 
+// Expand drill-down globs into cgroup paths relative to the cgroup base.
+// Duplicates are dropped and the result is capped at max entries.
+fn drilldown_cgrps(globs: &[String], max: usize) -> Vec<String> {
+    let base = cgrp_base();
+    let mut cgrps = vec![];
+    for pattern in globs.iter() {
+        let paths = match glob::glob(&format!("{}/{}", &base, pattern)) {
+            Ok(v) => v,
+            Err(e) => {
+                debug!(
+                    "report: Invalid drill-down pattern {:?} ({:?})",
+                    pattern, &e
+                );
+                continue;
+            }
+        };
+        for path in paths.filter_map(|x| x.ok()).filter(|x| x.is_dir()) {
+            if let Ok(rel) = path.strip_prefix(&base) {
+                let rel = rel.to_str().unwrap_or_default().to_string();
+                if rel.len() > 0 && !cgrps.contains(&rel) {
+                    cgrps.push(rel);
+                }
+            }
+        }
+    }
+    if cgrps.len() > max {
+        debug!(
+            "report: {} drill-down cgroups, only reporting the first {}",
+            cgrps.len(),
+            max
+        );
+        cgrps.truncate(max);
+    }
+    cgrps
+}
+
 pub struct UsageTracker {
     devnr: (u32, u32),
+    drilldown: Vec<String>,
+    drilldown_max: usize,
     at: Instant,
     cpu_total: f64,
     usages: HashMap<String, Usage>,
@@ -255,9 +375,11 @@ pub struct UsageTracker {
 }
 
 impl UsageTracker {
-    fn new(devnr: (u32, u32), runner: Runner) -> Self {
+    fn new(devnr: (u32, u32), drilldown: (Vec<String>, usize), runner: Runner) -> Self {
         let mut us = Self {
             devnr,
+            drilldown: drilldown.0,
+            drilldown_max: drilldown.1,
             at: Instant::now(),
             cpu_total: 0.0,
             usages: HashMap::new(),
@@ -283,7 +405,7 @@ impl UsageTracker {
         for slice in Slice::into_enum_iter() {
             usages.insert(
                 slice.name().to_string(),
-                read_cgroup_usage(slice.cgrp(), self.devnr),
+                read_cgroup_usage(&slice.cgrp(), self.devnr),
             );
         }
 
@@ -291,6 +413,11 @@ impl UsageTracker {
         for (svc, cgrp) in all_svcs.into_iter() {
             usages.insert(svc, read_cgroup_usage(&cgrp, self.devnr));
         }
+
+        for cgrp in drilldown_cgrps(&self.drilldown, self.drilldown_max).into_iter() {
+            let usage = read_cgroup_usage(&format!("{}/{}", cgrp_base(), &cgrp), self.devnr);
+            usages.insert(cgrp, usage);
+        }
         Ok((usages, cpu_total))
     }
 
@@ -319,11 +446,31 @@ impl UsageTracker {
             rep.cpu_usage = cur.cpu_busy;
             rep.cpu_usage_sys = cur.cpu_sys;
             rep.cpu_usage_base = cpu_total;
+            rep.cpu_nr_periods = cur.cpu_nr_periods;
+            rep.cpu_nr_throttled = cur.cpu_nr_throttled;
+            rep.cpu_throttled_usage = cur.cpu_throttled;
+            rep.cpu_nr_bursts = cur.cpu_nr_bursts;
+            rep.cpu_burst_usage = cur.cpu_burst;
+            rep.cpu_runq_delay = cur.cpu_runq_delay;
+            rep.cpu_nr_timeslices = cur.cpu_nr_timeslices;
+
+            if cur.cpu_nr_periods > last.cpu_nr_periods {
+                rep.cpu_throttled = (cur.cpu_nr_throttled.saturating_sub(last.cpu_nr_throttled)
+                    as f64
+                    / (cur.cpu_nr_periods - last.cpu_nr_periods) as f64)
+                    .min(1.0);
+            }
+            if cur.cpu_nr_timeslices > last.cpu_nr_timeslices {
+                rep.cpu_runq_lat = ((cur.cpu_runq_delay - last.cpu_runq_delay)
+                    / (cur.cpu_nr_timeslices - last.cpu_nr_timeslices) as f64)
+                    .max(0.0);
+            }
             rep.mem_bytes = cur.mem_bytes;
             rep.swap_bytes = cur.swap_bytes;
             rep.swap_free = cur.swap_free;
             rep.io_rbytes = cur.io_rbytes;
             rep.io_wbytes = cur.io_wbytes;
+            rep.mem_events = cur.mem_events.clone();
 
             if dur > 0.0 {
                 if cur.io_rbytes >= last.io_rbytes {
@@ -387,6 +534,8 @@ struct ReportFile {
     vmstat_acc: StatMap,
     iolat_acc: IoLatReport,
     iocost_acc: IoCostReport,
+    iolat_devs_acc: BTreeMap<String, IoLatReport>,
+    iocost_devs_acc: BTreeMap<String, IoCostReport>,
     nr_samples: u32,
 }
 
@@ -429,6 +578,7 @@ impl ReportFile {
         path: &str,
         d_path: &str,
         devnr: (u32, u32),
+        drilldown: (Vec<String>, usize),
         runner: Runner,
     ) -> ReportFile {
         let now = unix_now();
@@ -439,13 +589,15 @@ impl ReportFile {
             path: path.into(),
             d_path: d_path.into(),
             next_at: ((now / intv) + 1) * intv,
-            usage_tracker: UsageTracker::new(devnr, runner),
+            usage_tracker: UsageTracker::new(devnr, drilldown, runner),
             hashd_acc: Default::default(),
             mem_stat_acc: Default::default(),
             io_stat_acc: Default::default(),
             vmstat_acc: Default::default(),
             iolat_acc: Default::default(),
             iocost_acc: Default::default(),
+            iolat_devs_acc: Default::default(),
+            iocost_devs_acc: Default::default(),
             nr_samples: 0,
         };
 
@@ -498,6 +650,15 @@ impl ReportFile {
         Self::acc_stat_map(&mut self.vmstat_acc, &base_report.vmstat);
         self.iolat_acc.accumulate(&base_report.iolat);
         self.iocost_acc += &base_report.iocost;
+        for (dev, iolat) in base_report.iolat_devs.iter() {
+            self.iolat_devs_acc
+                .entry(dev.clone())
+                .or_default()
+                .accumulate(iolat);
+        }
+        for (dev, iocost) in base_report.iocost_devs.iter() {
+            *self.iocost_devs_acc.entry(dev.clone()).or_default() += iocost;
+        }
         self.nr_samples += 1;
 
         if now < self.next_at {
@@ -516,6 +677,12 @@ impl ReportFile {
 
         for i in 0..2 {
             self.hashd_acc[i] /= self.nr_samples;
+            // Averaged percentiles aren't percentiles. Use the merged
+            // histogram if available.
+            let acc = &mut self.hashd_acc[i];
+            if !acc.lat_hist.is_empty() {
+                acc.lat = acc.lat_hist.latencies(acc.lat_pct);
+            }
             report.hashd[i] = HashdReport {
                 svc: report.hashd[i].svc.clone(),
                 phase: report.hashd[i].phase,
@@ -543,6 +710,12 @@ impl ReportFile {
         report.iocost = self.iocost_acc.clone();
         self.iocost_acc = Default::default();
 
+        for (_, iocost) in self.iocost_devs_acc.iter_mut() {
+            *iocost /= self.nr_samples;
+        }
+        report.iolat_devs = std::mem::take(&mut self.iolat_devs_acc);
+        report.iocost_devs = std::mem::take(&mut self.iocost_devs_acc);
+
         self.nr_samples = 0;
 
         report.usages = match self.usage_tracker.update() {
@@ -661,10 +834,10 @@ impl IoLatReader {
         Ok(())
     }
 
-    fn new(cfg: &Config, name: &str, intv: &str) -> Result<Self> {
+    fn new(cfg: &Config, devnr: (u32, u32), name: &str, intv: &str) -> Result<Self> {
         let mut iolat = Self {
             biolatpcts_bin: cfg.biolatpcts_bin.as_ref().map(|x| x.to_owned()),
-            devnr: cfg.scr_devnr,
+            devnr,
             name: name.to_owned(),
             intv: intv.to_owned(),
             tx: None,
@@ -710,7 +883,9 @@ struct ReportWorker {
     report_file_1min: ReportFile,
     iolat: IoLatReport,
     iolat_cum: IoLatReport,
+    iolat_devs: BTreeMap<String, IoLatReport>,
     iocost_devnr: (u32, u32),
+    iocost_extra_devs: Vec<(String, (u32, u32))>,
 }
 
 impl ReportWorker {
@@ -720,6 +895,12 @@ impl ReportWorker {
         // and unlock it.
         let cfg = &rdata.cfg;
         let scr_devnr = cfg.scr_devnr;
+        let iocost_extra_devs: Vec<(String, (u32, u32))> = cfg
+            .iocost_devs
+            .iter()
+            .filter(|(_, devnr)| *devnr != scr_devnr)
+            .cloned()
+            .collect();
         let (rep_ret, rep_path, rep_d_path) = (
             cfg.rep_retention,
             cfg.report_path.clone(),
@@ -730,6 +911,7 @@ impl ReportWorker {
             cfg.report_1min_path.clone(),
             cfg.report_1min_d_path.clone(),
         );
+        let drilldown = (cfg.drilldown.clone(), cfg.drilldown_max);
         drop(rdata);
 
         Ok(Self {
@@ -740,6 +922,7 @@ impl ReportWorker {
                 &rep_path,
                 &rep_d_path,
                 scr_devnr,
+                drilldown.clone(),
                 runner.clone(),
             ),
             report_file_1min: ReportFile::new(
@@ -748,12 +931,15 @@ impl ReportWorker {
                 &rep_1min_path,
                 &rep_1min_d_path,
                 scr_devnr,
+                drilldown,
                 runner.clone(),
             ),
 
             iolat: Default::default(),
             iolat_cum: Default::default(),
+            iolat_devs: Default::default(),
             iocost_devnr: scr_devnr,
+            iocost_extra_devs,
             runner,
         })
     }
@@ -773,6 +959,11 @@ impl ReportWorker {
             ),
             None => (Default::default(), Default::default()),
         };
+        let mut iocost_devs = BTreeMap::new();
+        for (dev, devnr) in self.iocost_extra_devs.iter() {
+            iocost_devs.insert(dev.clone(), IoCostReport::read(*devnr)?);
+        }
+
         let bench_iocost = match runner.bench_iocost.as_mut() {
             Some(svc) => super::svc_refresh_and_report(&mut svc.unit)?,
             None => Default::default(),
@@ -790,6 +981,8 @@ impl ReportWorker {
             timestamp: DateTime::from(now),
             seq: super::instance_seq(),
             state: runner.state,
+            profile: runner.sobjs.profile.clone(),
+            profile_seq: runner.sobjs.profile_seq,
             resctl,
             oomd: runner.sobjs.oomd.report()?,
             sideloader: runner.sobjs.sideloader.report()?,
@@ -806,8 +999,11 @@ impl ReportWorker {
             iolat: self.iolat.clone(),
             iolat_cum: self.iolat_cum.clone(),
             iocost: IoCostReport::read(self.iocost_devnr)?,
+            iocost_devs,
+            iolat_devs: self.iolat_devs.clone(),
             swappiness: read_swappiness()?,
             zswap_enabled: read_zswap_enabled()?,
+            swap_devs: super::swap::swap_devs()?,
             ..Default::default()
         })
     }
@@ -856,8 +1052,22 @@ impl ReportWorker {
         let runner = self.runner.data.lock().unwrap();
         let cfg = &runner.cfg;
 
-        let mut iolat = IoLatReader::new(cfg, "iolat", "1").unwrap();
-        let mut iolat_cum = IoLatReader::new(cfg, "iolat_cum", "-1").unwrap();
+        let mut iolat = IoLatReader::new(cfg, cfg.scr_devnr, "iolat", "1").unwrap();
+        let mut iolat_cum = IoLatReader::new(cfg, cfg.scr_devnr, "iolat_cum", "-1").unwrap();
+
+        // Additional devices only get the 1s instances which are polled
+        // below rather than waited on.
+        let mut iolat_devs: Vec<(String, IoLatReader, u32)> = self
+            .iocost_extra_devs
+            .iter()
+            .map(|(dev, devnr)| {
+                (
+                    dev.clone(),
+                    IoLatReader::new(cfg, *devnr, &format!("iolat-{}", dev), "1").unwrap(),
+                    crate::misc::BCC_RETRIES,
+                )
+            })
+            .collect();
 
         drop(runner);
         let mut sleep_dur = Duration::from_secs(0);
@@ -915,6 +1125,26 @@ impl ReportWorker {
                 recv(channel::after(sleep_dur)) -> _ => (),
             }
 
+            for (dev, reader, retries) in iolat_devs.iter_mut() {
+                loop {
+                    match reader.rx.as_ref().unwrap().try_recv() {
+                        Ok(line) => match Self::parse_iolat_output(&line) {
+                            Ok(v) => {
+                                self.iolat_devs.insert(dev.clone(), v);
+                            }
+                            Err(e) => {
+                                warn!("report: failed to parse {} iolat output ({:?})", dev, &e)
+                            }
+                        },
+                        Err(channel::TryRecvError::Empty) => break,
+                        Err(channel::TryRecvError::Disconnected) => {
+                            Self::maybe_retry_iolat(retries, reader, &channel::RecvError);
+                            break;
+                        }
+                    }
+                }
+            }
+
             let sleep_till = UNIX_EPOCH + Duration::from_secs(next_at) + Duration::from_millis(500);
             match sleep_till.duration_since(SystemTime::now()) {
                 Ok(v) => {
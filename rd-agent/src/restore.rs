@@ -0,0 +1,292 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// System settings which rd-agent changes are recorded in the restore
+// journal before being modified so that they can be restored even if
+// rd-agent doesn't get to exit cleanly. The journal is replayed and removed
+// on clean exit, on the next startup or with --restore.
+use anyhow::Result;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use rd_agent_intf::EnforceConfig;
+use rd_util::*;
+
+use super::slices;
+
+const RESTORE_DOC: &str = "\
+//
+// rd-agent restore journal
+//
+// Original values of system settings modified by rd-agent. Replayed and
+// removed on exit, on the next startup or with --restore.
+//
+";
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct RestoreJournal {
+    pub iosched: Vec<(String, String)>,
+    pub wbt: Vec<(String, u64)>,
+    pub swappiness: Option<u32>,
+    pub zswap_enabled: Option<bool>,
+    pub oomd_sys_svc: Option<String>,
+    pub iocost: Vec<IoCostSysParams>,
+    pub slices: Option<EnforceConfig>,
+
+    #[serde(skip)]
+    path: String,
+    #[serde(skip)]
+    committed: bool,
+}
+
+impl JsonLoad for RestoreJournal {}
+
+impl JsonSave for RestoreJournal {
+    fn preamble() -> Option<String> {
+        Some(RESTORE_DOC.to_string())
+    }
+}
+
+impl RestoreJournal {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Write out the journal through a staging file. Both the file and the
+    /// directory are synced so that the journal survives a crash.
+    fn write_synced(&self) -> Result<()> {
+        let path = Path::new(&self.path);
+        let staging = format!("{}.staging", &self.path);
+
+        let mut f = fs::File::create(&staging)?;
+        f.write_all(self.as_json()?.as_bytes())?;
+        f.sync_all()?;
+        drop(f);
+
+        fs::rename(&staging, path)?;
+        if let Some(parent) = path.parent() {
+            fs::File::open(parent)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) {
+        if super::plan::dry_run() {
+            return;
+        }
+        match self.write_synced() {
+            Ok(()) => self.committed = true,
+            Err(e) => error!("cfg: Failed to update {:?} ({:#})", &self.path, &e),
+        }
+    }
+
+    pub fn record_iosched(&mut self, dev: &str, iosched: &str) {
+        if self.iosched.iter().all(|(d, _)| d != dev) {
+            self.iosched.push((dev.to_string(), iosched.to_string()));
+            self.commit();
+        }
+    }
+
+    pub fn record_wbt(&mut self, path: &str, wbt: u64) {
+        if self.wbt.iter().all(|(p, _)| p != path) {
+            self.wbt.push((path.to_string(), wbt));
+            self.commit();
+        }
+    }
+
+    pub fn record_swappiness(&mut self, swappiness: u32) {
+        if self.swappiness.is_none() {
+            self.swappiness = Some(swappiness);
+            self.commit();
+        }
+    }
+
+    pub fn record_zswap_enabled(&mut self, enabled: bool) {
+        if self.zswap_enabled.is_none() {
+            self.zswap_enabled = Some(enabled);
+            self.commit();
+        }
+    }
+
+    pub fn record_oomd_sys_svc(&mut self, svc_name: &str) {
+        if self.oomd_sys_svc.is_none() {
+            self.oomd_sys_svc = Some(svc_name.to_string());
+            self.commit();
+        }
+    }
+
+    /// Save the current io.cost.model,qos of `devnr`.
+    pub fn record_iocost(&mut self, devnr: (u32, u32)) -> Result<()> {
+        if self.iocost.iter().any(|params| params.devnr == devnr) {
+            return Ok(());
+        }
+        self.iocost.push(IoCostSysParams::read_from_sys(devnr)?);
+        self.commit();
+        Ok(())
+    }
+
+    pub fn record_slices(&mut self, ecfg: &EnforceConfig) {
+        if self.slices.is_none() {
+            self.slices = Some(ecfg.clone());
+            self.commit();
+        }
+    }
+
+    /// Restore all recorded settings and remove the journal file.
+    pub fn replay(&mut self) {
+        if let Some(ecfg) = self.slices.take() {
+            info!("cfg: Clearing slice configurations");
+            if let Err(e) = slices::clear_slices(&ecfg) {
+                warn!("cfg: Failed to clear slice configurations ({:#})", &e);
+            }
+        }
+        for params in self.iocost.drain(..) {
+            let (maj, min) = params.devnr;
+            info!("cfg: Restoring io.cost.model,qos of {}:{}", maj, min);
+            if let Err(e) = params.write_to_sys() {
+                error!(
+                    "cfg: Failed to restore io.cost.model,qos of {}:{} ({:#})",
+                    maj, min, &e
+                );
+            }
+        }
+        for (dev, iosched) in self.iosched.drain(..) {
+            if let Err(e) = super::set_iosched(&dev, &iosched) {
+                error!(
+                    "cfg: Failed to restore iosched of {:?} to {:?} ({:#})",
+                    &dev, &iosched, &e
+                );
+            }
+        }
+        for (path, wbt) in self.wbt.drain(..) {
+            info!("cfg: Restoring {:?} to {}", &path, wbt);
+            if let Err(e) = write_one_line(&path, &format!("{}", wbt)) {
+                error!("cfg: Failed to restore {:?} ({:#})", &path, &e);
+            }
+        }
+        if let Some(swappiness) = self.swappiness.take() {
+            info!("cfg: Restoring swappiness to {}", swappiness);
+            if let Err(e) = write_one_line(SWAPPINESS_PATH, &format!("{}", swappiness)) {
+                error!("cfg: Failed to restore swappiness ({:#})", &e);
+            }
+        }
+        if let Some(enabled) = self.zswap_enabled.take() {
+            info!("cfg: Restoring zswap enabled to {}", enabled);
+            if let Err(e) = write_one_line(
+                ZSWAP_ENABLED_PATH,
+                &format!("{}", if enabled { "Y" } else { "N" }),
+            ) {
+                error!("cfg: Failed to restore zswap enabled ({:#})", &e);
+            }
+        }
+        if let Some(name) = self.oomd_sys_svc.take() {
+            info!("cfg: Restoring {:?}", &name);
+            if let Err(e) = systemd::Unit::new_sys(name.clone()).and_then(|mut svc| svc.try_start())
+            {
+                error!("cfg: Failed to restore {:?} ({:#})", &name, &e);
+            }
+        }
+
+        if self.committed {
+            if let Err(e) = fs::remove_file(&self.path) {
+                warn!("cfg: Failed to remove {:?} ({:#})", &self.path, &e);
+            }
+            self.committed = false;
+        }
+    }
+
+    /// Replay the journal left behind by a previous instance, if any. A
+    /// journal which can't be loaded is moved aside so that it doesn't
+    /// block startup.
+    pub fn replay_file(path: &str) -> Result<()> {
+        if !Path::new(path).exists() {
+            return Ok(());
+        }
+        super::plan::act(&format!("Replay {:?}", path), || {
+            warn!("cfg: Found {:?}, restoring system settings", path);
+            let mut journal = match Self::load(path) {
+                Ok(v) => v,
+                Err(e) => {
+                    let aside = format!("{}.corrupt", path);
+                    error!(
+                        "cfg: Failed to load {:?}, moving it to {:?} ({:#})",
+                        path, &aside, &e
+                    );
+                    if let Err(e) = fs::rename(path, &aside) {
+                        warn!("cfg: Failed to move {:?} ({:#})", path, &e);
+                    }
+                    return Ok(());
+                }
+            };
+            journal.path = path.to_string();
+            journal.committed = true;
+            journal.replay();
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RestoreJournal;
+    use rd_agent_intf::EnforceConfig;
+    use rd_util::*;
+
+    #[test]
+    fn test_journal_roundtrip() {
+        let path =
+            std::env::temp_dir().join(format!("rd-agent-restore-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut journal = RestoreJournal::new(path);
+        journal.record_iosched("sda", "mq-deadline");
+        journal.record_iosched("sda", "none");
+        journal.record_wbt("/sys/block/sda/queue/wbt_lat_usec", 2000);
+        journal.record_swappiness(60);
+        journal.record_zswap_enabled(true);
+        journal.record_oomd_sys_svc("systemd-oomd.service");
+        journal.record_slices(&EnforceConfig {
+            mem: false,
+            ..Default::default()
+        });
+        assert!(journal.committed);
+
+        let loaded = RestoreJournal::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            loaded.iosched,
+            vec![("sda".to_string(), "mq-deadline".to_string())]
+        );
+        assert_eq!(
+            loaded.wbt,
+            vec![("/sys/block/sda/queue/wbt_lat_usec".to_string(), 2000)]
+        );
+        assert_eq!(loaded.swappiness, Some(60));
+        assert_eq!(loaded.zswap_enabled, Some(true));
+        assert_eq!(loaded.oomd_sys_svc.as_deref(), Some("systemd-oomd.service"));
+        let slices = loaded.slices.unwrap();
+        assert!(!slices.mem && slices.cpu && slices.io);
+        assert!(loaded.iocost.is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_journal_moved_aside() {
+        let path =
+            std::env::temp_dir().join(format!("rd-agent-corrupt-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let aside = format!("{}.corrupt", path);
+
+        std::fs::write(path, "{ not json").unwrap();
+        assert!(RestoreJournal::replay_file(path).is_ok());
+        assert!(!std::path::Path::new(path).exists());
+        assert!(std::path::Path::new(&aside).exists());
+        std::fs::remove_file(&aside).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// Native senpai. Every interval, memory pressure of the slice, the ratio of
+// stalled time to elapsed time, is compared against the stall threshold. If
+// lower, the slice is squeezed by up to max_probe; otherwise, the squeeze is
+// backed off by up to max_backoff. The squeeze is applied either through
+// memory.high or memory.reclaim.
+use anyhow::{anyhow, Result};
+use crossbeam::channel::{self, select, Receiver, Sender};
+use log::{debug, warn};
+use std::sync::{Arc, Mutex};
+use std::thread::{spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rd_agent_intf::{OomdSliceSenpaiKnobs, SenpaiMode, SenpaiReport, Slice};
+use rd_util::*;
+
+use super::report::read_stalls;
+
+// Returns the adjustment ratio for the stall ratio `pressure`, negative when
+// probing.
+fn calc_adj(knobs: &OomdSliceSenpaiKnobs, pressure: f64) -> f64 {
+    let thr = knobs.stall_threshold.max(0.001);
+    let error = (thr - pressure) / thr;
+    if error > 0.0 {
+        -(error / knobs.coeff_probe).min(knobs.max_probe)
+    } else {
+        (-error / knobs.coeff_backoff).min(knobs.max_backoff)
+    }
+}
+
+struct SenpaiWorker {
+    cgrp: String,
+    knobs: OomdSliceSenpaiKnobs,
+    min_bytes: u64,
+    max_bytes: u64,
+    limit: u64,
+    last_total: Option<(f64, Instant)>,
+    report: Arc<Mutex<SenpaiReport>>,
+    term_rx: Receiver<()>,
+}
+
+impl SenpaiWorker {
+    fn read_current(&self) -> Result<u64> {
+        let path = format!("{}/memory.current", &self.cgrp);
+        Ok(read_one_line(&path)?.trim().parse::<u64>()?)
+    }
+
+    fn step(&mut self) -> Result<()> {
+        let current = self.read_current()?;
+        let total = read_stalls(&format!("{}/memory.pressure", &self.cgrp))?.0;
+        let now = Instant::now();
+        let pressure = match self.last_total.replace((total, now)) {
+            Some((last, at)) => {
+                let elapsed = now.duration_since(at).as_secs_f64();
+                if elapsed <= 0.0 {
+                    return Ok(());
+                }
+                (total - last) / elapsed
+            }
+            None => return Ok(()),
+        };
+
+        let adj = calc_adj(&self.knobs, pressure);
+        let base = self.limit.min(current) as f64;
+        let target = ((base * (1.0 + adj)).round() as u64)
+            .max(self.min_bytes)
+            .min(self.max_bytes);
+
+        match self.knobs.mode {
+            SenpaiMode::MemHigh => {
+                write_one_line(
+                    &format!("{}/memory.high", &self.cgrp),
+                    &format!("{}", target),
+                )?;
+            }
+            SenpaiMode::MemReclaim => {
+                if target < current {
+                    // memory.reclaim fails with EAGAIN if it can't reclaim
+                    // the full amount which isn't an error for us.
+                    let _ = write_one_line(
+                        &format!("{}/memory.reclaim", &self.cgrp),
+                        &format!("{}", current - target),
+                    );
+                }
+            }
+            SenpaiMode::Oomd => return Err(anyhow!("oomd mode isn't native")),
+        }
+        self.limit = target;
+
+        debug!(
+            "senpai: {} current={:.2}G pressure={:.3} adj={:+.4} target={:.2}G",
+            &self.cgrp,
+            to_gb(current),
+            pressure,
+            adj,
+            to_gb(target)
+        );
+
+        let mut rep = self.report.lock().unwrap();
+        rep.target = target;
+        rep.current = current;
+        rep.pressure = pressure;
+        rep.adj = adj;
+        if adj < 0.0 {
+            rep.nr_probes += 1;
+        } else {
+            rep.nr_backoffs += 1;
+        }
+        Ok(())
+    }
+
+    fn run(mut self) {
+        let intv = Duration::from_secs(self.knobs.interval.max(1) as u64);
+        loop {
+            select! {
+                recv(self.term_rx) -> term => {
+                    if let Err(e) = term {
+                        debug!("senpai: {} term ({})", &self.cgrp, &e);
+                        break;
+                    }
+                },
+                default(intv) => {
+                    if let Err(e) = self.step() {
+                        warn!("senpai: Failed to update {} ({:#})", &self.cgrp, &e);
+                    }
+                },
+            }
+        }
+    }
+}
+
+pub struct Senpai {
+    pub report: Arc<Mutex<SenpaiReport>>,
+    term_tx: Option<Sender<()>>,
+    jh: Option<JoinHandle<()>>,
+}
+
+impl Senpai {
+    pub fn new(slice: Slice, knobs: &OomdSliceSenpaiKnobs, mem_size: u64) -> Self {
+        let report = Arc::new(Mutex::new(SenpaiReport {
+            mode: knobs.mode,
+            ..Default::default()
+        }));
+        let (term_tx, term_rx) = channel::unbounded::<()>();
+        let worker = SenpaiWorker {
+            cgrp: slice.cgrp().to_string(),
+            knobs: knobs.clone(),
+            min_bytes: (knobs.min_bytes_frac * mem_size as f64).round() as u64,
+            max_bytes: (knobs.max_bytes_frac * mem_size as f64).round() as u64,
+            limit: std::u64::MAX,
+            last_total: None,
+            report: report.clone(),
+            term_rx,
+        };
+        let jh = spawn(move || worker.run());
+
+        Self {
+            report,
+            term_tx: Some(term_tx),
+            jh: Some(jh),
+        }
+    }
+}
+
+impl Drop for Senpai {
+    fn drop(&mut self) {
+        drop(self.term_tx.take().unwrap());
+        let _ = self.jh.take().unwrap().join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_agent_intf::OomdSliceSenpaiKnobs;
+
+    #[test]
+    fn test_calc_adj() {
+        let knobs = OomdSliceSenpaiKnobs {
+            stall_threshold: 0.1,
+            max_probe: 0.01,
+            max_backoff: 0.5,
+            coeff_probe: 10.0,
+            coeff_backoff: 20.0,
+            ..Default::default()
+        };
+        let adj = |pressure| super::calc_adj(&knobs, pressure);
+        let near = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        // Probing: proportional below the clamp, clamped at max_probe.
+        assert!(near(adj(0.099), -0.001));
+        assert!(near(adj(0.0), -0.01));
+        // At the threshold, no adjustment.
+        assert!(near(adj(0.1), 0.0));
+        // Backing off: proportional below the clamp, clamped at max_backoff.
+        assert!(near(adj(0.3), 0.1));
+        assert!(near(adj(100.0), 0.5));
+    }
+}
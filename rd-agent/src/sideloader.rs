@@ -85,6 +85,8 @@ impl Sideloader {
                 SIDELOAD_SVC_PREFIX.into(),
                 "--dev".into(),
                 cfg.scr_dev.clone(),
+                "--cgroup-fs".into(),
+                rd_agent_intf::cgrp_base(),
                 "--dont-fix".into(),
             ],
             vec![],
@@ -113,13 +115,7 @@ impl Sideloader {
             }
         }
 
-        let mut f = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.daemon_cfg_path)?;
-        f.write_all(cfg.as_ref())?;
-        Ok(())
+        super::plan::write_file(&self.daemon_cfg_path, &cfg)
     }
 
     pub fn apply(&mut self, cmd: &SideloaderCmd, slice_knobs: &SliceKnobs) -> Result<()> {
@@ -127,7 +123,10 @@ impl Sideloader {
         trace!("sideloader state {:?}", self.svc.unit.state);
         match self.svc.unit.state {
             systemd::UnitState::Running => Ok(()),
-            _ => self.svc.start(),
+            _ => {
+                let svc = &mut self.svc;
+                super::plan::act(&format!("Start {:?}", &svc.unit.name), || svc.start())
+            }
         }
     }
 
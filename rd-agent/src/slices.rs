@@ -10,10 +10,10 @@ use std::fs;
 use std::io::prelude::*;
 use std::path::Path;
 
-use super::Config;
+use super::{plan, Config};
 use rd_agent_intf::{
-    DisableSeqKnobs, EnforceConfig, MemoryKnob, MissedSysReqs, Slice, SliceConfig, SliceKnobs,
-    SysReq,
+    cgrp_base, DisableSeqKnobs, EnforceConfig, MemoryKnob, MissedSysReqs, Slice, SliceConfig,
+    SliceKnobs, SysReq,
 };
 use rd_util::systemd::UnitState as US;
 use rd_util::*;
@@ -22,10 +22,11 @@ pub fn check_other_io_controllers(sr_failed: &mut MissedSysReqs) {
     let mut failed = None;
     let mut nr_fails = 0;
 
-    for path in glob("/sys/fs/cgroup/**/io.latency")
+    let base = cgrp_base();
+    for path in glob(&format!("{}/**/io.latency", &base))
         .unwrap()
-        .chain(glob("/sys/fs/cgroup/**/io.max").unwrap())
-        .chain(glob("/sys/fs/cgroup/**/io.low").unwrap())
+        .chain(glob(&format!("{}/**/io.max", &base)).unwrap())
+        .chain(glob(&format!("{}/**/io.low", &base)).unwrap())
         .filter_map(Result::ok)
     {
         match read_one_line(&path) {
@@ -157,7 +158,8 @@ fn apply_configlet(slice: Slice, configlet: &str) -> Result<bool> {
     if slice_needs_start_stop(slice) {
         match systemd::Unit::new_sys(slice.name().into()) {
             Ok(mut unit) => {
-                if let Err(e) = unit.try_start_nowait() {
+                let desc = format!("Start {:?}", slice.name());
+                if let Err(e) = plan::act(&desc, || unit.try_start_nowait()) {
                     warn!("resctl: Failed to start {:?} ({})", slice.name(), &e);
                 }
             }
@@ -228,7 +230,9 @@ fn propagate_one_slice(slice: Slice, resctl: &systemd::UnitResCtl) -> Result<()>
         }
 
         unit.resctl = resctl.clone();
-        match unit.apply() {
+        match plan::act(&format!("Apply {:?} to {:?}", resctl, &unit_name), || {
+            unit.apply()
+        }) {
             Ok(()) => debug!("resctl: propagated resctl config to {:?}", &unit_name),
             Err(e) => warn!(
                 "resctl: Failed to propagate config to {:?} ({:?})",
@@ -298,7 +302,7 @@ pub fn apply_slices(knobs: &mut SliceKnobs, hashd_mem_size: u64, cfg: &Config) -
     }
     if updated {
         info!("resctl: Applying updated slice configurations");
-        systemd::daemon_reload()?;
+        plan::act("Reload systemd", || systemd::daemon_reload())?;
     }
 
     let enable_iocost = knobs.disable_seqs.io < super::instance_seq();
@@ -401,39 +405,50 @@ fn fix_overrides(dseqs: &DisableSeqKnobs, cfg: &Config) -> Result<()> {
     }
 
     if disable.len() > 0 {
-        let mut scs: Vec<String> = glob("/sys/fs/cgroup/**/cgroup.subtree_control")
-            .unwrap()
-            .filter_map(|x| x.ok())
-            .map(|x| x.to_str().unwrap().to_string())
-            .collect();
-        scs.sort_unstable_by_key(|x| -(x.len() as i64));
-
-        let mut nr_failed = 0;
-        for sc in &scs {
-            if let Err(e) = write_one_line(sc, &disable) {
-                if nr_failed == 0 {
-                    warn!(
-                        "resctl: Failed to write {:?} to {:?} ({:?})",
-                        &disable, &sc, &e
-                    );
+        let desc = format!("Write {:?} to all cgroup.subtree_control files", &disable);
+        plan::act(&desc, || {
+            let mut scs: Vec<String> = glob(&format!("{}/**/cgroup.subtree_control", cgrp_base()))
+                .unwrap()
+                .filter_map(|x| x.ok())
+                .map(|x| x.to_str().unwrap().to_string())
+                .collect();
+            scs.sort_unstable_by_key(|x| -(x.len() as i64));
+
+            let mut nr_failed = 0;
+            for sc in &scs {
+                if let Err(e) = write_one_line(sc, &disable) {
+                    if nr_failed == 0 {
+                        warn!(
+                            "resctl: Failed to write {:?} to {:?} ({:?})",
+                            &disable, &sc, &e
+                        );
+                    }
+                    nr_failed += 1;
                 }
-                nr_failed += 1;
             }
-        }
 
-        if nr_failed > 1 {
-            warn!(
-                "resctl: Failed to write {:?} to {} files",
-                &disable, nr_failed
-            );
-        }
+            if nr_failed > 1 {
+                warn!(
+                    "resctl: Failed to write {:?} to {} files",
+                    &disable, nr_failed
+                );
+            }
+            Ok(())
+        })?;
     }
 
-    if enable.len() > 0 {
-        write_one_line("/sys/fs/cgroup/cgroup.subtree_control", &enable).context(format!(
-            "Writing {:?} to /sys/fs/cgroup/cgroup.subtree_control",
-            &enable
-        ))?;
+    let sc_path = format!("{}/cgroup.subtree_control", cgrp_base());
+    let cur = read_one_line(&sc_path).unwrap_or_default();
+    let cur: Vec<&str> = cur.split_whitespace().collect();
+    if enable
+        .split_whitespace()
+        .any(|x| !cur.contains(&x.trim_start_matches('+')))
+    {
+        let desc = format!("Write {:?} to {:?}", &enable, &sc_path);
+        plan::act(&desc, || {
+            write_one_line(&sc_path, &enable)
+                .context(format!("Writing {:?} to {:?}", &enable, &sc_path))
+        })?;
     }
 
     Ok(())
@@ -453,7 +468,7 @@ fn fix_slice_cpu(sk: &SliceConfig, path: &str, enable: bool) -> Result<()> {
                 "resctl: {:?} should be {} but is {:?}, fixing",
                 &cpu_weight_path, sk.cpu_weight, &v
             );
-            write_one_line(&cpu_weight_path, &format!("{}", sk.cpu_weight))?;
+            plan::write_knob(&cpu_weight_path, &format!("{}", sk.cpu_weight))?;
         }
     }
     Ok(())
@@ -473,7 +488,7 @@ fn fix_slice_io(sk: &SliceConfig, path: &str, enable: bool) -> Result<()> {
                 "resctl: {:?} should be {} but is {:?}, fixing",
                 &io_weight_path, sk.io_weight, &v
             );
-            write_one_line(&io_weight_path, &format!("default {}", sk.io_weight))?;
+            plan::write_knob(&io_weight_path, &format!("default {}", sk.io_weight))?;
         }
     }
     Ok(())
@@ -501,7 +516,7 @@ fn fix_cgrp_mem(path: &str, is_limit: bool, knob: MemoryKnob) -> Result<()> {
         "resctl: {:?} should be {:?} but is {:?}, fixing",
         path, &expected, &line
     );
-    write_one_line(path, &expected)?;
+    plan::write_knob(path, &expected)?;
 
     let file = Path::new(path)
         .file_name()
@@ -585,7 +600,7 @@ pub fn verify_and_fix_slices(
 ) -> Result<()> {
     let seq = super::instance_seq();
     let dseqs = &knobs.disable_seqs;
-    let line = read_one_line("/sys/fs/cgroup/cgroup.subtree_control")?;
+    let line = read_one_line(&format!("{}/cgroup.subtree_control", cgrp_base()))?;
 
     if (cfg.enforce.cpu && ((dseqs.cpu < seq) != line.contains("cpu")))
         || (cfg.enforce.io && !line.contains("io"))
@@ -600,7 +615,7 @@ pub fn verify_and_fix_slices(
     for slice in Slice::into_enum_iter() {
         let sk = knobs.slices.get(slice.name()).unwrap();
 
-        let path = slice.cgrp();
+        let path = &slice.cgrp();
         if !AsRef::<Path>::as_ref(path).exists() {
             continue;
         }
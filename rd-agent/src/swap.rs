@@ -0,0 +1,102 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// Swap device discovery. Swap can be on partitions and files on any device
+// and on zram, and multiple of them can be stacked as tiers by priority.
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use proc_mounts::SwapIter;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+use rd_agent_intf::{SwapDevReport, SwapKind, ZramReport};
+use rd_util::*;
+
+lazy_static::lazy_static! {
+    // zram devices which failed read_zram(), to warn only once per device.
+    static ref ZRAM_WARNED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+}
+
+fn read_zram(devname: &str) -> Result<ZramReport> {
+    let dir = format!("/sys/block/{}", devname);
+
+    // The selected algorithm is bracketed, e.g. "lzo lzo-rle [lz4] zstd".
+    let algs = read_one_line(&format!("{}/comp_algorithm", &dir))?;
+    let comp_algorithm = algs
+        .split_whitespace()
+        .find(|alg| alg.starts_with('['))
+        .map(|alg| alg.trim_matches(|c| c == '[' || c == ']'))
+        .ok_or_else(|| anyhow!("failed to parse comp_algorithm {:?}", algs.trim()))?
+        .to_string();
+
+    let disksize = read_one_line(&format!("{}/disksize", &dir))?
+        .trim()
+        .parse::<u64>()?;
+
+    // orig_data_size compr_data_size mem_used_total mem_limit ...
+    let mm_stat = read_one_line(&format!("{}/mm_stat", &dir))?
+        .split_whitespace()
+        .map(|v| v.parse::<u64>())
+        .collect::<std::result::Result<Vec<u64>, _>>()?;
+    if mm_stat.len() < 4 {
+        return Err(anyhow!("too few fields in {}/mm_stat", &dir));
+    }
+
+    Ok(ZramReport {
+        comp_algorithm,
+        disksize,
+        mem_limit: mm_stat[3],
+        orig_data_size: mm_stat[0],
+        compr_data_size: mm_stat[1],
+        mem_used_total: mm_stat[2],
+    })
+}
+
+/// List all swap devices, highest priority tier first.
+pub fn swap_devs() -> Result<Vec<SwapDevReport>> {
+    let mut devs = vec![];
+    for swap in SwapIter::new()? {
+        let swap = swap?;
+        let is_part = swap.kind == "partition";
+        let dev = match swap_devname(&swap.source, is_part) {
+            Ok(v) => v.to_str().unwrap_or_default().to_string(),
+            Err(e) => {
+                debug!(
+                    "swap: Failed to find backing dev of {:?} ({:#})",
+                    &swap.source, &e
+                );
+                "".into()
+            }
+        };
+
+        let (kind, zram) = if is_part && dev.starts_with("zram") {
+            // zram sysfs layout varies across kernel versions. Don't let
+            // that fail swap discovery.
+            let zram = match read_zram(&dev) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    if ZRAM_WARNED.lock().unwrap().insert(dev.clone()) {
+                        warn!("swap: Failed to read zram stats of {:?} ({:#})", &dev, &e);
+                    }
+                    None
+                }
+            };
+            (SwapKind::Zram, zram)
+        } else if is_part {
+            (SwapKind::Partition, None)
+        } else {
+            (SwapKind::File, None)
+        };
+
+        devs.push(SwapDevReport {
+            path: swap.source.to_str().unwrap_or_default().to_string(),
+            kind,
+            dev,
+            priority: swap.priority as i64,
+            size: swap.size as u64 * 1024,
+            used: swap.used as u64 * 1024,
+            zram,
+        });
+    }
+    devs.sort_by(|a, b| b.priority.cmp(&a.priority));
+    Ok(devs)
+}
@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 
 use super::Params;
+use rd_util::anon_area::AnonMode;
+use rd_util::numa::NumaPolicy;
 use rd_util::*;
 
 lazy_static::lazy_static! {
@@ -20,6 +22,9 @@ lazy_static::lazy_static! {
              -L, --log-size=[SIZE]         'Maximum log retention (default: {dfl_log_size:.2}G)'
              -i, --interval=[SECS]         'Summary report interval, 0 to disable (default: {dfl_intv}s)'
              -R, --rotational=[BOOL]       'Force rotational detection to either true or false'
+             -A, --anon-mode=[MODE]        'Anon memory backing - system, nothp, thp or hugetlb (default: system)'
+             -N, --numa=[POLICY]           'Anon memory NUMA policy - default, local, interleave or bind:NODE, also sets thread affinity (default: default)'
+             -S, --socket=[PATH]           'Serve hash requests from the Unix socket at PATH instead of generating them'
              -a, --args=[FILE]             'Load base command line arguments from FILE'
                  --keep-cache              'Don't drop page cache for testfiles on startup'
                  --clear-testfiles         'Clear testfiles before preparing them'
@@ -76,6 +81,9 @@ pub struct Args {
     pub log_size: u64,
     pub interval: u32,
     pub rotational: Option<bool>,
+    pub anon_mode: AnonMode,
+    pub numa: NumaPolicy,
+    pub socket: Option<String>,
 
     #[serde(skip)]
     pub keep_cache: bool,
@@ -134,6 +142,9 @@ impl Args {
             log_size: mem_size as u64 / 2,
             interval: 10,
             rotational: None,
+            anon_mode: AnonMode::System,
+            numa: NumaPolicy::Default,
+            socket: None,
             clear_testfiles: false,
             keep_cache: false,
             bench_preload_cache: None,
@@ -303,6 +314,30 @@ impl JsonArgs for Args {
             };
             updated_base = true;
         }
+        if let Some(v) = matches.value_of("anon-mode") {
+            self.anon_mode = if v.len() > 0 {
+                v.parse::<AnonMode>().unwrap()
+            } else {
+                dfl.anon_mode
+            };
+            updated_base = true;
+        }
+        if let Some(v) = matches.value_of("numa") {
+            self.numa = if v.len() > 0 {
+                v.parse::<NumaPolicy>().unwrap()
+            } else {
+                dfl.numa
+            };
+            updated_base = true;
+        }
+        if let Some(v) = matches.value_of("socket") {
+            self.socket = if v.len() > 0 {
+                Some(v.to_string())
+            } else {
+                None
+            };
+            updated_base = true;
+        }
 
         self.keep_cache = matches.is_present("keep-cache");
         if let Some(v) = matches.value_of("bench-preload-cache") {
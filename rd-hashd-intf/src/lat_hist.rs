@@ -0,0 +1,190 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops;
+
+use super::Latencies;
+
+/// HDR-style log-linear latency histogram. Latencies are recorded in
+/// microseconds. Values below 2 * SUB_BUCKETS are exact and larger ones are
+/// bucketed with SUB_BUCKETS buckets per power of two, bounding the relative
+/// error under 1 / SUB_BUCKETS. Unlike percentiles, histograms can be merged
+/// by simply adding up the counts, so percentiles over arbitrary periods can
+/// be calculated from per-second histograms.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatHist {
+    pub total: u64,
+    pub buckets: BTreeMap<u32, u64>,
+}
+
+impl LatHist {
+    const SUB_BITS: u32 = 7;
+    const SUB_BUCKETS: u64 = 1 << Self::SUB_BITS;
+
+    fn bucket(usecs: u64) -> u32 {
+        if usecs < 2 * Self::SUB_BUCKETS {
+            return usecs as u32;
+        }
+        let shift = 63 - usecs.leading_zeros() - Self::SUB_BITS;
+        let top = usecs >> shift;
+        (shift as u64 * Self::SUB_BUCKETS + top) as u32
+    }
+
+    /// The midpoint of the bucket in seconds.
+    fn bucket_value(bucket: u32) -> f64 {
+        let bucket = bucket as u64;
+        let usecs = if bucket < 2 * Self::SUB_BUCKETS {
+            bucket as f64
+        } else {
+            let shift = bucket / Self::SUB_BUCKETS - 1;
+            let top = bucket % Self::SUB_BUCKETS + Self::SUB_BUCKETS;
+            let low = top << shift;
+            let high = ((top + 1) << shift) - 1;
+            (low + high) as f64 / 2.0
+        };
+        usecs / 1_000_000.0
+    }
+
+    pub fn record(&mut self, secs: f64) {
+        let usecs = (secs.max(0.0) * 1_000_000.0).round() as u64;
+        *self.buckets.entry(Self::bucket(usecs)).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Given a cumulative histogram, the part recorded after `prev`, an
+    /// earlier snapshot of the same histogram. If it went backwards, e.g.
+    /// because the source restarted, the whole histogram is returned.
+    pub fn since(&self, prev: &LatHist) -> LatHist {
+        let mut delta = LatHist::default();
+        for (bucket, cnt) in self.buckets.iter() {
+            let base = prev.buckets.get(bucket).cloned().unwrap_or(0);
+            if *cnt < base {
+                return self.clone();
+            }
+            if *cnt > base {
+                delta.buckets.insert(*bucket, cnt - base);
+            }
+        }
+        if prev.buckets.keys().any(|b| !self.buckets.contains_key(b)) {
+            return self.clone();
+        }
+        delta.total = self.total - prev.total;
+        delta
+    }
+
+    /// Latency at quantile `q` [0.0, 1.0] in seconds.
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        let target = ((q.clamp(0.0, 1.0) * self.total as f64).ceil() as u64).max(1);
+        let mut cum = 0;
+        for (bucket, cnt) in self.buckets.iter() {
+            cum += cnt;
+            if cum >= target {
+                return Self::bucket_value(*bucket);
+            }
+        }
+        Self::bucket_value(*self.buckets.keys().last().unwrap())
+    }
+
+    pub fn latencies(&self, ctl_pct: f64) -> Latencies {
+        Latencies {
+            min: self.quantile(0.0),
+            p01: self.quantile(0.01),
+            p05: self.quantile(0.05),
+            p10: self.quantile(0.10),
+            p16: self.quantile(0.16),
+            p50: self.quantile(0.50),
+            p84: self.quantile(0.84),
+            p90: self.quantile(0.90),
+            p95: self.quantile(0.95),
+            p99: self.quantile(0.99),
+            p99_9: self.quantile(0.999),
+            p99_99: self.quantile(0.9999),
+            p99_999: self.quantile(0.99999),
+            max: self.quantile(1.0),
+            ctl: self.quantile(ctl_pct),
+        }
+    }
+}
+
+impl ops::AddAssign<&LatHist> for LatHist {
+    fn add_assign(&mut self, rhs: &LatHist) {
+        for (bucket, cnt) in rhs.buckets.iter() {
+            *self.buckets.entry(*bucket).or_insert(0) += cnt;
+        }
+        self.total += rhs.total;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LatHist;
+
+    #[test]
+    fn test_lat_hist() {
+        // Bucket boundaries must be continuous.
+        for usecs in 0..(1 << 20) {
+            let (b0, b1) = (LatHist::bucket(usecs), LatHist::bucket(usecs + 1));
+            assert!(
+                b1 == b0 || b1 == b0 + 1,
+                "usecs={} b0={} b1={}",
+                usecs,
+                b0,
+                b1
+            );
+        }
+
+        let mut lo = LatHist::default();
+        let mut hi = LatHist::default();
+        for i in 1..=1000 {
+            lo.record(i as f64 * 0.001);
+            hi.record(1.0 + i as f64 * 0.001);
+        }
+        let check = |v: f64, expected: f64| {
+            assert!(
+                (v - expected).abs() / expected < 0.01,
+                "{} != {}",
+                v,
+                expected
+            )
+        };
+        check(lo.quantile(0.5), 0.5);
+        check(lo.quantile(0.99), 0.99);
+
+        // Merged percentiles should reflect both halves.
+        lo += &hi;
+        assert_eq!(lo.total, 2000);
+        check(lo.quantile(0.25), 0.5);
+        check(lo.quantile(0.75), 1.5);
+        check(lo.quantile(1.0), 2.0);
+    }
+
+    #[test]
+    fn test_lat_hist_since() {
+        let mut cum = LatHist::default();
+        cum.record(0.001);
+        cum.record(0.002);
+        let snap = cum.clone();
+        assert!(cum.since(&snap).is_empty());
+
+        cum.record(0.002);
+        cum.record(0.5);
+        let delta = cum.since(&snap);
+        assert_eq!(delta.total, 2);
+        let mut expected = LatHist::default();
+        expected.record(0.002);
+        expected.record(0.5);
+        assert_eq!(delta, expected);
+
+        // A restarted source is taken as is.
+        let mut restarted = LatHist::default();
+        restarted.record(0.003);
+        assert_eq!(restarted.since(&cum), restarted);
+    }
+}
@@ -1,11 +1,17 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 pub mod args;
+pub mod lat_hist;
 pub mod params;
 pub mod report;
+pub mod sock;
 
 pub use args::Args;
-pub use params::Params;
-pub use report::{Latencies, Phase, Report, Stat};
+pub use lat_hist::LatHist;
+pub use params::{
+    AddrDist, AddrDrift, ArrivalDist, CpuKernel, LoadPattern, LogMode, Params, ReqClass,
+};
+pub use report::{ClassStat, LatBreakdown, Latencies, Phase, Report, Stat};
+pub use sock::{HashReq, HashResp};
 
 use rd_util::*;
 
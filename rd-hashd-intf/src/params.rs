@@ -4,6 +4,111 @@ use serde::{Deserialize, Serialize};
 
 use rd_util::*;
 
+/// Inter-arrival time distribution for open-loop dispatching.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArrivalDist {
+    Poisson,
+    Uniform,
+    Constant,
+}
+
+impl Default for ArrivalDist {
+    fn default() -> Self {
+        ArrivalDist::Poisson
+    }
+}
+
+/// Distribution of file and anon access addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddrDist {
+    Normal,
+    Zipf,
+    Uniform,
+    HotSet,
+}
+
+impl Default for AddrDist {
+    fn default() -> Self {
+        AddrDist::Normal
+    }
+}
+
+/// How the hot spot of the address distributions moves over time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddrDrift {
+    Static,
+    Rotate,
+    Jump,
+}
+
+impl Default for AddrDrift {
+    fn default() -> Self {
+        AddrDrift::Static
+    }
+}
+
+/// Durability mode of hash log writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogMode {
+    Buffered,
+    Fdatasync,
+    Direct,
+    Dsync,
+}
+
+impl Default for LogMode {
+    fn default() -> Self {
+        LogMode::Buffered
+    }
+}
+
+/// CPU work performed on the loaded data of each request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CpuKernel {
+    Sha1,
+    Compress,
+    Memcpy,
+    PtrChase,
+    Float,
+}
+
+impl Default for CpuKernel {
+    fn default() -> Self {
+        CpuKernel::Sha1
+    }
+}
+
+/// Periodic load pattern layered over the RPS target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoadPattern {
+    Sine,
+    Square,
+    Sawtooth,
+}
+
+impl Default for LoadPattern {
+    fn default() -> Self {
+        LoadPattern::Sine
+    }
+}
+
+impl LoadPattern {
+    /// Pattern value in [-1.0, 1.0] at `phase` [0.0, 1.0) of the period.
+    pub fn value(&self, phase: f64) -> f64 {
+        match self {
+            Self::Sine => (2.0 * std::f64::consts::PI * phase).sin(),
+            Self::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Self::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PidParams {
     pub kp: f64,
@@ -45,6 +150,61 @@ const PARAMS_DOC: &str = "\
 // Worker threads will sleep according to the sleep duration distribution and
 // their CPU consumption can be scaled up and down using `cpu_ratio`.
 //
+// Requests can be split into multiple classes, e.g. cheap lookups and heavy
+// scans, by listing them in `classes`. Each class takes its share of the
+// dispatched requests and scales access sizes and CPU usage relative to the
+// top-level parameters. Each class also has its own latency target and the
+// concurrency is limited by whichever target, including the top-level one,
+// is the most exceeded. If `classes` is empty, all requests belong to a
+// single implicit class described by the top-level parameters.
+//
+// If `open_loop` is set, the PID controllers are bypassed. Requests arrive
+// at `rps_target` with inter-arrival times following `arrival_dist` whether
+// or not the earlier ones are done, and wait in a queue while all
+// `concurrency_max` workers are busy. Queueing delay and service time are
+// reported separately. Arrivals are dropped once `queue_max` requests are
+// waiting.
+//
+// If `trace` is set, RPS target and access sizes follow the trace instead of
+// `rps_target`. The trace is either a JSON array of objects with `at`, `rps`
+// and optional `size_ratio` fields or CSV lines of `SECS,RPS[,SIZE_RATIO]`,
+// and is linearly interpolated between points. Its time axis is stretched by
+// `trace_stretch` and the RPS values are scaled by `trace_rps_ratio`.
+//
+// The RPS target, static or from trace, can be modulated with a periodic
+// `load_pattern` whose swing is `load_amplitude` of the target, and random
+// spikes which multiply the target by 1.0 + `spike_ratio` for `spike_dur`
+// every `spike_interval` on average. As the memory footprint follows RPS,
+// the working set grows and shrinks with the load.
+//
+// `addr_dist` selects how file and anon access addresses are distributed
+// over the footprint. `Normal` concentrates accesses following
+// `file_addr_stdev_ratio` and `anon_addr_stdev_ratio`. `Zipf` picks
+// footprint slices by popularity rank with `addr_zipf_exp` as the exponent.
+// `HotSet` sends `addr_hot_prob` of accesses uniformly to the hottest
+// `addr_hot_frac` of the footprint and the rest uniformly to the remainder.
+//
+// The hot spot stays put by default. `addr_drift` can make it `Rotate`
+// through the footprint at `addr_drift_rate` or `Jump` to a random position
+// every `addr_drift_interval`, continually shifting the working set.
+//
+// Hash log writes go through the page cache by default. `log_mode` can
+// instead issue `fdatasync` after every `log_sync_bytes` or `log_sync_intv`,
+// whichever comes first, write with `O_DIRECT` in page-aligned blocks, or
+// open the log with `O_DSYNC`.
+//
+// `cpu_kernel` selects the CPU work done on the loaded data. `Sha1` is
+// compute-bound, `Compress` is branchy with a small hash table, `Memcpy`
+// stresses memory bandwidth, `PtrChase` stresses memory latency and LLC, and
+// `Float` exercises the FPU. The benchmark calibrates `file_size_mean` with
+// the selected kernel, so re-run it after changing `cpu_kernel`.
+//
+// If `seed` is set, the request sizes and classes, the per-request access
+// addresses, and the contents of testfiles, anon pages and log padding are
+// derived from it so that runs can be replayed. Completion order still
+// depends on timing. Existing testfiles generated with a different seed are
+// recreated.
+//
 //  control_period: PID control period, best left alone
 //  concurrency_max: Maximum number of worker threads
 //  lat_target_pct: Latency target percentile
@@ -64,17 +224,84 @@ const PARAMS_DOC: &str = "\
 //  anon_addr_stdev_ratio: Standard deviation of anon access addresses
 //  anon_addr_rps_base_frac: Memory scaling starting point for anon accesses
 //  anon_write_frac: The proportion of writes in anon accesses
+//  addr_dist: Access address distribution - Normal, Zipf, Uniform or HotSet
+//  addr_zipf_exp: Zipf exponent, higher concentrates accesses more
+//  addr_hot_frac: HotSet hot fraction of the footprint - (0.0, 1.0]
+//  addr_hot_prob: HotSet proportion of accesses going to the hot set - [0.0, 1.0]
+//  addr_drift: Hot spot movement - Static, Rotate or Jump
+//  addr_drift_rate: Rotate speed in fraction of the footprint per second
+//  addr_drift_interval: Interval between Jumps
 //  sleep_mean: Worker sleep duration average
 //  sleep_stdev_ratio: Standard deviation of sleep duration distribution
 //  cpu_ratio: CPU usage scaling - 1.0 hashes the same number of bytes as accessed
+//  cpu_kernel: CPU work type - Sha1, Compress, Memcpy, PtrChase or Float
 //  log_bps: Log write bps at rps_max
+//  log_mode: Log write durability - Buffered, Fdatasync, Direct or Dsync
+//  log_sync_bytes: Maximum bytes written between fdatasyncs in Fdatasync mode
+//  log_sync_intv: Maximum interval between fdatasyncs in Fdatasync mode
 //  fake_cpu_load: Sleep equivalent time durations instead of calculating SHA1s
 //  acc_dist_slots: Access distribution report slots - 0 disables
 //  lat_pid: PID controller parameters for latency convergence
 //  rps_pid: PID controller parameters for RPS convergence
+//  open_loop: Dispatch at rps_target regardless of completions
+//  arrival_dist: Open-loop inter-arrival distribution - Poisson, Uniform or Constant
+//  queue_max: Maximum number of queued open-loop requests
+//  trace: Path to the RPS trace to replay, empty to use rps_target
+//  trace_loop: Restart the trace from the beginning when it ends
+//  trace_stretch: Trace time axis scaling - 2.0 replays at half speed
+//  trace_rps_ratio: Trace RPS scaling
+//  load_pattern: Periodic load pattern - Sine, Square or Sawtooth
+//  load_amplitude: Load pattern amplitude relative to RPS target, 0 disables
+//  load_period: Load pattern period
+//  spike_interval: Average interval between random load spikes
+//  spike_dur: Duration of each load spike
+//  spike_ratio: Load spike height relative to RPS target, 0 disables
+//  seed: Random seed for reproducible runs, null for random
+//  classes[].name: Request class name, used as the key in reports
+//  classes[].share: Relative share of dispatched requests
+//  classes[].file_size_ratio: File access size average relative to file_size_mean
+//  classes[].file_size_stdev_ratio: Standard deviation of file access sizes
+//  classes[].anon_size_ratio: Anon access size average relative to top-level
+//  classes[].anon_size_stdev_ratio: Standard deviation of anon access sizes
+//  classes[].cpu_ratio: CPU usage scaling relative to top-level cpu_ratio
+//  classes[].lat_target_pct: Latency target percentile of the class
+//  classes[].lat_target: Latency target of the class
 //
 ";
 
+/// A request class. Sizes and CPU usage are relative to the top-level
+/// parameters so that they follow benchmark results.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReqClass {
+    pub name: String,
+    pub share: f64,
+    pub file_size_ratio: f64,
+    pub file_size_stdev_ratio: f64,
+    pub anon_size_ratio: f64,
+    pub anon_size_stdev_ratio: f64,
+    pub cpu_ratio: f64,
+    pub lat_target_pct: f64,
+    pub lat_target: f64,
+}
+
+impl Default for ReqClass {
+    fn default() -> Self {
+        let dfl = Params::default();
+        Self {
+            name: "".into(),
+            share: 1.0,
+            file_size_ratio: 1.0,
+            file_size_stdev_ratio: dfl.file_size_stdev_ratio,
+            anon_size_ratio: 1.0,
+            anon_size_stdev_ratio: dfl.anon_size_stdev_ratio,
+            cpu_ratio: 1.0,
+            lat_target_pct: dfl.lat_target_pct,
+            lat_target: dfl.lat_target,
+        }
+    }
+}
+
 /// Dispatch and hash parameters, can be adjusted dynamially.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(default)]
@@ -98,14 +325,40 @@ pub struct Params {
     pub anon_addr_stdev_ratio: f64,
     pub anon_addr_rps_base_frac: f64,
     pub anon_write_frac: f64,
+    pub addr_dist: AddrDist,
+    pub addr_zipf_exp: f64,
+    pub addr_hot_frac: f64,
+    pub addr_hot_prob: f64,
+    pub addr_drift: AddrDrift,
+    pub addr_drift_rate: f64,
+    pub addr_drift_interval: f64,
     pub sleep_mean: f64,
     pub sleep_stdev_ratio: f64,
     pub cpu_ratio: f64,
+    pub cpu_kernel: CpuKernel,
     pub log_bps: u64,
+    pub log_mode: LogMode,
+    pub log_sync_bytes: u64,
+    pub log_sync_intv: f64,
     pub fake_cpu_load: bool,
     pub acc_dist_slots: usize,
     pub lat_pid: PidParams,
     pub rps_pid: PidParams,
+    pub open_loop: bool,
+    pub arrival_dist: ArrivalDist,
+    pub queue_max: u32,
+    pub trace: String,
+    pub trace_loop: bool,
+    pub trace_stretch: f64,
+    pub trace_rps_ratio: f64,
+    pub load_pattern: LoadPattern,
+    pub load_amplitude: f64,
+    pub load_period: f64,
+    pub spike_interval: f64,
+    pub spike_dur: f64,
+    pub spike_ratio: f64,
+    pub seed: Option<u64>,
+    pub classes: Vec<ReqClass>,
 }
 
 impl Params {
@@ -142,10 +395,21 @@ impl Default for Params {
             anon_addr_stdev_ratio: 0.235,
             anon_addr_rps_base_frac: 0.5,
             anon_write_frac: 0.3,
+            addr_dist: Default::default(),
+            addr_zipf_exp: 1.0,
+            addr_hot_frac: 0.1,
+            addr_hot_prob: 0.9,
+            addr_drift: Default::default(),
+            addr_drift_rate: 0.01,
+            addr_drift_interval: 60.0,
             sleep_mean: 20.0 * MSEC,
             sleep_stdev_ratio: 0.33,
             cpu_ratio: 0.93,
+            cpu_kernel: Default::default(),
             log_bps: 1100794,
+            log_mode: Default::default(),
+            log_sync_bytes: 1 << 20,
+            log_sync_intv: 10.0 * MSEC,
             fake_cpu_load: false,
             acc_dist_slots: 0,
             lat_pid: PidParams {
@@ -158,6 +422,21 @@ impl Default for Params {
                 ki: 0.01,
                 kd: 0.01,
             },
+            open_loop: false,
+            arrival_dist: Default::default(),
+            queue_max: 1 << 20,
+            trace: "".into(),
+            trace_loop: true,
+            trace_stretch: 1.0,
+            trace_rps_ratio: 1.0,
+            load_pattern: Default::default(),
+            load_amplitude: 0.0,
+            load_period: 60.0,
+            spike_interval: 60.0,
+            spike_dur: 5.0,
+            spike_ratio: 0.0,
+            seed: None,
+            classes: vec![],
         }
     }
 }
@@ -174,3 +453,32 @@ impl JsonSave for Params {
         Some(PARAMS_DOC.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::LoadPattern;
+
+    #[test]
+    fn test_load_pattern_value() {
+        let near = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        let end = 1.0 - 1e-12;
+
+        let sine = |phase| LoadPattern::Sine.value(phase);
+        assert!(near(sine(0.0), 0.0));
+        assert!(near(sine(0.25), 1.0));
+        assert!(near(sine(0.5), 0.0));
+        assert!(near(sine(0.75), -1.0));
+        assert!(near(sine(end), 0.0));
+
+        let square = |phase| LoadPattern::Square.value(phase);
+        assert_eq!(square(0.0), 1.0);
+        assert_eq!(square(0.5 - 1e-12), 1.0);
+        assert_eq!(square(0.5), -1.0);
+        assert_eq!(square(end), -1.0);
+
+        let saw = |phase| LoadPattern::Sawtooth.value(phase);
+        assert!(near(saw(0.0), -1.0));
+        assert!(near(saw(0.5), 0.0));
+        assert!(near(saw(end), 1.0));
+    }
+}
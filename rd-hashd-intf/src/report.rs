@@ -1,9 +1,11 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::ops;
 use std::time::UNIX_EPOCH;
 
+use super::LatHist;
 use rd_util::*;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -103,51 +105,212 @@ impl<T: Into<f64>> ops::DivAssign<T> for Latencies {
     }
 }
 
+/// Per-request latency broken down into phases. Each field is the mean
+/// over the requests completed in the last control period.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LatBreakdown {
+    pub queue: f64,
+    pub file_io: f64,
+    pub anon: f64,
+    pub anon_majflt: f64,
+    pub hash: f64,
+    pub hash_cpu_wait: f64,
+    pub sleep: f64,
+}
+
+impl ops::AddAssign<&LatBreakdown> for LatBreakdown {
+    fn add_assign(&mut self, rhs: &LatBreakdown) {
+        self.queue += rhs.queue;
+        self.file_io += rhs.file_io;
+        self.anon += rhs.anon;
+        self.anon_majflt += rhs.anon_majflt;
+        self.hash += rhs.hash;
+        self.hash_cpu_wait += rhs.hash_cpu_wait;
+        self.sleep += rhs.sleep;
+    }
+}
+
+impl<T: Into<f64>> ops::DivAssign<T> for LatBreakdown {
+    fn div_assign(&mut self, rhs: T) {
+        let div = rhs.into();
+        self.queue /= div;
+        self.file_io /= div;
+        self.anon /= div;
+        self.anon_majflt /= div;
+        self.hash /= div;
+        self.hash_cpu_wait /= div;
+        self.sleep /= div;
+    }
+}
+
 const STAT_DOC: &str = "\
 //  rps: Request per second in the last control period
+//  rps_target: Current RPS target, follows the trace if replaying
+//  trace_progress: Position in the current pass through the trace - [0.0, 1.0]
+//  trace_loops: Number of completed passes through the trace
 //  concurrency: Current number of active worker threads
 //  concurrency_max: Current concurrency max from latency target
 //  file_addr_frac: Current file footprint fraction
 //  anon_addr_frac: Current anon footprint fraction
+//  anon_huge_size: Bytes of the anon area backed by THP or hugetlb pages
+//  anon_node_size{}: Bytes of the anon area resident on each NUMA node
+//  addr_offset: Current hot spot position from params.addr_drift - [0.0, 1.0)
 //  nr_in_flight: The number of requests in flight
 //  nr_done: Total number of hashes calculated
 //  nr_workers: Number of worker threads
 //  nr_idle_workers: Number of idle workers
 //  lat.p*: Latency percentiles
 //  lat.ctl: Latency percentile used for rps control (params.lat_target_pct)
+//  lat_hist: Mergeable latency histogram of completions since the last report
+//  lat_hist_cum: Cumulative latency histogram since startup, readers which
+//                may miss or re-read reports should diff successive ones
+//  nr_queued: Number of open-loop requests waiting for a worker
+//  nr_dropped: Total number of open-loop arrivals dropped due to full queue
+//  queue_lat.p*: Open-loop queueing delay percentiles
+//  svc_lat.p*: Open-loop service time percentiles, lat.p* includes queueing
+//  log_write_lat: Mergeable hash log write latency histogram including syncs
+//  log_write_lat_cum: Cumulative log_write_lat since startup
+//  lat_breakdown.queue: Mean wait before a worker picked up the request
+//  lat_breakdown.file_io: Mean time spent loading file pages
+//  lat_breakdown.anon: Mean time spent touching anon pages
+//  lat_breakdown.anon_majflt: Mean number of major faults (swap-ins) on anon pages
+//  lat_breakdown.hash: Mean time spent calculating SHA1
+//  lat_breakdown.hash_cpu_wait: Mean time the hashing phase spent off-CPU
+//  lat_breakdown.sleep: Mean time spent sleeping
+//  classes.NAME.rps: Request per second of the class
+//  classes.NAME.nr_done: Total number of hashes calculated for the class
+//  classes.NAME.lat_target: Latency target of the class
+//  classes.NAME.lat_target_pct: Latency target percentile of the class
+//  classes.NAME.lat.p*: Latency percentiles of the class
+//  classes.NAME.lat.ctl: Latency percentile at the class's lat_target_pct
+//  classes.NAME.lat_hist: Mergeable latency histogram of the class
+//  classes.NAME.lat_hist_cum: Cumulative lat_hist of the class since startup
 ";
 
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ClassStat {
+    pub rps: f64,
+    pub nr_done: u64,
+    pub lat_target: f64,
+    #[serde(default)]
+    pub lat_target_pct: f64,
+    pub lat: Latencies,
+    #[serde(default)]
+    pub lat_hist: LatHist,
+    #[serde(default)]
+    pub lat_hist_cum: LatHist,
+}
+
+/// Targets are configuration and follow the latest. Latencies are
+/// recalculated from the merged histograms as percentiles can't be averaged.
+/// Without histograms, the latest latencies are used.
+impl ops::AddAssign<&ClassStat> for ClassStat {
+    fn add_assign(&mut self, rhs: &ClassStat) {
+        self.rps += rhs.rps;
+        self.nr_done += rhs.nr_done;
+        self.lat_target = rhs.lat_target;
+        self.lat_target_pct = rhs.lat_target_pct;
+        self.lat_hist += &rhs.lat_hist;
+        self.lat_hist_cum = rhs.lat_hist_cum.clone();
+        self.lat = match self.lat_hist.is_empty() {
+            true => rhs.lat.clone(),
+            false => self.lat_hist.latencies(self.lat_target_pct),
+        };
+    }
+}
+
+impl<T: Into<f64>> ops::DivAssign<T> for ClassStat {
+    fn div_assign(&mut self, rhs: T) {
+        let div = rhs.into();
+        self.rps /= div;
+        self.nr_done = (self.nr_done as f64 / div).round() as u64;
+    }
+}
+
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Stat {
     pub rps: f64,
+    #[serde(default)]
+    pub rps_target: f64,
+    #[serde(default)]
+    pub trace_progress: f64,
+    #[serde(default)]
+    pub trace_loops: u64,
     pub concurrency: f64,
     pub concurrency_max: f64,
     pub file_addr_frac: f64,
     pub anon_addr_frac: f64,
+    #[serde(default)]
+    pub addr_offset: f64,
     pub nr_in_flight: u32,
     pub nr_done: u64,
     pub nr_workers: usize,
     pub nr_idle_workers: usize,
     pub lat: Latencies,
+    #[serde(default)]
+    pub lat_hist: LatHist,
+    #[serde(default)]
+    pub lat_hist_cum: LatHist,
+    #[serde(default)]
+    pub nr_queued: u32,
+    #[serde(default)]
+    pub nr_dropped: u64,
+    #[serde(default)]
+    pub queue_lat: Latencies,
+    #[serde(default)]
+    pub svc_lat: Latencies,
+    #[serde(default)]
+    pub lat_breakdown: LatBreakdown,
+    #[serde(default)]
+    pub log_write_lat: LatHist,
+    #[serde(default)]
+    pub log_write_lat_cum: LatHist,
 
     pub file_size: u64,
     pub file_dist: Vec<u64>,
     pub anon_size: usize,
     pub anon_dist: Vec<u64>,
+    #[serde(default)]
+    pub anon_huge_size: usize,
+    #[serde(default)]
+    pub anon_node_size: BTreeMap<u32, usize>,
+
+    #[serde(default)]
+    pub classes: BTreeMap<String, ClassStat>,
 }
 
 impl ops::AddAssign<&Stat> for Stat {
     fn add_assign(&mut self, rhs: &Stat) {
         self.rps += rhs.rps;
+        self.rps_target += rhs.rps_target;
+        self.trace_progress += rhs.trace_progress;
+        self.trace_loops += rhs.trace_loops;
         self.concurrency += rhs.concurrency;
         self.concurrency_max += rhs.concurrency_max;
         self.file_addr_frac += rhs.file_addr_frac;
         self.anon_addr_frac += rhs.anon_addr_frac;
+        self.addr_offset += rhs.addr_offset;
         self.nr_in_flight += rhs.nr_in_flight;
         self.nr_done += rhs.nr_done;
         self.nr_workers += rhs.nr_workers;
         self.nr_idle_workers += rhs.nr_idle_workers;
         self.lat += &rhs.lat;
+        self.lat_hist += &rhs.lat_hist;
+        self.lat_hist_cum = rhs.lat_hist_cum.clone();
+        self.nr_queued += rhs.nr_queued;
+        self.nr_dropped += rhs.nr_dropped;
+        self.queue_lat += &rhs.queue_lat;
+        self.svc_lat += &rhs.svc_lat;
+        self.lat_breakdown += &rhs.lat_breakdown;
+        self.log_write_lat += &rhs.log_write_lat;
+        self.log_write_lat_cum = rhs.log_write_lat_cum.clone();
+        self.anon_huge_size += rhs.anon_huge_size;
+        for (node, size) in rhs.anon_node_size.iter() {
+            *self.anon_node_size.entry(*node).or_default() += size;
+        }
+        for (name, cs) in rhs.classes.iter() {
+            *self.classes.entry(name.clone()).or_default() += cs;
+        }
     }
 }
 
@@ -158,15 +321,31 @@ impl Stat {
     {
         let divf64 = div.into();
         self.rps /= divf64;
+        self.rps_target /= divf64;
+        self.trace_progress /= divf64;
+        self.trace_loops = (self.trace_loops as f64 / divf64).round() as u64;
         self.concurrency /= divf64;
         self.concurrency_max /= divf64;
         self.file_addr_frac /= divf64;
         self.anon_addr_frac /= divf64;
+        self.addr_offset /= divf64;
         self.nr_in_flight = (self.nr_in_flight as f64 / divf64).round() as u32;
         self.nr_done = (self.nr_done as f64 / divf64).round() as u64;
         self.nr_workers = (self.nr_workers as f64 / divf64).round() as usize;
         self.nr_idle_workers = (self.nr_idle_workers as f64 / divf64).round() as usize;
         self.lat /= divf64;
+        self.nr_queued = (self.nr_queued as f64 / divf64).round() as u32;
+        self.nr_dropped = (self.nr_dropped as f64 / divf64).round() as u64;
+        self.queue_lat /= divf64;
+        self.svc_lat /= divf64;
+        self.lat_breakdown /= divf64;
+        self.anon_huge_size = (self.anon_huge_size as f64 / divf64).round() as usize;
+        for size in self.anon_node_size.values_mut() {
+            *size = (*size as f64 / divf64).round() as usize;
+        }
+        for cs in self.classes.values_mut() {
+            *cs /= divf64;
+        }
     }
 }
 
@@ -183,6 +362,7 @@ const REPORT_DOC_HEADER: &str = "\
 //  params_modified: Modified timestamp of the loaded params file
 //  mem_probe_frac: Memory frac benchmark is currently probing
 //  mem_probe_at: The timestamp this memory probing started at
+//  seed: Random seed from params, null if random
 ";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -196,6 +376,8 @@ pub struct Report {
     pub params_modified: DateTime<Local>,
     pub mem_probe_size: usize,
     pub mem_probe_at: DateTime<Local>,
+    #[serde(default)]
+    pub seed: Option<u64>,
     #[serde(flatten)]
     pub hasher: Stat,
 }
@@ -212,6 +394,7 @@ impl Default for Report {
             params_modified: DateTime::from(UNIX_EPOCH),
             mem_probe_size: 0,
             mem_probe_at: DateTime::from(UNIX_EPOCH),
+            seed: None,
             hasher: Default::default(),
         }
     }
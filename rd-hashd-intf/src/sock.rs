@@ -0,0 +1,35 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// Messages exchanged over the `--socket` request interface. Each message is
+// a single line of JSON. A connection may pipeline multiple requests and the
+// responses are sent back in completion order, so clients should match them
+// using `id`.
+use serde::{Deserialize, Serialize};
+
+use super::LatBreakdown;
+
+/// A hash request from an external client. Unspecified fields are sampled
+/// from the current params the same way self-generated requests are.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HashReq {
+    pub id: u64,
+    pub class: Option<String>,
+    pub file_size: Option<usize>,
+    pub anon_size: Option<usize>,
+    pub sleep: Option<f64>,
+}
+
+/// The response to a `HashReq`. On failure, `error` is set and the rest of
+/// the fields are left at their defaults.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HashResp {
+    pub id: u64,
+    pub error: Option<String>,
+    pub digest: String,
+    pub lat: f64,
+    pub queue_lat: f64,
+    pub svc_lat: f64,
+    pub breakdown: LatBreakdown,
+}
@@ -4,6 +4,7 @@ use linreg::linear_regression_of;
 use log::{debug, error, info, warn};
 use num::Integer;
 use pid::Pid;
+use std::cell::Cell;
 use std::collections::VecDeque;
 use std::fmt::Write;
 use std::rc::Rc;
@@ -12,7 +13,9 @@ use std::thread::{sleep, spawn, JoinHandle};
 use std::time::{Duration, Instant};
 use std::u32;
 
-use rd_hashd_intf::{params, Params, Phase, Report, Stat};
+use rd_hashd_intf::{params, AddrDist, Params, Phase, Report, Stat};
+use rd_util::anon_area::AnonMode;
+use rd_util::numa::NumaPolicy;
 use rd_util::*;
 
 use super::hasher;
@@ -266,18 +269,25 @@ impl TestHasher {
         }
     }
 
+    pub fn anon_mode(&self) -> AnonMode {
+        self.disp_hist.lock().unwrap().disp.anon_mode()
+    }
+
     pub fn new(
         max_size: u64,
         tf: TestFiles,
         params: &Params,
         comp: f64,
+        anon_mode: AnonMode,
+        numa: NumaPolicy,
         logger: Option<super::Logger>,
         hist_max: usize,
         report_file: Arc<Mutex<JsonReportFile<Report>>>,
         fill_anon: bool,
         verbose: bool,
     ) -> Self {
-        let disp = hasher::Dispatch::new(max_size, tf, params, comp, logger);
+        let disp =
+            hasher::Dispatch::new(max_size, tf, params, comp, anon_mode, numa, logger, false);
         if fill_anon {
             disp.fill_anon();
         }
@@ -539,6 +549,7 @@ pub struct Bench {
     params_file: JsonConfigFile<Params>,
     report_file: Arc<Mutex<JsonReportFile<Report>>>,
     params: Params,
+    anon_mode: Cell<AnonMode>,
     bar_hidden: bool,
 }
 
@@ -550,12 +561,23 @@ impl Bench {
     ) -> Self {
         let args = &args_file.data;
         let verbosity = args.verbosity;
+        let anon_mode = Cell::new(args.anon_mode);
+
+        // Benchmarks start from the default params but CPU calibration must
+        // use the configured kernel for rps_max to be meaningful. Keep the
+        // seed too so that bench runs can be replayed.
+        let params = Params {
+            cpu_kernel: params_file.data.cpu_kernel,
+            seed: params_file.data.seed,
+            ..Default::default()
+        };
 
         Self {
             args_file,
             params_file,
             report_file: Arc::new(Mutex::new(report_file)),
-            params: Default::default(),
+            params,
+            anon_mode,
             bar_hidden: verbosity > 1,
         }
     }
@@ -580,6 +602,7 @@ impl Bench {
             TESTFILE_UNIT_SIZE,
             size,
             self.args_file.data.compressibility,
+            self.params.seed,
         );
         let mut tfbar =
             TestFilesProgressBar::new(size, &greet, "Preparing testfiles", self.bar_hidden);
@@ -618,17 +641,23 @@ impl Bench {
         params: &Params,
         fill_anon: bool,
     ) -> TestHasher {
-        TestHasher::new(
+        // Once hugetlb falls back to THP, stick with it so that all phases
+        // and the recorded result agree on the backing mode.
+        let th = TestHasher::new(
             max_size,
             tf,
             params,
             self.args_file.data.compressibility,
+            self.anon_mode.get(),
+            self.args_file.data.numa,
             create_logger(&self.args_file.data, params),
             HIST_MAX,
             self.report_file.clone(),
             fill_anon,
             self.args_file.data.verbosity > 0,
-        )
+        );
+        self.anon_mode.set(th.anon_mode());
+        th
     }
 
     /// We want to regulate the number of distinct IOs generated by each
@@ -647,7 +676,7 @@ impl Bench {
     }
 
     fn time_hash(size: usize, params: &Params, tf: &TestFiles) -> f64 {
-        let mut hasher = hasher::Hasher::new(1.0, 0.0);
+        let mut hasher = hasher::Hasher::new(1.0, params.cpu_kernel, 0.0);
         let chunk_size = params.chunk_pages * *PAGE_SIZE;
         let chunks_per_unit = Integer::div_ceil(&(tf.unit_size as usize), &chunk_size);
 
@@ -662,7 +691,7 @@ impl Bench {
                 i, chunk_size, chunks_per_unit, &path, off
             ));
         }
-        hasher.sha1();
+        hasher.digest();
 
         Instant::now().duration_since(started_at).as_secs_f64()
     }
@@ -707,6 +736,7 @@ impl Bench {
         params.file_addr_stdev_ratio = 100.0;
         params.anon_size_stdev_ratio = 0.0;
         params.anon_addr_stdev_ratio = 100.0;
+        params.addr_dist = AddrDist::Normal;
         params.sleep_mean = 0.0;
         params.sleep_stdev_ratio = 0.0;
 
@@ -794,6 +824,7 @@ impl Bench {
 
         params.file_addr_stdev_ratio = 100.0;
         params.anon_addr_stdev_ratio = 100.0;
+        params.addr_dist = AddrDist::Normal;
         params.rps_target = u32::MAX;
 
         let th = self.create_test_hasher(cfg.size, tf, &params, true);
@@ -1186,6 +1217,7 @@ impl Bench {
             );
 
             self.params.mem_frac = self.bench_memio_saturation_refine(mem_sat_cfg, &mut th);
+            self.anon_mode.set(th.anon_mode());
 
             // Longer-runs might need more memory due to access from
             // accumulating long tails and other system disturbances. Plus, IO
@@ -1205,15 +1237,19 @@ impl Bench {
         }
 
         info!(
-            "Bench results: memory {:.2}G ({:.2}%), hash {:.2}M, rps {}",
+            "Bench results: memory {:.2}G ({:.2}%), hash {:.2}M, rps {}, anon {:?}, numa {:?}",
             to_gb(max_size as f64 * self.params.mem_frac),
             self.params.mem_frac * TO_PCT,
             to_mb(self.params.file_size_mean),
             self.params.rps_max,
+            self.anon_mode.get(),
+            args.numa,
         );
 
-        // Save results.
+        // Save results. Record the anon mode which was actually used so
+        // that the bench result reflects e.g. hugetlb -> THP fallback.
         self.params_file.data = self.params.clone();
+        self.args_file.data.anon_mode = self.anon_mode.get();
 
         self.args_file.save().expect("failed to save args file");
         self.params_file.save().expect("failed to save params file");
@@ -0,0 +1,111 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// Non-SHA1 CPU kernels for Hasher. Each consumes the input slice and folds
+// its result into a u64 accumulator so that the work can't be optimized
+// away. The accumulator is used to generate the digest for the hash log.
+use std::hint::black_box;
+
+const LZ_HASH_BITS: u32 = 12;
+const LZ_MAX_MATCH: usize = 255;
+
+fn word_at(data: &[u8], idx: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[idx * 8..idx * 8 + 8]);
+    u64::from_ne_bytes(bytes)
+}
+
+/// Greedy LZ77 with a single-entry hash table, returns the compressed size
+/// in bytes. Branchy with a cache-resident working set.
+pub fn compress(data: &[u8], table: &mut Vec<usize>, acc: u64) -> u64 {
+    table.clear();
+    table.resize(1 << LZ_HASH_BITS, usize::MAX);
+
+    let mut out = 0;
+    let mut i = 0;
+    while i + 4 <= data.len() {
+        let mut key = [0u8; 4];
+        key.copy_from_slice(&data[i..i + 4]);
+        let hash =
+            (u32::from_ne_bytes(key).wrapping_mul(2654435761) >> (32 - LZ_HASH_BITS)) as usize;
+        let cand = table[hash];
+        table[hash] = i;
+
+        if cand < i && data[cand..cand + 4] == data[i..i + 4] {
+            let mut len = 4;
+            while i + len < data.len() && len < LZ_MAX_MATCH && data[cand + len] == data[i + len] {
+                len += 1;
+            }
+            out += 3;
+            i += len;
+        } else {
+            out += 1;
+            i += 1;
+        }
+    }
+    out += data.len() - i;
+
+    acc.wrapping_add(out as u64)
+}
+
+/// Copy the input into a scratch buffer and then once more within it. Bound
+/// by memory bandwidth once the input doesn't fit in the cache.
+pub fn memcpy(data: &[u8], scratch: &mut Vec<u8>, acc: u64) -> u64 {
+    let len = data.len();
+    scratch.resize(2 * len, 0);
+    scratch[..len].copy_from_slice(data);
+    black_box(&mut scratch[..]);
+    scratch.copy_within(..len, len);
+    black_box(&mut scratch[..]);
+
+    let sum: u64 = scratch[len..].iter().step_by(4096).map(|v| *v as u64).sum();
+    acc.wrapping_add(sum)
+}
+
+/// Follow data-dependent indices through the input, one dependent load per
+/// word. Bound by memory latency and sensitive to LLC contention.
+pub fn ptr_chase(data: &[u8], acc: u64) -> u64 {
+    let nr_words = data.len() / 8;
+    if nr_words == 0 {
+        return acc;
+    }
+
+    let mut idx = (acc as usize) % nr_words;
+    let mut sum = acc;
+    for _ in 0..nr_words {
+        let word = word_at(data, idx);
+        sum = sum.wrapping_add(word);
+        idx = ((word ^ sum).wrapping_mul(0x9e3779b97f4a7c15) >> 11) as usize % nr_words;
+    }
+    sum
+}
+
+/// Floating point arithmetic on each word interpreted as a value in [0, 1).
+pub fn float(data: &[u8], acc: u64) -> u64 {
+    let mut v = acc as f64 / u64::MAX as f64;
+    for idx in 0..data.len() / 8 {
+        let x = (word_at(data, idx) >> 11) as f64 / (1u64 << 53) as f64;
+        v = (v * 0.999 + x.sqrt() * 1.001).sin().abs() + x * x;
+    }
+    acc.wrapping_add(v.to_bits())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_compress() {
+        let mut table = vec![];
+
+        // Repetitive data should compress well, random data shouldn't.
+        let rep: Vec<u8> = (0..65536).map(|i| (i % 64) as u8).collect();
+        assert!(super::compress(&rep, &mut table, 0) < 65536 / 8);
+
+        let mut state = 1u64;
+        let rand: Vec<u8> = (0..65536)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (state >> 56) as u8
+            })
+            .collect();
+        assert!(super::compress(&rand, &mut table, 0) > 65536 * 9 / 10);
+    }
+}
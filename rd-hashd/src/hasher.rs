@@ -1,14 +1,16 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
-use anyhow::Result;
+use anyhow::{bail, Result};
 use crossbeam::channel::{self, select, Receiver, Sender};
-use log::{debug, error, trace, warn};
+use log::{debug, error, info, trace, warn};
 use num::Integer;
 use pid::Pid;
 use quantiles::ckms::CKMS;
+use rand::distributions::WeightedIndex;
 use rand::rngs::SmallRng;
-use rand::SeedableRng;
-use rand_distr::{Distribution, Normal, Uniform};
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Exp, Normal, Uniform, Zipf};
 use sha1_smol::{Digest, Sha1};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::convert::TryInto;
 use std::fs::OpenOptions;
 use std::io::{prelude::*, SeekFrom};
@@ -17,30 +19,42 @@ use std::sync::{Arc, RwLock};
 use std::thread::{sleep, spawn, JoinHandle};
 use std::time::{Duration, Instant};
 
-use rd_hashd_intf::{Latencies, Params, Stat};
-use rd_util::anon_area::AnonArea;
+use rd_hashd_intf::{
+    AddrDist, AddrDrift, ArrivalDist, ClassStat, CpuKernel, HashReq, HashResp, LatBreakdown,
+    LatHist, Latencies, Params, Stat,
+};
+use rd_util::anon_area::{AnonArea, AnonMode};
+use rd_util::numa::NumaPolicy;
 use rd_util::*;
 
 use super::bench::{Bench, Cfg};
-use super::logger::Logger;
+use super::cpu_kernel;
+use super::logger::{LogCfg, Logger};
 use super::testfiles::TestFiles;
+use super::trace::Trace;
 use super::workqueue::WorkQueue;
 
-/// Load files and calculate sha1.
+/// Load files and calculate sha1 or run another CPU kernel on them.
 pub struct Hasher {
     buf: Vec<u8>,
     off: usize,
     cpu_ratio: f64,
+    cpu_kernel: CpuKernel,
     fake_cpu_load_time_per_byte: f64,
+    scratch: Vec<u8>,
+    table: Vec<usize>,
 }
 
 impl Hasher {
-    pub fn new(cpu_ratio: f64, fake_cpu_load_time_per_byte: f64) -> Self {
+    pub fn new(cpu_ratio: f64, cpu_kernel: CpuKernel, fake_cpu_load_time_per_byte: f64) -> Self {
         Hasher {
             buf: vec![],
             off: 0,
             cpu_ratio,
+            cpu_kernel,
             fake_cpu_load_time_per_byte,
+            scratch: vec![],
+            table: vec![],
         }
     }
 
@@ -101,10 +115,10 @@ impl Hasher {
         }
     }
 
-    /// Calculates sha1 of self.buf * self.cpu_ratio.  Hasher exists
-    /// to waste cpu and io and self.cpu_ratio controls the ratio
-    /// between cpu and io.
-    pub fn sha1(&mut self) -> Digest {
+    /// Runs the CPU kernel on self.buf * self.cpu_ratio and returns the
+    /// digest. Hasher exists to waste cpu and io and self.cpu_ratio
+    /// controls the ratio between cpu and io.
+    pub fn digest(&mut self) -> Digest {
         if self.fake_cpu_load_time_per_byte > 0.0 {
             // Sleep for the equivalent duration instead of actually calculating SHA1.
             sleep(Duration::from_secs_f64(
@@ -115,6 +129,7 @@ impl Hasher {
 
         let mut repeat = self.cpu_ratio;
         let mut hasher = Sha1::new();
+        let mut acc = 0u64;
         let mut nr_bytes = 0;
 
         while repeat > 0.01 {
@@ -126,13 +141,45 @@ impl Hasher {
                 repeat -= 1.0;
             }
             nr_bytes += self.buf.len();
-            hasher.update(&self.buf);
+            let buf = &self.buf;
+            acc = match self.cpu_kernel {
+                CpuKernel::Sha1 => {
+                    hasher.update(buf);
+                    acc
+                }
+                CpuKernel::Compress => cpu_kernel::compress(buf, &mut self.table, acc),
+                CpuKernel::Memcpy => cpu_kernel::memcpy(buf, &mut self.scratch, acc),
+                CpuKernel::PtrChase => cpu_kernel::ptr_chase(buf, acc),
+                CpuKernel::Float => cpu_kernel::float(buf, acc),
+            };
+        }
+        trace!(
+            "processed {} bytes, cpu_ratio={} cpu_kernel={:?}",
+            nr_bytes,
+            self.cpu_ratio,
+            self.cpu_kernel
+        );
+        if self.cpu_kernel != CpuKernel::Sha1 {
+            hasher.update(&acc.to_ne_bytes());
         }
-        trace!("hashed {} bytes, cpu_ratio={}", nr_bytes, self.cpu_ratio);
         hasher.digest()
     }
 }
 
+/// Returns the calling thread's consumed CPU time in seconds and the number
+/// of major faults it took.
+fn thread_rusage() -> (f64, u64) {
+    let mut ru: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut ru) } < 0 {
+        return (0.0, 0);
+    }
+    let tv_secs = |tv: libc::timeval| tv.tv_sec as f64 + tv.tv_usec as f64 / 1_000_000.0;
+    (
+        tv_secs(ru.ru_utime) + tv_secs(ru.ru_stime),
+        ru.ru_majflt as u64,
+    )
+}
+
 /// Normal distribution with clamps. The portion of the distribution which is
 /// cut off by the clamps uniformly raise the distribution within the clamps
 /// such that it gradually transforms into uniform distribution as stdev
@@ -165,19 +212,195 @@ impl ClampedNormal {
     }
 }
 
+/// Access address sampler following `Params::addr_dist`. Samples are in
+/// [-1.0, 1.0] where the magnitude is the distance from the hottest spot and
+/// the sign selects the side, which is what rel_to_file_page() and
+/// AnonArea::rel_to_page_idx() expect.
+enum AddrSampler {
+    Normal(ClampedNormal),
+    Zipf(Zipf<f64>),
+    Uniform,
+    HotSet { frac: f64, prob: f64 },
+}
+
+impl AddrSampler {
+    /// Zipf ranks are mapped to this many equal slices of the footprint.
+    const ZIPF_NR_SLICES: u64 = 1 << 16;
+
+    fn new(params: &Params, stdev_ratio: f64) -> Self {
+        match params.addr_dist {
+            AddrDist::Normal => Self::Normal(ClampedNormal::new(0.0, stdev_ratio, -1.0, 1.0)),
+            AddrDist::Zipf => {
+                Self::Zipf(Zipf::new(Self::ZIPF_NR_SLICES, params.addr_zipf_exp).unwrap())
+            }
+            AddrDist::Uniform => Self::Uniform,
+            AddrDist::HotSet => Self::HotSet {
+                frac: params.addr_hot_frac,
+                prob: params.addr_hot_prob,
+            },
+        }
+    }
+
+    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        let mag = match self {
+            Self::Normal(normal) => return normal.sample(rng),
+            Self::Zipf(zipf) => {
+                (zipf.sample(rng) - 1.0 + rng.gen::<f64>()) / Self::ZIPF_NR_SLICES as f64
+            }
+            Self::Uniform => rng.gen::<f64>(),
+            Self::HotSet { frac, prob } => {
+                if rng.gen::<f64>() < *prob {
+                    rng.gen::<f64>() * frac
+                } else {
+                    frac + rng.gen::<f64>() * (1.0 - frac)
+                }
+            }
+        };
+        if rng.gen::<bool>() {
+            mag
+        } else {
+            -mag
+        }
+    }
+}
+
+/// Latency percentile accumulator for a control period.
+struct LatAcc {
+    min: f64,
+    max: f64,
+    ckms: CKMS<f64>,
+}
+
+impl LatAcc {
+    const CKMS_ERROR: f64 = 0.001;
+
+    fn new() -> Self {
+        Self {
+            min: std::f64::MAX,
+            max: 0.0,
+            ckms: CKMS::<f64>::new(Self::CKMS_ERROR),
+        }
+    }
+
+    fn insert(&mut self, dur: f64) {
+        self.min = self.min.min(dur);
+        self.max = self.max.max(dur);
+        self.ckms.insert(dur);
+    }
+
+    fn latencies(&self, ctl_pct: f64) -> Latencies {
+        Latencies {
+            min: self.min,
+            p01: self.ckms.query(0.01).unwrap().1,
+            p05: self.ckms.query(0.05).unwrap().1,
+            p10: self.ckms.query(0.10).unwrap().1,
+            p16: self.ckms.query(0.16).unwrap().1,
+            p50: self.ckms.query(0.50).unwrap().1,
+            p84: self.ckms.query(0.84).unwrap().1,
+            p90: self.ckms.query(0.90).unwrap().1,
+            p95: self.ckms.query(0.95).unwrap().1,
+            p99: self.ckms.query(0.99).unwrap().1,
+            p99_9: self.ckms.query(0.999).unwrap().1,
+            p99_99: self.ckms.query(0.9999).unwrap().1,
+            p99_999: self.ckms.query(0.99999).unwrap().1,
+            max: self.max,
+            ctl: self.ckms.query(ctl_pct).unwrap().1,
+        }
+    }
+}
+
+/// Per-class access size distributions and latency tracking. If
+/// `Params::classes` is empty, there's a single implicit class built from the
+/// top-level params which isn't tracked separately.
+struct ReqClassState {
+    name: String,
+    file_size_normal: ClampedNormal,
+    anon_size_normal: ClampedNormal,
+    cpu_ratio: f64,
+    lat_target_pct: f64,
+    lat_target: f64,
+
+    lat_acc: LatAcc,
+    lat: Latencies,
+    lat_hist: LatHist,
+    lat_hist_cum: LatHist,
+    nr_done: u64,
+    last_nr_done: u64,
+    rps: f64,
+}
+
+impl ReqClassState {
+    /// Carry over the latency tracking and counters of the class of the
+    /// same name so that they survive params updates.
+    fn inherit_stats(&mut self, old: &mut ReqClassState) {
+        self.lat_acc = std::mem::replace(&mut old.lat_acc, LatAcc::new());
+        self.lat = std::mem::take(&mut old.lat);
+        self.lat_hist = std::mem::take(&mut old.lat_hist);
+        self.lat_hist_cum = std::mem::take(&mut old.lat_hist_cum);
+        self.nr_done = old.nr_done;
+        self.last_nr_done = old.last_nr_done;
+        self.rps = old.rps;
+    }
+}
+
 /// Commands from user to the dispatch thread.
 pub enum DispatchCmd {
     SetParams(Params),
     GetStat(Sender<Stat>),
     FillAnon,
+    HashReq(HashReq, Sender<HashResp>),
+}
+
+/// A request received through `Submitter::submit()` waiting for a worker.
+struct ExtReq {
+    at: Instant,
+    req: HashReq,
+    resp_tx: Sender<HashResp>,
+}
+
+/// External requests may sleep up to this many times the latency target.
+const EXT_SLEEP_MAX_LAT_TARGETS: f64 = 10.0;
+
+/// Upper bounds of the sizes and sleep duration which external requests may
+/// specify, see `DispatchThread::ext_req_limits()`.
+struct ExtReqLimits {
+    file_size: usize,
+    anon_size: usize,
+    sleep: f64,
+}
+
+impl ExtReqLimits {
+    fn check(&self, req: &HashReq) -> Result<()> {
+        if let Some(v) = req.file_size {
+            if v > self.file_size {
+                bail!("file_size {} out of range [0, {}]", v, self.file_size);
+            }
+        }
+        if let Some(v) = req.anon_size {
+            if v > self.anon_size {
+                bail!("anon_size {} out of range [0, {}]", v, self.anon_size);
+            }
+        }
+        if let Some(v) = req.sleep {
+            // Also catches NaN.
+            if !(v >= 0.0 && v <= self.sleep) {
+                bail!("sleep {} out of range [0, {}]", v, self.sleep);
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Hasher worker thread's completion for the dispatch thread.
 struct HashCompletion {
+    class: String,
     digest: Digest,
+    queued_at: Instant,
     started_at: Instant,
+    breakdown: LatBreakdown,
     file_dist: Vec<u64>,
     anon_dist: Vec<u64>,
+    ext_resp: Option<(u64, Sender<HashResp>)>,
 }
 
 struct HasherThread {
@@ -188,22 +411,28 @@ struct HasherThread {
     file_max_frac: f64,
     file_frac: f64,
     file_nr_chunks: usize,
-    file_addr_stdev_ratio: f64,
+    file_addr_sampler: AddrSampler,
+    addr_offset: f64,
     file_addr_frac: f64,
     file_write_frac: f64,
 
     anon_area: Arc<RwLock<AnonArea>>,
     anon_nr_chunks: usize,
-    anon_addr_stdev_ratio: f64,
+    anon_addr_sampler: AddrSampler,
     anon_addr_frac: f64,
     anon_write_frac: f64,
 
     sleep_dur: f64,
     cpu_ratio: f64,
+    cpu_kernel: CpuKernel,
+    rng_seed: u64,
     fake_cpu_load_time_per_byte: f64,
 
+    class: String,
     cmpl_tx: Sender<HashCompletion>,
+    ext_resp: Option<(u64, Sender<HashResp>)>,
 
+    queued_at: Instant,
     started_at: Instant,
     file_dist_slots: usize,
     anon_dist_slots: usize,
@@ -212,6 +441,8 @@ struct HasherThread {
 impl HasherThread {
     /// Translate [-1.0, 1.0] `rel` to page index. Similar to
     /// AnonArea::rel_to_page().
+    /// The hot spot is shifted by `addr_offset` of the footprint, wrapping
+    /// around at the end.
     fn rel_to_file_page(&self, rel: f64) -> u64 {
         let frac = self.mem_frac * self.file_frac / self.file_max_frac;
         let nr_pages = ((self.tf.size as f64 * frac) as u64).min(self.tf.size) / *PAGE_SIZE as u64;
@@ -220,7 +451,8 @@ impl HasherThread {
         if rel.is_sign_negative() {
             pg_idx += 1;
         }
-        pg_idx.min(nr_pages - 1)
+        pg_idx = pg_idx.min(nr_pages - 1);
+        (pg_idx + (nr_pages as f64 * self.addr_offset) as u64) % nr_pages
     }
 
     fn file_page_to_idx_off(&self, page: u64) -> (u64, u64) {
@@ -254,7 +486,19 @@ impl HasherThread {
     }
 
     fn run(self) {
-        let mut rng = SmallRng::from_entropy();
+        let mut bd = LatBreakdown::default();
+        let mut at = Instant::now();
+        bd.queue = at.duration_since(self.queued_at).as_secs_f64();
+
+        // Returns the duration since the last call.
+        let mut lap = || {
+            let now = Instant::now();
+            let dur = now.duration_since(at).as_secs_f64();
+            at = now;
+            dur
+        };
+
+        let mut rng = SmallRng::seed_from_u64(self.rng_seed);
 
         let mut file_dist = Vec::<u64>::new();
         let mut anon_dist = Vec::<u64>::new();
@@ -264,12 +508,15 @@ impl HasherThread {
         let rw_uniform = Uniform::new_inclusive(0.0, 1.0);
 
         // Load hash input files.
-        let file_addr_normal = ClampedNormal::new(0.0, self.file_addr_stdev_ratio, -1.0, 1.0);
 
         trace!("hasher::run(): cpu_ratio={:.2}", self.cpu_ratio);
-        let mut rdh = Hasher::new(self.cpu_ratio, self.fake_cpu_load_time_per_byte);
+        let mut rdh = Hasher::new(
+            self.cpu_ratio,
+            self.cpu_kernel,
+            self.fake_cpu_load_time_per_byte,
+        );
         for _ in 0..self.file_nr_chunks {
-            let rel = file_addr_normal.sample(&mut rng) * self.file_addr_frac;
+            let rel = self.file_addr_sampler.sample(&mut rng) * self.file_addr_frac;
             let page = self.rel_to_file_page(rel);
             let (file_idx, file_off) = self.file_page_to_idx_off(page);
             let path = self.tf.path(file_idx);
@@ -286,16 +533,21 @@ impl HasherThread {
                 Err(e) => error!("Failed to load {:?}:{} ({:?})", &path, file_off, &e),
             }
         }
+        bd.file_io = lap();
         sleep(Duration::from_secs_f64(self.sleep_dur / 3.0));
+        bd.sleep += lap();
 
-        // Generate anonymous accesses.
+        // Generate anonymous accesses. Major faults here are swap-ins.
+        let (_, majflt_before) = thread_rusage();
         let aa = self.anon_area.read().unwrap();
-        let anon_addr_normal = ClampedNormal::new(0.0, self.anon_addr_stdev_ratio, -1.0, 1.0);
 
         for _ in 0..self.anon_nr_chunks {
-            let rel = anon_addr_normal.sample(&mut rng) * self.anon_addr_frac;
-            let page_base =
-                AnonArea::rel_to_page_idx(rel, aa.size() - (self.chunk_pages - 1) * *PAGE_SIZE);
+            let rel = self.anon_addr_sampler.sample(&mut rng) * self.anon_addr_frac;
+            let base_size = aa.size() - (self.chunk_pages - 1) * *PAGE_SIZE;
+            let base_pages = base_size / *PAGE_SIZE;
+            let page_base = (AnonArea::rel_to_page_idx(rel, base_size)
+                + (base_pages as f64 * self.addr_offset) as usize)
+                % base_pages;
             let is_write =
                 self.anon_write_frac != 0.0 && rw_uniform.sample(&mut rng) <= self.anon_write_frac;
 
@@ -311,18 +563,33 @@ impl HasherThread {
             }
             Self::anon_dist_count(&mut anon_dist, page_base, self.chunk_pages, &aa);
         }
+        let (cpu_before, majflt_after) = thread_rusage();
+        bd.anon = lap();
+        bd.anon_majflt = majflt_after.saturating_sub(majflt_before) as f64;
         sleep(Duration::from_secs_f64(self.sleep_dur / 3.0));
-
-        // Calculate sha1 and signal completion.
-        let digest = rdh.sha1();
+        bd.sleep += lap();
+
+        // Calculate sha1 and signal completion. Time not spent on CPU while
+        // hashing indicates CPU contention.
+        let digest = rdh.digest();
+        let (cpu_after, _) = thread_rusage();
+        bd.hash = lap();
+        if self.fake_cpu_load_time_per_byte == 0.0 {
+            bd.hash_cpu_wait = (bd.hash - (cpu_after - cpu_before)).max(0.0);
+        }
         sleep(Duration::from_secs_f64(self.sleep_dur / 3.0));
+        bd.sleep += lap();
 
         self.cmpl_tx
             .send(HashCompletion {
+                class: self.class.clone(),
                 digest,
+                queued_at: self.queued_at,
                 started_at: self.started_at,
+                breakdown: bd,
                 file_dist,
                 anon_dist,
+                ext_resp: self.ext_resp,
             })
             .unwrap();
     }
@@ -343,27 +610,67 @@ struct DispatchThread {
     cmpl_tx: Sender<HashCompletion>,
     cmpl_rx: Receiver<HashCompletion>,
 
-    // Hash input file and anon area access patterns.
-    file_size_normal: ClampedNormal,
+    // Request classes and their file and anon area access patterns.
+    classes: Vec<ReqClassState>,
+    class_dist: Option<WeightedIndex<f64>>,
     anon_area: Arc<RwLock<AnonArea>>,
-    anon_size_normal: ClampedNormal,
     sleep_normal: ClampedNormal,
 
     // Latency percentile calculation.
-    lat_min: f64,
-    lat_max: f64,
-    ckms: CKMS<f64>,
-    ckms_at: Instant,
+    lat_acc: LatAcc,
+    lat_acc_at: Instant,
+    queue_lat_acc: LatAcc,
+    svc_lat_acc: LatAcc,
+    lat_hist: LatHist,
+    lat_hist_cum: LatHist,
+    log_write_lat_cum: LatHist,
+    lat_breakdown_acc: LatBreakdown,
+
+    // Trace being replayed.
+    trace: Option<Trace>,
+    trace_at: Instant,
+    trace_progress: f64,
+    trace_loops: u64,
+
+    // Load pattern and random spikes.
+    pattern_at: Instant,
+    next_spike_at: Option<Instant>,
+    spike_until: Option<Instant>,
+
+    // Hot spot drift.
+    drift_at: Instant,
+    next_drift_jump_at: Option<Instant>,
+    addr_offset: f64,
+
+    // Open-loop arrivals waiting for workers.
+    arrivals: VecDeque<Instant>,
+    next_arrival_at: Option<Instant>,
+    rng: SmallRng,
+
+    // Request parameters are sampled from a separate rng so that the Nth
+    // request is the same across seeded runs regardless of timing.
+    req_rng: SmallRng,
+
+    // Requests from external clients. When enabled, no requests are
+    // generated internally.
+    ext_mode: bool,
+    ext_reqs: VecDeque<ExtReq>,
 
     // Latency and rps PID controllers.
     lat_pid: Pid<f64>,
     rps_pid: Pid<f64>,
 
     // Runtime parameters.
+    rps_target: f64,
+    size_ratio: f64,
     lat: Latencies,
+    queue_lat: Latencies,
+    svc_lat: Latencies,
+    lat_breakdown: LatBreakdown,
     concurrency_max: f64,
     concurrency: f64,
     nr_in_flight: u32,
+    nr_dropped: u64,
     nr_done: u64,
     last_nr_done: u64,
     rps: f64,
@@ -377,7 +684,6 @@ struct DispatchThread {
 
 impl DispatchThread {
     const WQ_IDLE_TIMEOUT: f64 = 60.0;
-    const CKMS_ERROR: f64 = 0.001;
 
     fn anon_total(max_size: u64, params: &Params) -> usize {
         (max_size as f64
@@ -386,12 +692,12 @@ impl DispatchThread {
                 .min(1.0)) as usize
     }
 
-    fn file_size_normal(params: &Params) -> ClampedNormal {
-        let size_mean = params.file_size_mean as f64;
-        let size_stdev = size_mean * params.file_size_stdev_ratio;
+    fn file_size_normal(name: &str, size_mean: f64, stdev_ratio: f64) -> ClampedNormal {
+        let size_stdev = size_mean * stdev_ratio;
 
         debug!(
-            "file: size_mean={} size_stdev={}",
+            "file{}: size_mean={} size_stdev={}",
+            name,
             size_mean.round(),
             size_stdev.round(),
         );
@@ -404,12 +710,13 @@ impl DispatchThread {
         )
     }
 
-    fn anon_size_normal(params: &Params) -> ClampedNormal {
-        let size_mean = (params.file_size_mean as f64 * params.anon_size_ratio as f64).max(0.0);
-        let size_stdev = size_mean * params.anon_size_stdev_ratio;
+    fn anon_size_normal(name: &str, size_mean: f64, stdev_ratio: f64) -> ClampedNormal {
+        let size_mean = size_mean.max(0.0);
+        let size_stdev = size_mean * stdev_ratio;
 
         debug!(
-            "anon: size_mean={} size_stdev={}",
+            "anon{}: size_mean={} size_stdev={}",
+            name,
             size_mean.round(),
             size_stdev.round(),
         );
@@ -422,6 +729,89 @@ impl DispatchThread {
         )
     }
 
+    /// Per-class stats are keyed by name, not position, across params updates.
+    fn inherit_class_stats(classes: &mut [ReqClassState], old: &mut [ReqClassState]) {
+        for rc in classes.iter_mut() {
+            if let Some(orc) = old.iter_mut().find(|orc| orc.name == rc.name) {
+                rc.inherit_stats(orc);
+            }
+        }
+    }
+
+    fn req_classes(params: &Params) -> (Vec<ReqClassState>, Option<WeightedIndex<f64>>) {
+        let file_mean = params.file_size_mean as f64;
+        let anon_mean = file_mean * params.anon_size_ratio;
+
+        let new_state = |name: String,
+                         file_size_normal,
+                         anon_size_normal,
+                         cpu_ratio,
+                         lat_target_pct,
+                         lat_target| ReqClassState {
+            name,
+            file_size_normal,
+            anon_size_normal,
+            cpu_ratio,
+            lat_target_pct,
+            lat_target,
+            lat_acc: LatAcc::new(),
+            lat: Default::default(),
+            lat_hist: Default::default(),
+            lat_hist_cum: Default::default(),
+            nr_done: 0,
+            last_nr_done: 0,
+            rps: 0.0,
+        };
+
+        if params.classes.is_empty() {
+            return (
+                vec![new_state(
+                    "".into(),
+                    Self::file_size_normal("", file_mean, params.file_size_stdev_ratio),
+                    Self::anon_size_normal("", anon_mean, params.anon_size_stdev_ratio),
+                    params.cpu_ratio,
+                    params.lat_target_pct,
+                    params.lat_target,
+                )],
+                None,
+            );
+        }
+
+        let classes = params
+            .classes
+            .iter()
+            .map(|rc| {
+                let tag = format!("[{}]", &rc.name);
+                new_state(
+                    rc.name.clone(),
+                    Self::file_size_normal(
+                        &tag,
+                        file_mean * rc.file_size_ratio,
+                        rc.file_size_stdev_ratio,
+                    ),
+                    Self::anon_size_normal(
+                        &tag,
+                        anon_mean * rc.anon_size_ratio,
+                        rc.anon_size_stdev_ratio,
+                    ),
+                    params.cpu_ratio * rc.cpu_ratio,
+                    rc.lat_target_pct,
+                    rc.lat_target,
+                )
+            })
+            .collect();
+        let class_dist = WeightedIndex::new(params.classes.iter().map(|rc| rc.share.max(0.0)))
+            .map_err(|e| {
+                warn!(
+                    "Invalid request class shares, using the first class ({})",
+                    &e
+                )
+            })
+            .ok();
+
+        (classes, class_dist)
+    }
+
     fn sleep_normal(params: &Params) -> ClampedNormal {
         let sleep_mean = params.sleep_mean;
         let sleep_stdev = params.sleep_mean * params.sleep_stdev_ratio;
@@ -451,6 +841,14 @@ impl DispatchThread {
 
     fn verify_params(&mut self) {
         let file_max_frac = self.tf.size as f64 / self.max_size as f64;
+        if self.params.trace_stretch <= 0.0 {
+            warn!(
+                "trace_stretch {} is not positive, using 1.0",
+                self.params.trace_stretch
+            );
+            self.params.trace_stretch = 1.0;
+        }
+
         if self.params.file_frac > file_max_frac {
             warn!(
                 "file_frac {:.2} is higher than maximum {:.2} allowed by testfiles, see --file_max_frac",
@@ -459,23 +857,56 @@ impl DispatchThread {
             self.params.file_frac = file_max_frac;
         }
 
+        if self.params.addr_zipf_exp.is_nan() || self.params.addr_zipf_exp < 0.0 {
+            warn!(
+                "addr_zipf_exp {} is invalid, using 1.0",
+                self.params.addr_zipf_exp
+            );
+            self.params.addr_zipf_exp = 1.0;
+        }
+        if self.params.addr_hot_frac.is_nan()
+            || self.params.addr_hot_frac <= 0.0
+            || self.params.addr_hot_frac > 1.0
+        {
+            warn!(
+                "addr_hot_frac {} is out of range (0.0, 1.0], using default",
+                self.params.addr_hot_frac
+            );
+            self.params.addr_hot_frac = Params::default().addr_hot_frac;
+        }
+        self.params.addr_hot_prob = self.params.addr_hot_prob.max(0.0).min(1.0);
+
         self.file_dist = vec![];
         self.anon_dist = vec![];
         self.file_dist.resize(self.params.acc_dist_slots, 0);
         self.anon_dist.resize(self.params.acc_dist_slots, 0);
+
+        // Class names are used as report keys and must be unique.
+        let mut names = HashSet::new();
+        for (idx, rc) in self.params.classes.iter_mut().enumerate() {
+            if rc.name.is_empty() || !names.insert(rc.name.clone()) {
+                let name = format!("class{}", idx);
+                warn!(
+                    "Request class name {:?} is empty or duplicate, renaming to {:?}",
+                    &rc.name, &name
+                );
+                rc.name = name;
+                names.insert(rc.name.clone());
+            }
+        }
     }
 
     pub fn new(
         max_size: u64,
         tf: TestFiles,
         params: Params,
-        anon_comp: f64,
+        anon_area: Arc<RwLock<AnonArea>>,
         logger: Option<Logger>,
+        ext_mode: bool,
         cmd_rx: Receiver<DispatchCmd>,
     ) -> Self {
         let (cmpl_tx, cmpl_rx) = channel::unbounded::<HashCompletion>();
         let (lat_pid, rps_pid) = Self::pid_controllers(&params);
-        let anon_total = Self::anon_total(max_size, &params);
         let now = Instant::now();
 
         let mut dt = Self {
@@ -487,22 +918,52 @@ impl DispatchThread {
 
             cmpl_tx,
             cmpl_rx,
-            file_size_normal: Self::file_size_normal(&params),
-            anon_area: Arc::new(RwLock::new(AnonArea::new(anon_total, anon_comp))),
-            anon_size_normal: Self::anon_size_normal(&params),
+            classes: vec![],
+            class_dist: None,
+            anon_area,
             sleep_normal: Self::sleep_normal(&params),
 
-            lat_min: std::f64::MAX,
-            lat_max: 0.0,
-            ckms: CKMS::<f64>::new(Self::CKMS_ERROR),
-            ckms_at: now,
+            lat_acc: LatAcc::new(),
+            lat_acc_at: now,
+            queue_lat_acc: LatAcc::new(),
+            svc_lat_acc: LatAcc::new(),
+            lat_hist: Default::default(),
+            lat_hist_cum: Default::default(),
+            log_write_lat_cum: Default::default(),
+            lat_breakdown_acc: Default::default(),
+
+            trace: None,
+            trace_at: now,
+            trace_progress: 0.0,
+            trace_loops: 0,
+
+            pattern_at: now,
+            next_spike_at: None,
+            spike_until: None,
+
+            drift_at: now,
+            next_drift_jump_at: None,
+            addr_offset: 0.0,
+
+            arrivals: VecDeque::new(),
+            next_arrival_at: None,
+            rng: seeded_rng(params.seed, "dispatch", 0),
+            req_rng: seeded_rng(params.seed, "requests", 0),
+            ext_mode,
+            ext_reqs: VecDeque::new(),
             lat_pid,
             rps_pid,
 
+            rps_target: params.rps_target as f64,
+            size_ratio: 1.0,
             lat: Latencies::default(),
+            queue_lat: Latencies::default(),
+            svc_lat: Latencies::default(),
+            lat_breakdown: LatBreakdown::default(),
             concurrency_max: params.concurrency_max as f64,
             concurrency: (nr_cpus() as f64 / 2.0).max(1.0),
             nr_in_flight: 0,
+            nr_dropped: 0,
             nr_done: 0,
             last_nr_done: 0,
             rps: 0.0,
@@ -518,9 +979,134 @@ impl DispatchThread {
             params,
         };
         dt.verify_params();
+        let (classes, class_dist) = Self::req_classes(&dt.params);
+        dt.classes = classes;
+        dt.class_dist = class_dist;
+        dt.load_trace();
         dt
     }
 
+    fn load_trace(&mut self) {
+        self.trace = None;
+        self.trace_at = Instant::now();
+        self.trace_progress = 0.0;
+        self.trace_loops = 0;
+
+        if self.params.trace.is_empty() {
+            return;
+        }
+        match Trace::load(&self.params.trace) {
+            Ok(trace) => {
+                info!(
+                    "Replaying trace {:?} ({} long)",
+                    &self.params.trace,
+                    format_duration(trace.duration() * self.params.trace_stretch)
+                );
+                self.trace = Some(trace);
+            }
+            Err(e) => error!("Failed to load trace, using rps_target ({:#})", &e),
+        }
+    }
+
+    /// Base (rps, size_ratio) from the trace or the static rps_target.
+    fn trace_target(&mut self, now: Instant) -> (f64, f64) {
+        let trace = match self.trace.as_ref() {
+            Some(v) => v,
+            None => return (self.params.rps_target as f64, 1.0),
+        };
+
+        let elapsed = now.duration_since(self.trace_at).as_secs_f64() / self.params.trace_stretch;
+        let dur = trace.duration();
+        let pos = if dur <= 0.0 {
+            0.0
+        } else if self.params.trace_loop {
+            self.trace_loops = (elapsed / dur) as u64;
+            elapsed % dur
+        } else {
+            elapsed.min(dur)
+        };
+
+        let (rps, size_ratio) = trace.at(pos);
+        self.trace_progress = if dur > 0.0 { pos / dur } else { 1.0 };
+        (rps * self.params.trace_rps_ratio, size_ratio)
+    }
+
+    /// RPS multiplier from the periodic load pattern and random spikes.
+    fn load_factor(&mut self, now: Instant) -> f64 {
+        let params = &self.params;
+        let mut factor = 1.0;
+
+        if params.load_amplitude > 0.0 && params.load_period > 0.0 {
+            let elapsed = now.duration_since(self.pattern_at).as_secs_f64();
+            let phase = (elapsed / params.load_period).fract();
+            factor += params.load_amplitude * params.load_pattern.value(phase);
+        }
+
+        if params.spike_ratio > 0.0 && params.spike_interval > 0.0 {
+            // Spikes arrive as a Poisson process.
+            let intv_dist = Exp::new(1.0 / params.spike_interval).unwrap();
+            let mut next_at = match self.next_spike_at {
+                Some(v) => v,
+                None => now + Duration::from_secs_f64(intv_dist.sample(&mut self.rng)),
+            };
+            if now >= next_at {
+                self.spike_until = Some(next_at + Duration::from_secs_f64(params.spike_dur));
+                next_at += Duration::from_secs_f64(intv_dist.sample(&mut self.rng));
+            }
+            self.next_spike_at = Some(next_at);
+
+            if let Some(until) = self.spike_until {
+                if now < until {
+                    factor *= 1.0 + params.spike_ratio;
+                }
+            }
+        } else {
+            self.next_spike_at = None;
+            self.spike_until = None;
+        }
+
+        factor.max(0.0)
+    }
+
+    /// Update the current RPS target and size ratio.
+    fn update_rps_target(&mut self, now: Instant) {
+        let (rps, size_ratio) = self.trace_target(now);
+        self.rps_target = rps * self.load_factor(now);
+        self.size_ratio = size_ratio;
+    }
+
+    /// Move the hot spot of the address distributions according to
+    /// `Params::addr_drift`.
+    fn update_addr_offset(&mut self, now: Instant) {
+        let params = &self.params;
+        let dur = now.duration_since(self.drift_at).as_secs_f64();
+        self.drift_at = now;
+
+        match params.addr_drift {
+            AddrDrift::Static => {
+                self.addr_offset = 0.0;
+                self.next_drift_jump_at = None;
+            }
+            AddrDrift::Rotate => {
+                self.addr_offset =
+                    (self.addr_offset + dur * params.addr_drift_rate).rem_euclid(1.0);
+                self.next_drift_jump_at = None;
+            }
+            AddrDrift::Jump if params.addr_drift_interval > 0.0 => {
+                let intv = Duration::from_secs_f64(params.addr_drift_interval);
+                let mut next_at = self.next_drift_jump_at.unwrap_or(now + intv);
+                if now >= next_at {
+                    self.addr_offset = self.rng.gen::<f64>();
+                    while next_at <= now {
+                        next_at += intv;
+                    }
+                }
+                self.next_drift_jump_at = Some(next_at);
+            }
+            AddrDrift::Jump => self.next_drift_jump_at = None,
+        }
+    }
+
     fn params_updated(&mut self) {
         self.fake_cpu_load_time_per_byte = match self.params.fake_cpu_load {
             true => {
@@ -545,17 +1131,41 @@ impl DispatchThread {
     fn update_params(&mut self, new_params: Params) {
         let old_anon_total = Self::anon_total(self.max_size, &self.params);
         let new_anon_total = Self::anon_total(self.max_size, &new_params);
+        let trace_changed = new_params.trace != self.params.trace;
+        let seed_changed = new_params.seed != self.params.seed;
         self.params = new_params;
         self.verify_params();
+        if trace_changed {
+            self.load_trace();
+        }
+        if seed_changed {
+            self.rng = seeded_rng(self.params.seed, "dispatch", 0);
+            self.req_rng = seeded_rng(self.params.seed, "requests", 0);
+            self.anon_area.write().unwrap().set_seed(self.params.seed);
+        }
         let params = &self.params;
 
-        self.file_size_normal = Self::file_size_normal(params);
-        self.anon_size_normal = Self::anon_size_normal(params);
+        let (mut classes, class_dist) = Self::req_classes(params);
+        Self::inherit_class_stats(&mut classes, &mut self.classes);
+        self.classes = classes;
+        self.class_dist = class_dist;
         self.sleep_normal = Self::sleep_normal(params);
         let (lp, rp) = Self::pid_controllers(params);
         self.lat_pid = lp;
         self.rps_pid = rp;
 
+        // Switching back to closed-loop discards the queued arrivals.
+        if !params.open_loop {
+            if !self.arrivals.is_empty() {
+                warn!(
+                    "Discarding {} queued open-loop requests",
+                    self.arrivals.len()
+                );
+            }
+            self.arrivals.clear();
+            self.next_arrival_at = None;
+        }
+
         if new_anon_total != old_anon_total {
             let mut aa = self.anon_area.write().unwrap();
             aa.resize(new_anon_total);
@@ -563,26 +1173,153 @@ impl DispatchThread {
 
         if let Some(logger) = self.logger.as_mut() {
             logger.set_padding(params.log_padding());
+            logger.set_cfg(LogCfg::from_params(params));
         }
 
         self.params_at = Instant::now();
         self.params_updated();
     }
 
+    /// Whether requests can queue up waiting for workers, in which case
+    /// concurrency is pinned at the max and queueing delay is tracked.
+    fn is_open_loop(&self) -> bool {
+        self.params.open_loop || self.ext_mode
+    }
+
+    /// External requests can't reach beyond the testfiles or the anon area
+    /// and may sleep up to `EXT_SLEEP_MAX_LAT_TARGETS` latency targets.
+    fn ext_req_limits(&self) -> ExtReqLimits {
+        ExtReqLimits {
+            file_size: self.tf.size as usize,
+            anon_size: Self::anon_total(self.max_size, &self.params),
+            sleep: EXT_SLEEP_MAX_LAT_TARGETS * self.params.lat_target,
+        }
+    }
+
+    fn queue_ext_req(&mut self, req: HashReq, resp_tx: Sender<HashResp>) {
+        let error = if !self.ext_mode {
+            "external requests not enabled".to_string()
+        } else if let Err(e) = self.ext_req_limits().check(&req) {
+            format!("{:#}", &e)
+        } else if self.ext_reqs.len() >= self.params.queue_max as usize {
+            self.nr_dropped += 1;
+            "queue full".to_string()
+        } else {
+            self.ext_reqs.push_back(ExtReq {
+                at: Instant::now(),
+                req,
+                resp_tx,
+            });
+            return;
+        };
+
+        let _ = resp_tx.send(HashResp {
+            id: req.id,
+            error: Some(error),
+            ..Default::default()
+        });
+    }
+
+    fn interarrival(&mut self, rate: f64) -> f64 {
+        match self.params.arrival_dist {
+            ArrivalDist::Poisson => Exp::new(rate).unwrap().sample(&mut self.rng),
+            ArrivalDist::Uniform => Uniform::new_inclusive(0.0, 2.0 / rate).sample(&mut self.rng),
+            ArrivalDist::Constant => 1.0 / rate,
+        }
+    }
+
+    /// Queue open-loop arrivals which should have happened by `now`.
+    fn queue_arrivals(&mut self, now: Instant) {
+        let rate = self.rps_target;
+        if rate <= 0.0 {
+            self.next_arrival_at = None;
+            return;
+        }
+
+        let mut at = self.next_arrival_at.unwrap_or(now);
+        while at <= now {
+            if self.arrivals.len() < self.params.queue_max as usize {
+                self.arrivals.push_back(at);
+            } else {
+                self.nr_dropped += 1;
+            }
+            at += Duration::from_secs_f64(self.interarrival(rate));
+        }
+        self.next_arrival_at = Some(at);
+    }
+
     fn launch_hashers(&mut self) {
-        // Fire off hash workers to fill up the target concurrency.
-        let mut rng = SmallRng::from_entropy();
+        // Fire off hash workers to fill up the target concurrency. In
+        // open-loop mode, start queued arrivals while workers are available.
+
+        loop {
+            let (queued_at, ext_req) = if self.ext_mode {
+                if self.nr_in_flight >= self.params.concurrency_max {
+                    break;
+                }
+                match self.ext_reqs.pop_front() {
+                    Some(er) => (er.at, Some(er)),
+                    None => break,
+                }
+            } else if self.params.open_loop {
+                if self.nr_in_flight >= self.params.concurrency_max {
+                    break;
+                }
+                match self.arrivals.pop_front() {
+                    Some(at) => (at, None),
+                    None => break,
+                }
+            } else {
+                if self.nr_in_flight >= self.concurrency as u32 {
+                    break;
+                }
+                (Instant::now(), None)
+            };
+            let req = ext_req.as_ref().map(|er| &er.req);
 
-        while self.nr_in_flight < self.concurrency as u32 {
+            // External requests may pick the class by name. The class
+            // indices change on params updates, so look it up here.
             let chunk_size = *PAGE_SIZE * self.params.chunk_pages;
+            let class = match (
+                req.and_then(|req| req.class.clone()),
+                self.class_dist.as_ref(),
+            ) {
+                (Some(name), _) => match self.classes.iter().position(|rc| rc.name == name) {
+                    Some(class) => class,
+                    None => {
+                        let er = ext_req.unwrap();
+                        let _ = er.resp_tx.send(HashResp {
+                            id: er.req.id,
+                            error: Some(format!("unknown class {:?}", name)),
+                            ..Default::default()
+                        });
+                        continue;
+                    }
+                },
+                (None, Some(dist)) => dist.sample(&mut self.req_rng),
+                (None, None) => 0,
+            };
+            let rc = &self.classes[class];
 
             // Determine file and anon access chunk counts. Indices are
             // determined by each hash worker to avoid overloading the
-            // dispatch thread.
-            let file_size = self.file_size_normal.sample(&mut rng).round() as usize;
+            // dispatch thread. External requests may specify the sizes.
+            let file_size = match req.and_then(|req| req.file_size) {
+                Some(v) => v,
+                None => (rc.file_size_normal.sample(&mut self.req_rng) * self.size_ratio).round()
+                    as usize,
+            };
             let file_nr_chunks = Integer::div_ceil(&file_size, &chunk_size).max(1);
-            let anon_size = self.anon_size_normal.sample(&mut rng).round() as usize;
+            let anon_size = match req.and_then(|req| req.anon_size) {
+                Some(v) => v,
+                None => (rc.anon_size_normal.sample(&mut self.req_rng) * self.size_ratio).round()
+                    as usize,
+            };
             let anon_nr_chunks = Integer::div_ceil(&anon_size, &chunk_size);
+            let sleep_dur = match req.and_then(|req| req.sleep) {
+                Some(v) => v,
+                None => self.sleep_normal.sample(&mut self.req_rng),
+            };
 
             let hasher_thread = HasherThread {
                 tf: self.tf.clone(),
@@ -592,22 +1329,34 @@ impl DispatchThread {
                 file_max_frac: self.tf.size as f64 / self.max_size as f64,
                 file_frac: self.params.file_frac,
                 file_nr_chunks,
-                file_addr_stdev_ratio: self.params.file_addr_stdev_ratio,
+                file_addr_sampler: AddrSampler::new(
+                    &self.params,
+                    self.params.file_addr_stdev_ratio,
+                ),
+                addr_offset: self.addr_offset,
                 file_addr_frac: self.file_addr_frac,
                 file_write_frac: self.params.file_write_frac,
 
                 anon_area: self.anon_area.clone(),
                 anon_nr_chunks,
-                anon_addr_stdev_ratio: self.params.anon_addr_stdev_ratio,
+                anon_addr_sampler: AddrSampler::new(
+                    &self.params,
+                    self.params.anon_addr_stdev_ratio,
+                ),
                 anon_addr_frac: self.anon_addr_frac,
                 anon_write_frac: self.params.anon_write_frac,
 
-                sleep_dur: self.sleep_normal.sample(&mut rng),
-                cpu_ratio: self.params.cpu_ratio,
+                sleep_dur,
+                cpu_ratio: rc.cpu_ratio,
+                cpu_kernel: self.params.cpu_kernel,
+                rng_seed: self.req_rng.gen(),
                 fake_cpu_load_time_per_byte: self.fake_cpu_load_time_per_byte,
 
+                class: rc.name.clone(),
                 cmpl_tx: self.cmpl_tx.clone(),
+                ext_resp: ext_req.map(|er| (er.req.id, er.resp_tx)),
 
+                queued_at,
                 started_at: Instant::now(),
                 file_dist_slots: self.file_dist.len(),
                 anon_dist_slots: self.anon_dist.len(),
@@ -620,37 +1369,47 @@ impl DispatchThread {
     }
 
     fn reset_lat_rps(&mut self, now: Instant) {
-        self.lat_min = std::f64::MAX;
-        self.lat_max = 0.0;
-        self.ckms_at = now;
-        self.ckms = CKMS::<f64>::new(Self::CKMS_ERROR);
+        self.lat_acc = LatAcc::new();
+        self.lat_acc_at = now;
+        self.queue_lat_acc = LatAcc::new();
+        self.svc_lat_acc = LatAcc::new();
+        self.lat_breakdown_acc = Default::default();
         self.last_nr_done = self.nr_done;
+        for rc in self.classes.iter_mut() {
+            rc.lat_acc = LatAcc::new();
+            rc.last_nr_done = rc.nr_done;
+        }
     }
 
     fn refresh_lat_rps(&mut self, now: Instant) -> bool {
-        let dur = now.duration_since(self.ckms_at);
+        let dur = now.duration_since(self.lat_acc_at);
         if dur.as_secs_f64() < self.params.control_period {
             return false;
         }
 
+        if !self.params.classes.is_empty() {
+            for rc in self.classes.iter_mut() {
+                rc.lat = match rc.nr_done > rc.last_nr_done {
+                    true => rc.lat_acc.latencies(rc.lat_target_pct),
+                    false => Default::default(),
+                };
+                rc.rps = (rc.nr_done - rc.last_nr_done) as f64 / dur.as_secs_f64();
+            }
+        }
+
         if self.nr_done > self.last_nr_done {
-            self.lat.min = self.lat_min;
-            self.lat.p01 = self.ckms.query(0.01).unwrap().1;
-            self.lat.p05 = self.ckms.query(0.05).unwrap().1;
-            self.lat.p10 = self.ckms.query(0.10).unwrap().1;
-            self.lat.p16 = self.ckms.query(0.16).unwrap().1;
-            self.lat.p50 = self.ckms.query(0.50).unwrap().1;
-            self.lat.p84 = self.ckms.query(0.84).unwrap().1;
-            self.lat.p90 = self.ckms.query(0.90).unwrap().1;
-            self.lat.p95 = self.ckms.query(0.95).unwrap().1;
-            self.lat.p99 = self.ckms.query(0.99).unwrap().1;
-            self.lat.p99_9 = self.ckms.query(0.999).unwrap().1;
-            self.lat.p99_99 = self.ckms.query(0.9999).unwrap().1;
-            self.lat.p99_999 = self.ckms.query(0.99999).unwrap().1;
-            self.lat.max = self.lat_max;
-            self.lat.ctl = self.ckms.query(self.params.lat_target_pct).unwrap().1;
+            self.lat = self.lat_acc.latencies(self.params.lat_target_pct);
+            if self.is_open_loop() {
+                self.queue_lat = self.queue_lat_acc.latencies(self.params.lat_target_pct);
+                self.svc_lat = self.svc_lat_acc.latencies(self.params.lat_target_pct);
+            }
+            self.lat_breakdown = self.lat_breakdown_acc.clone();
+            self.lat_breakdown /= (self.nr_done - self.last_nr_done) as f64;
         } else {
             self.lat = Default::default();
+            self.queue_lat = Default::default();
+            self.svc_lat = Default::default();
+            self.lat_breakdown = Default::default();
             if self.nr_in_flight > 0 {
                 warn!(
                     "No completion in {} with {} requests in flight, con={:.1}/{:.1}",
@@ -672,10 +1431,28 @@ impl DispatchThread {
     /// Two pid controllers work in conjunction to determine the concurrency
     /// level. The latency one caps the max concurrency to keep latency within
     /// the target. The rps one tries to converge on the target rps.
+    ///
+    /// With request classes, the latency controller follows whichever of the
+    /// top-level and per-class targets is exceeded the most.
+    ///
+    /// In open-loop mode, the arrival rate is fixed and concurrency is simply
+    /// capped at `Params::concurrency_max`.
     fn update_control(&mut self) {
-        let out = self
-            .lat_pid
-            .next_control_output(self.lat.ctl / self.params.lat_target);
+        if self.is_open_loop() {
+            self.concurrency_max = self.params.concurrency_max as f64;
+            self.concurrency = self.concurrency_max;
+            self.update_addr_fracs();
+            return;
+        }
+
+        let mut lat_ratio = self.lat.ctl / self.params.lat_target;
+        if !self.params.classes.is_empty() {
+            for rc in self.classes.iter().filter(|rc| rc.lat_target > 0.0) {
+                lat_ratio = lat_ratio.max(rc.lat.ctl / rc.lat_target);
+            }
+        }
+
+        let out = self.lat_pid.next_control_output(lat_ratio);
         let adj = out.output;
 
         // Negative adjustment means latency is in charge. concurrency_max
@@ -690,7 +1467,7 @@ impl DispatchThread {
 
         let adj = self
             .rps_pid
-            .next_control_output(self.rps / self.params.rps_target as f64)
+            .next_control_output(self.rps / self.rps_target.max(1.0))
             .output;
         self.concurrency = (self.concurrency * (1.0 + adj)).max(1.0);
 
@@ -707,10 +1484,14 @@ impl DispatchThread {
         // After sudden latency spikes, the integral term can keep rps at
         // minimum for an extended period of time. Reset integral term if
         // latency is lower than target.
-        if out.i.is_sign_negative() && (self.lat.ctl <= self.params.lat_target) {
+        if out.i.is_sign_negative() && lat_ratio <= 1.0 {
             self.lat_pid.reset_integral_term();
         }
 
+        self.update_addr_fracs();
+    }
+
+    fn update_addr_fracs(&mut self) {
         let rps_max = self.params.rps_max as f64;
         let file_base = self.params.file_addr_rps_base_frac;
         let anon_base = self.params.anon_addr_rps_base_frac;
@@ -719,7 +1500,7 @@ impl DispatchThread {
 
         debug!(
             "p50={:.1} p84={:.1} p90={:.1} p95={:.1} p99={:.1} ctl={:.1} rps={:.1} con={:.1}/{:.1} \
-             ffrac={:.2} aafrac={:.2}",
+             queued={} ffrac={:.2} aafrac={:.2}",
             self.lat.p50 * TO_MSEC,
             self.lat.p84 * TO_MSEC,
             self.lat.p90 * TO_MSEC,
@@ -729,6 +1510,7 @@ impl DispatchThread {
             self.rps,
             self.concurrency,
             self.concurrency_max,
+            self.arrivals.len(),
             self.file_addr_frac,
             self.anon_addr_frac,
         );
@@ -738,9 +1520,20 @@ impl DispatchThread {
         self.params_updated();
         loop {
             // Launch hashers to fill target concurrency.
+            self.update_rps_target(Instant::now());
+            self.update_addr_offset(Instant::now());
+            if self.params.open_loop && !self.ext_mode {
+                self.queue_arrivals(Instant::now());
+            }
             self.launch_hashers();
 
-            // Handle user commands and hasher completions.
+            // Wake up for the next open-loop arrival.
+            let arrival_timer = match self.next_arrival_at {
+                Some(at) if self.params.open_loop && !self.ext_mode => channel::at(at),
+                _ => channel::never(),
+            };
+
+            // Handle user commands, hasher completions and arrivals.
             select! {
                 recv(self.cmd_rx) -> cmd => {
                     match cmd {
@@ -752,14 +1545,51 @@ impl DispatchThread {
                             anon_dist.resize(self.params.acc_dist_slots, 0);
                             std::mem::swap(&mut self.file_dist, &mut file_dist);
                             std::mem::swap(&mut self.anon_dist, &mut anon_dist);
+                            let lat_hist = std::mem::take(&mut self.lat_hist);
+                            self.lat_hist_cum += &lat_hist;
+                            let log_write_lat = match self.logger.as_ref() {
+                                Some(logger) => logger.take_write_lat(),
+                                None => Default::default(),
+                            };
+                            self.log_write_lat_cum += &log_write_lat;
+
+                            let mut classes = BTreeMap::new();
+                            if !self.params.classes.is_empty() {
+                                for rc in self.classes.iter_mut() {
+                                    let lat_hist = std::mem::take(&mut rc.lat_hist);
+                                    rc.lat_hist_cum += &lat_hist;
+                                    classes.insert(rc.name.clone(), ClassStat {
+                                        rps: rc.rps,
+                                        nr_done: rc.nr_done,
+                                        lat_target: rc.lat_target,
+                                        lat_target_pct: rc.lat_target_pct,
+                                        lat: rc.lat.clone(),
+                                        lat_hist,
+                                        lat_hist_cum: rc.lat_hist_cum.clone(),
+                                    });
+                                }
+                            }
 
                             ch.send(Stat { lat: self.lat.clone(),
+                                           lat_hist,
+                                           lat_hist_cum: self.lat_hist_cum.clone(),
                                            rps: self.rps,
+                                           rps_target: self.rps_target,
+                                           trace_progress: self.trace_progress,
+                                           trace_loops: self.trace_loops,
                                            concurrency: self.concurrency,
                                            concurrency_max: self.concurrency_max,
                                            file_addr_frac: self.file_addr_frac,
                                            anon_addr_frac: self.anon_addr_frac,
+                                           addr_offset: self.addr_offset,
                                            nr_in_flight: self.nr_in_flight,
+                                           nr_queued: (self.arrivals.len() + self.ext_reqs.len()) as u32,
+                                           nr_dropped: self.nr_dropped,
+                                           queue_lat: self.queue_lat.clone(),
+                                           svc_lat: self.svc_lat.clone(),
+                                           lat_breakdown: self.lat_breakdown.clone(),
+                                           log_write_lat,
+                                           log_write_lat_cum: self.log_write_lat_cum.clone(),
                                            nr_done: self.nr_done,
                                            nr_workers: self.wq.nr_workers(),
                                            nr_idle_workers: self.wq.nr_idle_workers(),
@@ -767,9 +1597,14 @@ impl DispatchThread {
                                            file_dist,
                                            anon_size: self.anon_area.read().unwrap().size(),
                                            anon_dist,
+                                           // Filled in by Dispatch::get_stat().
+                                           anon_huge_size: 0,
+                                           anon_node_size: Default::default(),
+                                           classes,
                             })
                                 .unwrap();
                         }
+                        Ok(DispatchCmd::HashReq(req, resp_tx)) => self.queue_ext_req(req, resp_tx),
                         Ok(DispatchCmd::FillAnon) => {
                             let aa = self.anon_area.read().unwrap();
                             for i in 0 .. aa.size() / *PAGE_SIZE {
@@ -784,13 +1619,41 @@ impl DispatchThread {
                 },
                 recv(self.cmpl_rx) -> cmpl => {
                     match cmpl {
-                        Ok(HashCompletion {digest, started_at, file_dist, anon_dist}) => {
+                        Ok(HashCompletion {class, digest, queued_at, started_at, breakdown, file_dist, anon_dist, ext_resp}) => {
                             self.nr_in_flight -= 1;
                             self.nr_done += 1;
-                            let dur = Instant::now().duration_since(started_at).as_secs_f64();
-                            self.lat_min = self.lat_min.min(dur);
-                            self.lat_max = self.lat_max.max(dur);
-                            self.ckms.insert(dur);
+                            let now = Instant::now();
+                            let dur = now.duration_since(queued_at).as_secs_f64();
+                            self.lat_acc.insert(dur);
+                            self.lat_hist.record(dur);
+                            self.lat_breakdown_acc += &breakdown;
+                            let queue_lat = started_at.duration_since(queued_at).as_secs_f64();
+                            let svc_lat = now.duration_since(started_at).as_secs_f64();
+                            if self.is_open_loop() {
+                                self.queue_lat_acc.insert(queue_lat);
+                                self.svc_lat_acc.insert(svc_lat);
+                            }
+                            // The client may have gone away, ignore errors.
+                            if let Some((id, resp_tx)) = ext_resp {
+                                let _ = resp_tx.send(HashResp {
+                                    id,
+                                    error: None,
+                                    digest: digest.to_string(),
+                                    lat: dur,
+                                    queue_lat,
+                                    svc_lat,
+                                    breakdown: breakdown.clone(),
+                                });
+                            }
+                            // Completions from before a params update may
+                            // refer to a class which no longer exists.
+                            if !self.params.classes.is_empty() {
+                                if let Some(rc) = self.classes.iter_mut().find(|rc| rc.name == class) {
+                                    rc.nr_done += 1;
+                                    rc.lat_acc.insert(dur);
+                                    rc.lat_hist.record(dur);
+                                }
+                            }
                             if let Some(logger) = self.logger.as_mut() {
                                 logger.log(&format!("{} {:.2}ms",
                                                     digest, dur * TO_MSEC));
@@ -811,7 +1674,8 @@ impl DispatchThread {
                             return;
                         }
                     }
-                }
+                },
+                recv(arrival_timer) -> _ => {}
             }
 
             // Refresh stat and update control parameters.  Params
@@ -833,6 +1697,7 @@ impl DispatchThread {
 /// dispatch spawns an associated dispatch thread which keeps
 /// scheduling Hasher workers according to params.
 pub struct Dispatch {
+    anon_area: Arc<RwLock<AnonArea>>,
     cmd_tx: Option<Sender<DispatchCmd>>,
     dispatch_jh: Option<JoinHandle<()>>,
     stat_tx: Sender<Stat>,
@@ -845,17 +1710,38 @@ impl Dispatch {
         tf: TestFiles,
         params: &Params,
         anon_comp: f64,
+        anon_mode: AnonMode,
+        numa: NumaPolicy,
         logger: Option<Logger>,
+        ext_mode: bool,
     ) -> Self {
         let params_copy = params.clone();
+        let mut aa = AnonArea::new(
+            DispatchThread::anon_total(max_size, params),
+            anon_comp,
+            anon_mode,
+            numa,
+        );
+        aa.set_seed(params.seed);
+        let anon_area = Arc::new(RwLock::new(aa));
+        let anon_area_copy = anon_area.clone();
         let (cmd_tx, cmd_rx) = channel::unbounded();
         let dispatch_jh = Option::Some(spawn(move || {
-            let mut dt = DispatchThread::new(max_size, tf, params_copy, anon_comp, logger, cmd_rx);
+            let mut dt = DispatchThread::new(
+                max_size,
+                tf,
+                params_copy,
+                anon_area_copy,
+                logger,
+                ext_mode,
+                cmd_rx,
+            );
             dt.run();
         }));
         let (stat_tx, stat_rx) = channel::unbounded();
 
         Dispatch {
+            anon_area,
             cmd_tx: Some(cmd_tx),
             dispatch_jh,
             stat_tx,
@@ -871,13 +1757,40 @@ impl Dispatch {
             .unwrap();
     }
 
+    /// The anon backing mode in effect, see `AnonArea::mode()`.
+    pub fn anon_mode(&self) -> AnonMode {
+        self.anon_area.read().unwrap().mode()
+    }
+
     pub fn get_stat(&self) -> Stat {
         self.cmd_tx
             .as_ref()
             .unwrap()
             .send(DispatchCmd::GetStat(self.stat_tx.clone()))
             .unwrap();
-        self.stat_rx.recv().unwrap()
+        let mut stat = self.stat_rx.recv().unwrap();
+
+        // Scanning smaps can take a while, keep it off the dispatch thread.
+        let aa = self.anon_area.read().unwrap();
+        match aa.huge_size() {
+            Ok(v) => stat.anon_huge_size = v,
+            Err(e) => debug!("Failed to determine anon huge page usage ({:?})", &e),
+        }
+        match aa.node_sizes() {
+            Ok(v) => stat.anon_node_size = v,
+            Err(e) => debug!("Failed to determine anon NUMA usage ({:?})", &e),
+        }
+        drop(aa);
+        stat
+    }
+
+    /// Returns a handle which can be used to submit external hash requests
+    /// from other threads. Only serviced if created with `ext_mode`. Note
+    /// that the dispatch thread stays alive while any submitter exists.
+    pub fn submitter(&self) -> Submitter {
+        Submitter {
+            cmd_tx: self.cmd_tx.as_ref().unwrap().clone(),
+        }
     }
 
     pub fn fill_anon(&self) {
@@ -889,6 +1802,19 @@ impl Dispatch {
     }
 }
 
+#[derive(Clone)]
+pub struct Submitter {
+    cmd_tx: Sender<DispatchCmd>,
+}
+
+impl Submitter {
+    /// Queue `req`. The response will be sent to `resp_tx` on completion.
+    pub fn submit(&self, req: HashReq, resp_tx: Sender<HashResp>) -> Result<()> {
+        self.cmd_tx.send(DispatchCmd::HashReq(req, resp_tx))?;
+        Ok(())
+    }
+}
+
 impl Drop for Dispatch {
     fn drop(&mut self) {
         drop(self.cmd_tx.take());
@@ -906,6 +1832,62 @@ mod tests {
 
     const CKMS_ERROR: f64 = 0.001;
 
+    #[test]
+    fn test_ext_req_limits() {
+        let limits = super::ExtReqLimits {
+            file_size: 1 << 20,
+            anon_size: 1 << 20,
+            sleep: 1.0,
+        };
+        let req = |file_size, anon_size, sleep| rd_hashd_intf::HashReq {
+            file_size,
+            anon_size,
+            sleep,
+            ..Default::default()
+        };
+
+        assert!(limits.check(&req(None, None, None)).is_ok());
+        assert!(limits
+            .check(&req(Some(1 << 20), Some(0), Some(1.0)))
+            .is_ok());
+        assert!(limits.check(&req(Some((1 << 20) + 1), None, None)).is_err());
+        assert!(limits.check(&req(None, Some(usize::MAX), None)).is_err());
+        assert!(limits.check(&req(None, None, Some(1e300))).is_err());
+        assert!(limits.check(&req(None, None, Some(-1.0))).is_err());
+        assert!(limits.check(&req(None, None, Some(f64::NAN))).is_err());
+    }
+
+    #[test]
+    fn test_inherit_class_stats() {
+        let class = |name: &str| rd_hashd_intf::ReqClass {
+            name: name.into(),
+            ..Default::default()
+        };
+        let mut params = rd_hashd_intf::Params {
+            classes: vec![class("a"), class("b")],
+            ..Default::default()
+        };
+        let (mut old, _) = super::DispatchThread::req_classes(&params);
+        old[0].nr_done = 3;
+        old[0].lat_hist.record(0.01);
+        old[1].nr_done = 5;
+
+        // Reorder, drop "a" and add "c".
+        params.classes = vec![class("c"), class("b")];
+        let (mut classes, _) = super::DispatchThread::req_classes(&params);
+        super::DispatchThread::inherit_class_stats(&mut classes, &mut old);
+        assert_eq!(classes[0].nr_done, 0);
+        assert!(classes[0].lat_hist.is_empty());
+        assert_eq!(classes[1].nr_done, 5);
+
+        // Re-adding "a" picks up where it left off.
+        params.classes = vec![class("a")];
+        let (mut classes, _) = super::DispatchThread::req_classes(&params);
+        super::DispatchThread::inherit_class_stats(&mut classes, &mut old);
+        assert_eq!(classes[0].nr_done, 3);
+        assert_eq!(classes[0].lat_hist.total, 1);
+    }
+
     #[test]
     fn test_clamped_normal() {
         let _ = ::env_logger::try_init();
@@ -952,4 +1934,37 @@ mod tests {
         assert!(p50 >= -0.1 && p50 <= 0.1);
         assert!(p75 >= 0.4 && p75 <= 0.6);
     }
+
+    #[test]
+    fn test_addr_sampler() {
+        let _ = ::env_logger::try_init();
+        let mut rng = SmallRng::from_entropy();
+        let mut params = rd_hashd_intf::Params::default();
+
+        // 90% of accesses should land in the hottest 10%.
+        println!("Testing AddrSampler HotSet (0.1, 0.9)");
+        params.addr_dist = rd_hashd_intf::AddrDist::HotSet;
+        let s = super::AddrSampler::new(&params, 0.0);
+        let nr_hot = (0..4096)
+            .map(|_| s.sample(&mut rng))
+            .inspect(|v| assert!(*v >= -1.0 && *v <= 1.0))
+            .filter(|v| v.abs() < 0.1)
+            .count();
+        println!("nr_hot={}", nr_hot);
+        assert!(nr_hot >= 3500 && nr_hot <= 3850);
+
+        // Zipf should be heavily skewed towards the hottest spot.
+        println!("Testing AddrSampler Zipf (1.0)");
+        params.addr_dist = rd_hashd_intf::AddrDist::Zipf;
+        let s = super::AddrSampler::new(&params, 0.0);
+        let mut ckms = CKMS::<f64>::new(CKMS_ERROR);
+        for _ in 0..4096 {
+            let v = s.sample(&mut rng);
+            assert!(v >= -1.0 && v <= 1.0);
+            ckms.insert(v.abs());
+        }
+        let p50 = ckms.query(0.5).unwrap().1;
+        println!("p50={:.5}", p50);
+        assert!(p50 < 0.01);
+    }
 }
@@ -4,32 +4,69 @@ use chrono::prelude::*;
 use crossbeam::channel::{self, Receiver, Sender};
 use log::{debug, error, info};
 use rand::rngs::SmallRng;
-use rand::{Rng, SeedableRng};
+use rand::Rng;
 use scan_fmt::scan_fmt;
 use std::cmp;
 use std::collections::VecDeque;
 use std::fs::{self, File};
-use std::io::prelude::*;
+use std::io::{self, prelude::*};
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
 use std::path::Path;
 use std::sync::atomic::{self, AtomicU64};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread::{spawn, JoinHandle};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use rd_hashd_intf::{LatHist, LogMode, Params};
 use rd_util::*;
 
 const LOG_FILENAME: &str = "rd-hashd.log";
+const DIRECT_ALIGN: usize = 4096;
+
+/// Log durability configuration, see `Params::log_mode`, and the seed for
+/// the padding contents.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogCfg {
+    pub mode: LogMode,
+    pub sync_bytes: u64,
+    pub sync_intv: f64,
+    pub seed: Option<u64>,
+}
+
+impl LogCfg {
+    pub fn from_params(params: &Params) -> Self {
+        Self {
+            mode: params.log_mode,
+            sync_bytes: params.log_sync_bytes,
+            sync_intv: params.log_sync_intv,
+            seed: params.seed,
+        }
+    }
+}
 
 struct LogWorker {
     log_rx: Receiver<String>,
     dir_path: String,
     padding: Arc<AtomicU64>,
+    shared_cfg: Arc<Mutex<LogCfg>>,
+    write_lat: Arc<Mutex<LatHist>>,
+    cfg: LogCfg,
     unit_size: u64,
     nr_to_keep: usize,
     rng: SmallRng,
     file: Option<File>,
     size: u64,
     old_logs: VecDeque<String>,
+
+    // Fdatasync mode state.
+    unsynced: u64,
+    synced_at: Instant,
+
+    // Direct mode state. Data is written out in DIRECT_ALIGN blocks at
+    // `direct_pos` and the trailing partial block is kept in `direct_tail`.
+    direct_pos: u64,
+    direct_tail: Vec<u8>,
+    direct_buf: Vec<u8>,
 }
 
 impl LogWorker {
@@ -50,22 +87,42 @@ impl LogWorker {
         )
     }
 
+    fn open_log(path: &str, mode: LogMode, create_new: bool) -> io::Result<File> {
+        let mut opts = fs::OpenOptions::new();
+        if create_new {
+            opts.create_new(true);
+        } else {
+            opts.create(true);
+        }
+        opts.write(true);
+        match mode {
+            LogMode::Buffered | LogMode::Fdatasync => {
+                opts.append(true);
+            }
+            // O_DIRECT writes are positioned explicitly to keep alignment.
+            LogMode::Direct => {
+                opts.custom_flags(libc::O_DIRECT);
+            }
+            LogMode::Dsync => {
+                opts.append(true).custom_flags(libc::O_DSYNC);
+            }
+        }
+        opts.open(path)
+    }
+
     fn new(
         dir_path: String,
         padding: Arc<AtomicU64>,
+        shared_cfg: Arc<Mutex<LogCfg>>,
+        write_lat: Arc<Mutex<LatHist>>,
         unit_size: u64,
         max_size: u64,
         log_rx: Receiver<String>,
     ) -> Result<Self> {
         fs::create_dir_all(&dir_path)?;
         let path = Self::log_path(&dir_path);
-        let file = Some(
-            fs::OpenOptions::new()
-                .create(true)
-                .write(true)
-                .append(true)
-                .open(&path)?,
-        );
+        let cfg = shared_cfg.lock().unwrap().clone();
+        let file = Some(Self::open_log(&path, cfg.mode, false)?);
         let size = file.as_ref().unwrap().metadata()?.len();
 
         let prefix = format!("{}/{}-", &dir_path, LOG_FILENAME);
@@ -93,21 +150,139 @@ impl LogWorker {
             &old_logs,
         );
 
+        let rng = seeded_rng(cfg.seed, "logger", 0);
         let mut lw = LogWorker {
             log_rx,
             dir_path,
             padding,
+            shared_cfg,
+            write_lat,
+            cfg,
             unit_size,
             nr_to_keep: ((max_size + unit_size - 1) / unit_size) as usize,
-            rng: SmallRng::from_entropy(),
+            rng,
             file,
             size,
             old_logs: VecDeque::from(old_logs),
+            unsynced: 0,
+            synced_at: Instant::now(),
+            direct_pos: Self::direct_start(size),
+            direct_tail: vec![],
+            direct_buf: vec![],
         };
         lw.expire_old_logs();
         Ok(lw)
     }
 
+    /// O_DIRECT writes must start at an aligned position. If the existing
+    /// file has a partial block at the end, skip over it leaving a hole.
+    fn direct_start(size: u64) -> u64 {
+        let align = DIRECT_ALIGN as u64;
+        (size + align - 1) / align * align
+    }
+
+    /// Write out `len` bytes from the start of `direct_tail` at
+    /// `direct_pos`. `len` must be a multiple of DIRECT_ALIGN.
+    fn write_direct_blocks(&mut self, len: usize) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+
+        // Vec doesn't guarantee alignment. Over-allocate and write from the
+        // first aligned position.
+        if self.direct_buf.len() < len + DIRECT_ALIGN {
+            self.direct_buf.resize(len + DIRECT_ALIGN, 0);
+        }
+        let off = self.direct_buf.as_ptr().align_offset(DIRECT_ALIGN);
+        let buf = &mut self.direct_buf[off..off + len];
+        buf.copy_from_slice(&self.direct_tail[..len]);
+
+        let res = self
+            .file
+            .as_ref()
+            .unwrap()
+            .write_all_at(buf, self.direct_pos);
+        self.direct_tail.drain(..len);
+        self.direct_pos += len as u64;
+        res
+    }
+
+    /// Pad the trailing partial block, if any, and write it out so that
+    /// nothing is left behind when switching files or modes.
+    fn flush_direct_tail(&mut self) {
+        if self.file.is_none() || self.direct_tail.is_empty() {
+            self.direct_tail.clear();
+            return;
+        }
+        let len = Self::direct_start(self.direct_tail.len() as u64) as usize;
+        self.direct_tail.resize(len - 1, b' ');
+        self.direct_tail.push(b'\n');
+        if let Err(e) = self.write_direct_blocks(len) {
+            error!("logger: Failed to flush O_DIRECT tail ({:?})", &e);
+        }
+        self.size = self.direct_pos;
+    }
+
+    /// Pick up configuration changes. Switching modes requires reopening
+    /// the log file with different flags.
+    fn update_cfg(&mut self) {
+        let cfg = self.shared_cfg.lock().unwrap().clone();
+        if cfg == self.cfg {
+            return;
+        }
+        let mode_changed = cfg.mode != self.cfg.mode;
+        if mode_changed && self.cfg.mode == LogMode::Direct {
+            self.flush_direct_tail();
+        }
+        if cfg.seed != self.cfg.seed {
+            self.rng = seeded_rng(cfg.seed, "logger", 0);
+        }
+        self.cfg = cfg;
+        if !mode_changed || self.file.is_none() {
+            return;
+        }
+
+        let path = Self::log_path(&self.dir_path);
+        match Self::open_log(&path, self.cfg.mode, false) {
+            Ok(file) => {
+                self.size = file.metadata().map(|md| md.len()).unwrap_or(self.size);
+                self.direct_pos = Self::direct_start(self.size);
+                self.file = Some(file);
+                info!("logger: Switched to {:?} mode", self.cfg.mode);
+            }
+            Err(e) => {
+                error!(
+                    "logger: Failed to reopen {:?} in {:?} mode ({:?}), disabling",
+                    &path, self.cfg.mode, &e
+                );
+                self.file = None;
+            }
+        }
+    }
+
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        match self.cfg.mode {
+            LogMode::Buffered | LogMode::Dsync => self.file.as_mut().unwrap().write_all(line),
+            LogMode::Fdatasync => {
+                self.file.as_mut().unwrap().write_all(line)?;
+                self.unsynced += line.len() as u64;
+                if self.unsynced >= self.cfg.sync_bytes
+                    || self.synced_at.elapsed() >= Duration::from_secs_f64(self.cfg.sync_intv)
+                {
+                    self.file.as_ref().unwrap().sync_data()?;
+                    self.unsynced = 0;
+                    self.synced_at = Instant::now();
+                }
+                Ok(())
+            }
+            LogMode::Direct => {
+                self.direct_tail.extend_from_slice(line);
+                let len = self.direct_tail.len() / DIRECT_ALIGN * DIRECT_ALIGN;
+                self.write_direct_blocks(len)
+            }
+        }
+    }
+
     fn expire_old_logs(&mut self) {
         while self.old_logs.len() >= self.nr_to_keep {
             let path = match self.old_logs.pop_front() {
@@ -124,6 +299,9 @@ impl LogWorker {
         if self.size < self.unit_size || self.file.is_none() {
             return;
         }
+        if self.cfg.mode == LogMode::Direct {
+            self.flush_direct_tail();
+        }
 
         let lpath = Self::log_path(&self.dir_path);
         let apath = self.log_archive_path();
@@ -138,15 +316,11 @@ impl LogWorker {
         self.expire_old_logs();
 
         let path = Self::log_path(&self.dir_path);
-        match fs::OpenOptions::new()
-            .create_new(true)
-            .write(true)
-            .append(true)
-            .open(&path)
-        {
+        match Self::open_log(&path, self.cfg.mode, true) {
             Ok(file) => {
                 self.file = Some(file);
                 self.size = 0;
+                self.direct_pos = 0;
             }
             Err(err) => {
                 error!(
@@ -159,6 +333,7 @@ impl LogWorker {
     }
 
     fn log(&mut self, msg: &str) {
+        self.update_cfg();
         self.rotate();
         if self.file.is_none() {
             return;
@@ -178,7 +353,8 @@ impl LogWorker {
             line.push(b'\n');
         }
 
-        if let Err(err) = self.file.as_mut().unwrap().write_all(line.as_ref()) {
+        let started_at = Instant::now();
+        if let Err(err) = self.write(line.as_ref()) {
             error!(
                 "logger: failed to write to {:?} ({}_, disabling",
                 &Self::log_path(&self.dir_path),
@@ -186,6 +362,10 @@ impl LogWorker {
             );
             self.file = None;
         }
+        self.write_lat
+            .lock()
+            .unwrap()
+            .record(started_at.elapsed().as_secs_f64());
         self.size += line.len() as u64;
     }
 
@@ -199,12 +379,17 @@ impl LogWorker {
                 }
             }
         }
+        if self.cfg.mode == LogMode::Direct {
+            self.flush_direct_tail();
+        }
     }
 }
 
 pub struct Logger {
     log_tx: Option<Sender<String>>,
     padding: Arc<AtomicU64>,
+    cfg: Arc<Mutex<LogCfg>>,
+    write_lat: Arc<Mutex<LatHist>>,
     worker_jh: Option<JoinHandle<()>>,
 }
 
@@ -212,6 +397,7 @@ impl Logger {
     pub fn new<P>(
         dir_path: P,
         padding: u64,
+        cfg: LogCfg,
         unit_size: u64,
         max_size: u64,
         capacity: usize,
@@ -227,16 +413,41 @@ impl Logger {
 
         let (log_tx, log_rx) = channel::bounded(capacity);
         let padding = Arc::new(AtomicU64::new(padding));
-        let worker = LogWorker::new(dir_path, padding.clone(), unit_size, max_size, log_rx)?;
+        let cfg = Arc::new(Mutex::new(cfg));
+        let write_lat = Arc::new(Mutex::new(LatHist::default()));
+        let worker = LogWorker::new(
+            dir_path,
+            padding.clone(),
+            cfg.clone(),
+            write_lat.clone(),
+            unit_size,
+            max_size,
+            log_rx,
+        )?;
         let worker_jh = spawn(move || worker.run());
 
         Ok(Self {
             log_tx: Some(log_tx),
             padding,
+            cfg,
+            write_lat,
             worker_jh: Some(worker_jh),
         })
     }
 
+    pub fn set_cfg(&self, cfg: LogCfg) {
+        let mut cur = self.cfg.lock().unwrap();
+        if *cur != cfg {
+            info!("Logger: Updating config to {:?}", &cfg);
+            *cur = cfg;
+        }
+    }
+
+    /// Returns the write latency histogram since the last call.
+    pub fn take_write_lat(&self) -> LatHist {
+        std::mem::take(&mut *self.write_lat.lock().unwrap())
+    }
+
     pub fn set_padding(&self, size: u64) {
         if self.padding.load(atomic::Ordering::Relaxed) != size {
             info!("Logger: Updating padding to {:.2}k", size);
@@ -255,3 +466,99 @@ impl Drop for Logger {
         self.worker_jh.take().unwrap().join().unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{LogCfg, LogWorker, DIRECT_ALIGN};
+    use crossbeam::channel;
+    use rd_hashd_intf::LogMode;
+    use std::fs;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::{Arc, Mutex};
+
+    // Block and tail handling doesn't depend on O_DIRECT itself, which
+    // tmpfs doesn't support. Open the log buffered and swap in a plain
+    // non-append handle so that positioned writes land where asked.
+    fn direct_worker(dir: &str, init: &[u8]) -> LogWorker {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let path = LogWorker::log_path(dir);
+        fs::write(&path, init).unwrap();
+
+        let cfg = LogCfg {
+            mode: LogMode::Buffered,
+            sync_bytes: 0,
+            sync_intv: 0.0,
+            seed: Some(0),
+        };
+        let (_tx, rx) = channel::unbounded();
+        let mut lw = LogWorker::new(
+            dir.to_string(),
+            Arc::new(AtomicU64::new(0)),
+            Arc::new(Mutex::new(cfg)),
+            Default::default(),
+            1 << 20,
+            1 << 30,
+            rx,
+        )
+        .unwrap();
+        lw.file = Some(fs::OpenOptions::new().write(true).open(&path).unwrap());
+        lw.cfg.mode = LogMode::Direct;
+        lw
+    }
+
+    #[test]
+    fn test_direct_start() {
+        assert_eq!(LogWorker::direct_start(0), 0);
+        assert_eq!(LogWorker::direct_start(1), 4096);
+        assert_eq!(LogWorker::direct_start(4096), 4096);
+        assert_eq!(LogWorker::direct_start(4097), 8192);
+    }
+
+    #[test]
+    fn test_direct_blocks_and_tail() {
+        let dir = std::env::temp_dir()
+            .join(format!("rd-hashd-logger-{}", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        let path = LogWorker::log_path(&dir);
+
+        // A partial block at the end of the existing file is skipped over.
+        let mut lw = direct_worker(&dir, &[b'x'; 100]);
+        assert_eq!(lw.direct_pos, DIRECT_ALIGN as u64);
+
+        // Only whole blocks are written, the rest stays in the tail.
+        lw.write(&[b'a'; DIRECT_ALIGN + 904]).unwrap();
+        assert_eq!(lw.direct_pos, 2 * DIRECT_ALIGN as u64);
+        assert_eq!(lw.direct_tail.len(), 904);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 2 * DIRECT_ALIGN as u64);
+
+        lw.write(&[b'b'; 100]).unwrap();
+        assert_eq!(lw.direct_tail.len(), 1004);
+
+        // Flushing pads the tail to a full block ending with a newline.
+        lw.flush_direct_tail();
+        assert!(lw.direct_tail.is_empty());
+        assert_eq!(lw.direct_pos, 3 * DIRECT_ALIGN as u64);
+        assert_eq!(lw.size, lw.direct_pos);
+
+        let data = fs::read(&path).unwrap();
+        assert_eq!(data.len(), 3 * DIRECT_ALIGN);
+        assert!(data[..100].iter().all(|&c| c == b'x'));
+        assert!(data[100..DIRECT_ALIGN].iter().all(|&c| c == 0));
+        let (blk1, blk2) = (&data[DIRECT_ALIGN..], &data[2 * DIRECT_ALIGN..]);
+        assert!(blk1[..DIRECT_ALIGN].iter().all(|&c| c == b'a'));
+        assert!(blk2[..904].iter().all(|&c| c == b'a'));
+        assert!(blk2[904..1004].iter().all(|&c| c == b'b'));
+        assert!(blk2[1004..DIRECT_ALIGN - 1].iter().all(|&c| c == b' '));
+        assert_eq!(blk2[DIRECT_ALIGN - 1], b'\n');
+
+        // Nothing to flush is a no-op.
+        lw.flush_direct_tail();
+        assert_eq!(lw.direct_pos, 3 * DIRECT_ALIGN as u64);
+
+        drop(lw);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -12,12 +12,15 @@ use rd_hashd_intf::{Args, Params, Phase, Report, Stat};
 use rd_util::*;
 
 mod bench;
+mod cpu_kernel;
 mod hasher;
 mod logger;
+mod server;
 mod testfiles;
+mod trace;
 mod workqueue;
 
-use logger::Logger;
+use logger::{LogCfg, Logger};
 use testfiles::TestFiles;
 
 lazy_static::lazy_static! {
@@ -132,15 +135,17 @@ fn create_logger(args: &Args, params: &Params) -> Option<Logger> {
     match args.log_dir.as_ref() {
         Some(log_dir) => {
             info!(
-                "Setting up hash logging at {} ({:.2}G @ {:.2}Mbps pad {:.2}k)",
+                "Setting up hash logging at {} ({:.2}G @ {:.2}Mbps pad {:.2}k {:?})",
                 &log_dir,
                 to_gb(args.log_size),
                 to_mb(params.log_bps),
                 to_kb(params.log_padding()),
+                params.log_mode,
             );
             match Logger::new(
                 log_dir,
                 params.log_padding(),
+                LogCfg::from_params(params),
                 LOGFILE_UNIT_SIZE,
                 args.log_size,
                 (params.rps_max as f64 * LOGGER_HOLD_SEC) as usize,
@@ -202,6 +207,7 @@ fn main() {
         TESTFILE_UNIT_SIZE,
         args.file_max_size(),
         args.compressibility,
+        params.seed,
     );
     tf.prep_base_dir().unwrap();
 
@@ -232,6 +238,7 @@ fn main() {
     //
     let mut report_file = JsonReportFile::<Report>::new(args.report.as_ref());
     report_file.data.params_modified = DateTime::from(params_file.loaded_mod);
+    report_file.data.seed = params.seed;
     report_tick(&mut report_file, false);
 
     if args.clear_testfiles {
@@ -274,6 +281,15 @@ fn main() {
         return;
     }
 
+    //
+    // Set CPU affinity according to the NUMA policy. All threads are created
+    // after this and inherit the affinity.
+    //
+    if let Err(e) = args.numa.bind_cpus() {
+        error!("Failed to apply NUMA policy {:?} ({:#})", args.numa, &e);
+        panic!();
+    }
+
     //
     // Benchmark and exit if requested.
     //
@@ -290,12 +306,14 @@ fn main() {
     let fsize = (size * params.file_frac).min(size);
     let asize = size - fsize;
     info!(
-        "Starting hasher (maxcon={} lat={:.1}ms rps={} file={:.2}G anon={:.2}G)",
+        "Starting hasher (maxcon={} lat={:.1}ms rps={} file={:.2}G anon={:.2}G/{:?}/{:?})",
         params.concurrency_max,
         params.lat_target * TO_MSEC,
         params.rps_target,
         to_gb(fsize),
-        to_gb(asize)
+        to_gb(asize),
+        args.anon_mode,
+        args.numa,
     );
 
     let mut dispatch = hasher::Dispatch::new(
@@ -303,9 +321,19 @@ fn main() {
         tf,
         &params,
         args.compressibility,
+        args.anon_mode,
+        args.numa,
         create_logger(args, &params),
+        args.socket.is_some(),
     );
 
+    if let Some(path) = args.socket.as_ref() {
+        if let Err(e) = server::start(path, dispatch.submitter()) {
+            error!("Failed to set up request socket {:?} ({:?})", path, &e);
+            panic!();
+        }
+    }
+
     //
     // Monitor and report.
     //
@@ -330,27 +358,57 @@ fn main() {
         {
             stat_sum.avg(nr_sums);
 
+            // Percentiles can't be averaged. Calculate them over the whole
+            // interval from the merged histogram.
+            let lh = &stat_sum.lat_hist;
             let mut buf = format!(
                 "p50:{:5.1} p84:{:5.1} p90:{:5.1} p99:{:5.1} rps:{:6.1} con:{:5.1}",
-                stat_sum.lat.p50 * TO_MSEC,
-                stat_sum.lat.p84 * TO_MSEC,
-                stat_sum.lat.p90 * TO_MSEC,
-                stat_sum.lat.p99 * TO_MSEC,
+                lh.quantile(0.50) * TO_MSEC,
+                lh.quantile(0.84) * TO_MSEC,
+                lh.quantile(0.90) * TO_MSEC,
+                lh.quantile(0.99) * TO_MSEC,
                 stat_sum.rps,
                 stat.concurrency
             );
             if args.verbosity > 0 {
                 write!(
                     buf,
-                    "/{:.1} infl:{} workers:{}/{} done:{}",
+                    "/{:.1} infl:{} queued:{} workers:{}/{} done:{}",
                     stat.concurrency_max,
                     stat.nr_in_flight,
+                    stat.nr_queued,
                     stat.nr_workers - stat.nr_idle_workers,
                     stat.nr_workers,
                     stat.nr_done,
                 )
                 .unwrap();
             }
+            if args.verbosity > 1 {
+                let bd = &stat_sum.lat_breakdown;
+                write!(
+                    buf,
+                    " q:{:.1} io:{:.1} anon:{:.1}/{:.1}flt hash:{:.1}/{:.1}wait sleep:{:.1}",
+                    bd.queue * TO_MSEC,
+                    bd.file_io * TO_MSEC,
+                    bd.anon * TO_MSEC,
+                    bd.anon_majflt,
+                    bd.hash * TO_MSEC,
+                    bd.hash_cpu_wait * TO_MSEC,
+                    bd.sleep * TO_MSEC,
+                )
+                .unwrap();
+
+                let wl = &stat_sum.log_write_lat;
+                if !wl.is_empty() {
+                    write!(
+                        buf,
+                        " log:{:.1}/{:.1}",
+                        wl.quantile(0.5) * TO_MSEC,
+                        wl.quantile(0.99) * TO_MSEC
+                    )
+                    .unwrap();
+                }
+            }
             info!("{}", buf);
 
             stat_sum = Default::default();
@@ -362,6 +420,7 @@ fn main() {
             Ok(true) => {
                 dispatch.set_params(&params_file.data);
                 report_file.data.params_modified = DateTime::from(params_file.loaded_mod);
+                report_file.data.seed = params_file.data.seed;
                 info!(
                     "Reloaded params file {:?}",
                     &params_file.path.as_ref().unwrap()
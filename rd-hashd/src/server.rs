@@ -0,0 +1,184 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// Unix socket interface for external clients to drive rd-hashd. See
+// rd_hashd_intf::sock for the protocol.
+use anyhow::{anyhow, bail, Result};
+use crossbeam::channel;
+use log::{debug, info, warn};
+use std::fs;
+use std::io::{prelude::*, BufReader};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::spawn;
+
+use rd_hashd_intf::{HashReq, HashResp};
+
+use super::hasher::Submitter;
+
+const MAX_CONNS: usize = 64;
+
+fn encode_resp(resp: &HashResp) -> String {
+    let mut line = serde_json::to_string(resp).unwrap();
+    line.push('\n');
+    line
+}
+
+// On failure, returns the request's id if it can still be found along with
+// the error message so that the client can match the error response.
+fn decode_req(line: &str) -> std::result::Result<HashReq, (Option<u64>, String)> {
+    serde_json::from_str::<HashReq>(line).map_err(|e| {
+        let id = serde_json::from_str::<serde_json::Value>(line)
+            .ok()
+            .and_then(|v| v.get("id")?.as_u64());
+        (id, format!("invalid request ({})", &e))
+    })
+}
+
+fn serve_conn(stream: UnixStream, submitter: Submitter) -> Result<()> {
+    let (resp_tx, resp_rx) = channel::unbounded::<HashResp>();
+
+    // Responses are written as they complete. The writer exits once the
+    // requests in flight are done after the client closed its end.
+    let mut writer = stream.try_clone()?;
+    let writer_jh = spawn(move || {
+        for resp in resp_rx.iter() {
+            if let Err(e) = writer.write_all(encode_resp(&resp).as_bytes()) {
+                debug!("server: failed to write response ({:?})", &e);
+                break;
+            }
+        }
+    });
+
+    let mut res = Ok(());
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(v) => v,
+            Err(e) => {
+                res = Err(e.into());
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match decode_req(&line) {
+            Ok(req) => {
+                if let Err(e) = submitter.submit(req, resp_tx.clone()) {
+                    res = Err(e);
+                    break;
+                }
+            }
+            Err((id, msg)) => {
+                let _ = resp_tx.send(HashResp {
+                    id: id.unwrap_or(0),
+                    error: Some(msg),
+                    ..Default::default()
+                });
+                // The client can't tell which request failed, give up.
+                if id.is_none() {
+                    res = Err(anyhow!("invalid request without id"));
+                    break;
+                }
+            }
+        }
+    }
+
+    drop(resp_tx);
+    let _ = writer_jh.join();
+    res
+}
+
+/// Listen on the Unix socket at `path` and submit the received requests
+/// through `submitter`. Each connection is served by its own threads and
+/// connections beyond `MAX_CONNS` are refused.
+pub fn start(path: &str, submitter: Submitter) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(md) if md.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => bail!("{:?} exists and is not a socket", path),
+        Err(_) => {}
+    }
+
+    let listener = UnixListener::bind(path)?;
+    info!("Serving hash requests on {:?}", path);
+
+    let nr_conns = Arc::new(AtomicUsize::new(0));
+    spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(mut stream) => {
+                    if nr_conns.load(Ordering::Relaxed) >= MAX_CONNS {
+                        warn!("server: Refusing connection, {} already open", MAX_CONNS);
+                        let resp = HashResp {
+                            error: Some("too many connections".into()),
+                            ..Default::default()
+                        };
+                        let _ = stream.write_all(encode_resp(&resp).as_bytes());
+                        continue;
+                    }
+                    nr_conns.fetch_add(1, Ordering::Relaxed);
+                    let nr_conns = nr_conns.clone();
+                    let submitter = submitter.clone();
+                    spawn(move || {
+                        if let Err(e) = serve_conn(stream, submitter) {
+                            warn!("server: connection terminated ({:?})", &e);
+                        }
+                        nr_conns.fetch_sub(1, Ordering::Relaxed);
+                    });
+                }
+                Err(e) => warn!("server: failed to accept connection ({:?})", &e),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_hashd_intf::{HashReq, HashResp};
+
+    #[test]
+    fn test_req_resp_encoding() {
+        let req = HashReq {
+            id: 7,
+            class: Some("fg".into()),
+            file_size: Some(4096),
+            anon_size: None,
+            sleep: Some(0.01),
+        };
+        let dec = super::decode_req(&serde_json::to_string(&req).unwrap()).unwrap();
+        assert_eq!((dec.id, dec.class.as_deref()), (7, Some("fg")));
+        assert_eq!(
+            (dec.file_size, dec.anon_size, dec.sleep),
+            (Some(4096), None, Some(0.01))
+        );
+
+        // Unspecified fields are left for sampling.
+        let dec = super::decode_req(r#"{"id": 3}"#).unwrap();
+        assert_eq!((dec.id, dec.class, dec.file_size), (3, None, None));
+
+        // Errors carry the id if it can be found.
+        assert_eq!(
+            super::decode_req(r#"{"id": 5, "sleep": "x"}"#)
+                .unwrap_err()
+                .0,
+            Some(5)
+        );
+        assert_eq!(super::decode_req("garbage").unwrap_err().0, None);
+
+        let resp = HashResp {
+            id: 7,
+            digest: "abcd".into(),
+            lat: 0.5,
+            ..Default::default()
+        };
+        let line = super::encode_resp(&resp);
+        assert!(line.ends_with('\n') && !line.trim_end().contains('\n'));
+        let dec: HashResp = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            (dec.id, dec.error, dec.digest.as_str(), dec.lat),
+            (7, None, "abcd", 0.5)
+        );
+    }
+}
@@ -2,8 +2,6 @@
 use anyhow::{bail, Result};
 use log::{debug, trace, warn};
 use num::Integer;
-use rand::rngs::SmallRng;
-use rand::SeedableRng;
 use std::convert::TryInto;
 use std::ffi::OsStr;
 use std::fs;
@@ -26,17 +24,25 @@ pub struct TestFiles {
     pub size: u64,
     pub nr_files: u64,
     pub comp: f64,
+    pub seed: Option<u64>,
     prefix: String,
 }
 
 impl TestFiles {
-    pub fn new<P: AsRef<Path>>(base_path: P, unit_size: u64, size: u64, comp: f64) -> Self {
+    pub fn new<P: AsRef<Path>>(
+        base_path: P,
+        unit_size: u64,
+        size: u64,
+        comp: f64,
+        seed: Option<u64>,
+    ) -> Self {
         TestFiles {
             base_path: PathBuf::from(base_path.as_ref()),
             unit_size,
             size,
             nr_files: Integer::div_ceil(&size, &unit_size),
             comp,
+            seed,
             prefix: String::from(DFL_PREFIX),
         }
     }
@@ -89,17 +95,28 @@ impl TestFiles {
         (di, fi, dname, fname)
     }
 
-    fn read_comp<P: AsRef<Path>>(path_in: P) -> Result<f64> {
+    /// Each file starts with the compressibility followed by the seed the
+    /// contents were generated with. Unseeded files have random bytes in
+    /// the latter.
+    fn read_comp_seed<P: AsRef<Path>>(path_in: P) -> Result<(f64, u64)> {
         let path = path_in.as_ref();
         let mut f = fs::File::open(path)?;
-        let mut buf = [0u8; 8];
+        let mut buf = [0u8; 16];
         f.read_exact(&mut buf)?;
-        Ok(f64::from_le_bytes(buf))
+        Ok((
+            f64::from_le_bytes(buf[0..8].try_into().unwrap()),
+            u64::from_le_bytes(buf[8..16].try_into().unwrap()),
+        ))
     }
 
-    pub fn setup<F: FnMut(u64)>(&mut self, mut progress: F) -> Result<()> {
-        let mut rng = SmallRng::from_entropy();
+    fn is_valid<P: AsRef<Path>>(&self, path: P) -> bool {
+        match Self::read_comp_seed(path) {
+            Ok((comp, seed)) => comp == self.comp && self.seed.map_or(true, |v| v == seed),
+            Err(_) => false,
+        }
+    }
 
+    pub fn setup<F: FnMut(u64)>(&mut self, mut progress: F) -> Result<()> {
         if self.nr_files > 1 << FILE_BITS {
             bail!("maximum supported nr_files is {}", 1u64 << FILE_BITS);
         }
@@ -121,13 +138,12 @@ impl TestFiles {
                 fs::create_dir_all(&dpath)?;
             }
 
-            // if file exists and already of the right size and compressibility, skip
+            // if file exists and already of the right size, compressibility
+            // and seed, skip
             if fpath.exists() {
                 match fpath.metadata() {
                     Ok(ref md)
-                        if md.is_file()
-                            && md.len() == self.unit_size
-                            && Self::read_comp(&fpath).unwrap_or(-1.0) == self.comp =>
+                        if md.is_file() && md.len() == self.unit_size && self.is_valid(&fpath) =>
                     {
                         trace!("testfiles: using existing {:?}", &fpath);
                         continue;
@@ -150,8 +166,12 @@ impl TestFiles {
                 .open(&fpath)?;
 
             let mut buf = vec![0u8; self.unit_size as usize];
+            let mut rng = seeded_rng(self.seed, "testfiles", i);
             fill_area_with_random(&mut buf, self.comp, &mut rng);
-            buf[0..8].copy_from_slice(&self.comp.to_ne_bytes());
+            buf[0..8].copy_from_slice(&self.comp.to_le_bytes());
+            if let Some(seed) = self.seed {
+                buf[8..16].copy_from_slice(&seed.to_le_bytes());
+            }
             f.write_all(&buf)?;
 
             progress(i * self.unit_size);
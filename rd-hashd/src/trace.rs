@@ -0,0 +1,120 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+//
+// Load traces to replay. A trace is a time series of RPS and, optionally,
+// request size ratio points. It can be either a JSON array of
+// {"at": SECS, "rps": RPS, "size_ratio": RATIO} objects or CSV lines of
+// "SECS,RPS[,SIZE_RATIO]". Values are linearly interpolated between points.
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+fn dfl_size_ratio() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TracePoint {
+    pub at: f64,
+    pub rps: f64,
+    #[serde(default = "dfl_size_ratio")]
+    pub size_ratio: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Trace {
+    points: Vec<TracePoint>,
+}
+
+impl Trace {
+    fn parse_csv(input: &str) -> Result<Vec<TracePoint>> {
+        let mut points = vec![];
+        for (idx, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            let parsed: Result<Vec<f64>, _> = fields.iter().map(|f| f.parse::<f64>()).collect();
+            match parsed {
+                Ok(vals) if vals.len() == 2 || vals.len() == 3 => points.push(TracePoint {
+                    at: vals[0],
+                    rps: vals[1],
+                    size_ratio: *vals.get(2).unwrap_or(&1.0),
+                }),
+                // Allow a header line.
+                Err(_) if points.is_empty() => continue,
+                _ => bail!("invalid trace line {}: {:?}", idx + 1, line),
+            }
+        }
+        Ok(points)
+    }
+
+    pub fn new(mut points: Vec<TracePoint>) -> Result<Self> {
+        if points.is_empty() {
+            bail!("trace is empty");
+        }
+        for pt in points.iter() {
+            let finite = pt.at.is_finite() && pt.rps.is_finite() && pt.size_ratio.is_finite();
+            if !finite || pt.at < 0.0 || pt.rps < 0.0 || pt.size_ratio <= 0.0 {
+                bail!("invalid trace point {:?}", pt);
+            }
+        }
+        points.sort_by(|a, b| a.at.partial_cmp(&b.at).unwrap());
+        Ok(Self { points })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let input = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read trace {:?}", path))?;
+        let points = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&input)?,
+            _ => Self::parse_csv(&input)?,
+        };
+        Self::new(points).with_context(|| format!("failed to load trace {:?}", path))
+    }
+
+    /// The duration of one pass through the trace.
+    pub fn duration(&self) -> f64 {
+        self.points.last().unwrap().at
+    }
+
+    /// Interpolated (rps, size_ratio) at `at` seconds into the trace.
+    pub fn at(&self, at: f64) -> (f64, f64) {
+        let idx = self.points.partition_point(|pt| pt.at <= at);
+        if idx == 0 {
+            let first = &self.points[0];
+            return (first.rps, first.size_ratio);
+        }
+        if idx == self.points.len() {
+            let last = self.points.last().unwrap();
+            return (last.rps, last.size_ratio);
+        }
+
+        let (left, right) = (&self.points[idx - 1], &self.points[idx]);
+        let frac = (at - left.at) / (right.at - left.at);
+        (
+            left.rps + (right.rps - left.rps) * frac,
+            left.size_ratio + (right.size_ratio - left.size_ratio) * frac,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Trace;
+
+    #[test]
+    fn test_trace_csv() {
+        let input = "at,rps,size_ratio\n# comment\n0,100\n10,200,2.0\n\n20,0,1.0\n";
+        let trace = Trace::new(Trace::parse_csv(input).unwrap()).unwrap();
+        assert_eq!(trace.duration(), 20.0);
+        assert_eq!(trace.at(0.0), (100.0, 1.0));
+        assert_eq!(trace.at(5.0), (150.0, 1.5));
+        assert_eq!(trace.at(15.0), (100.0, 1.5));
+        assert_eq!(trace.at(30.0), (0.0, 1.0));
+        assert!(Trace::parse_csv("0,100\nfoo,bar\n").is_err());
+        assert!(Trace::new(Trace::parse_csv("0,100\nnan,200\n").unwrap()).is_err());
+        assert!(Trace::new(Trace::parse_csv("0,inf\n10,200\n").unwrap()).is_err());
+    }
+}
@@ -1,24 +1,107 @@
+use super::numa::NumaPolicy;
 use super::PAGE_SIZE;
+use anyhow::{bail, Result};
+use log::warn;
 use num::Integer;
 use rand::rngs::SmallRng;
 use rand::SeedableRng;
-use std::alloc::{alloc, dealloc, Layout};
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ptr;
 
 std::thread_local!(static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy()));
 
+const HPAGE_SIZE: usize = 2 << 20;
+
+/// How the anon area is backed. `System` follows the system-wide THP
+/// setting, `NoThp` and `Thp` override it with madvise(2) and `Hugetlb`
+/// allocates from the hugetlb pool, which must be reserved beforehand.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnonMode {
+    #[default]
+    System,
+    NoThp,
+    Thp,
+    Hugetlb,
+}
+
+impl std::str::FromStr for AnonMode {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        Ok(match input {
+            "system" => Self::System,
+            "nothp" => Self::NoThp,
+            "thp" => Self::Thp,
+            "hugetlb" => Self::Hugetlb,
+            _ => bail!("invalid anon mode {:?}", input),
+        })
+    }
+}
+
 struct AnonUnit {
     data: *mut u8,
-    layout: Layout,
+    size: usize,
 }
 
 impl AnonUnit {
-    fn new(size: usize) -> Self {
-        let layout = Layout::from_size_align(size, *PAGE_SIZE).unwrap();
-        Self {
-            data: unsafe { alloc(layout) },
-            layout,
+    fn mmap(size: usize, flags: i32) -> Result<*mut u8> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | flags,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            bail!("mmap failed ({:?})", std::io::Error::last_os_error());
+        }
+        Ok(ptr.cast::<u8>())
+    }
+
+    fn new(size: usize, mode: AnonMode, numa: NumaPolicy) -> Result<Self> {
+        let unit = Self::new_mapped(size, mode)?;
+        if let Err(e) = numa.mbind(unit.data, size) {
+            warn!("anon_area: Failed to apply {:?} ({:#})", numa, &e);
+        }
+        Ok(unit)
+    }
+
+    fn new_mapped(size: usize, mode: AnonMode) -> Result<Self> {
+        if mode == AnonMode::Hugetlb {
+            let data = Self::mmap(size, libc::MAP_HUGETLB)?;
+            return Ok(Self { data, size });
+        }
+
+        // Over-allocate and trim so that the unit is aligned to huge pages.
+        let raw = Self::mmap(size + HPAGE_SIZE, 0)?;
+        let head = raw.align_offset(HPAGE_SIZE);
+        let data = unsafe {
+            if head > 0 {
+                libc::munmap(raw.cast(), head);
+            }
+            libc::munmap(raw.add(head + size).cast(), HPAGE_SIZE - head);
+            raw.add(head)
+        };
+
+        let advice = match mode {
+            AnonMode::NoThp => libc::MADV_NOHUGEPAGE,
+            AnonMode::Thp => libc::MADV_HUGEPAGE,
+            _ => return Ok(Self { data, size }),
+        };
+        if unsafe { libc::madvise(data.cast(), size, advice) } < 0 {
+            warn!(
+                "anon_area: madvise({:?}) failed ({:?})",
+                mode,
+                std::io::Error::last_os_error()
+            );
         }
+        Ok(Self { data, size })
     }
 }
 
@@ -28,7 +111,7 @@ unsafe impl Sync for AnonUnit {}
 impl Drop for AnonUnit {
     fn drop(&mut self) {
         unsafe {
-            dealloc(self.data, self.layout);
+            libc::munmap(self.data.cast(), self.size);
         }
     }
 }
@@ -37,6 +120,9 @@ pub struct AnonArea {
     units: Vec<AnonUnit>,
     size: usize,
     comp: f64,
+    mode: AnonMode,
+    numa: NumaPolicy,
+    seed: Option<u64>,
 }
 
 /// Anonymous memory which can be shared by multiple threads with RwLock
@@ -45,11 +131,14 @@ pub struct AnonArea {
 impl AnonArea {
     const UNIT_SIZE: usize = 32 << 20;
 
-    pub fn new(size: usize, comp: f64) -> Self {
+    pub fn new(size: usize, comp: f64, mode: AnonMode, numa: NumaPolicy) -> Self {
         let mut area = AnonArea {
             units: Vec::new(),
             size: 0,
             comp,
+            mode,
+            numa,
+            seed: None,
         };
         area.resize(size);
         area
@@ -59,6 +148,18 @@ impl AnonArea {
         self.size
     }
 
+    /// Make page contents a function of `seed` and the page index instead
+    /// of random. See `fill_page_with_random()`.
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+    }
+
+    /// The backing mode in effect. This may differ from the requested one
+    /// if hugetlb allocation failed.
+    pub fn mode(&self) -> AnonMode {
+        self.mode
+    }
+
     pub fn resize(&mut self, mut size: usize) {
         size = size.max(Self::UNIT_SIZE);
         let nr = Integer::div_ceil(&size, &Self::UNIT_SIZE);
@@ -66,12 +167,128 @@ impl AnonArea {
         self.units.truncate(nr);
         self.units.reserve(nr);
         for _ in self.units.len()..nr {
-            self.units.push(AnonUnit::new(Self::UNIT_SIZE));
+            let unit = match AnonUnit::new(Self::UNIT_SIZE, self.mode, self.numa) {
+                Ok(v) => v,
+                Err(e) if self.mode == AnonMode::Hugetlb => {
+                    warn!(
+                        "anon_area: Failed to allocate hugetlb pages, falling back to THP ({:#})",
+                        &e
+                    );
+                    self.mode = AnonMode::Thp;
+                    AnonUnit::new(Self::UNIT_SIZE, self.mode, self.numa).unwrap()
+                }
+                Err(e) => panic!("anon_area: Failed to allocate memory ({:#})", &e),
+            };
+            self.units.push(unit);
         }
 
         self.size = size;
     }
 
+    /// Returns a closure which tests whether the address range [start, end)
+    /// overlaps the area. Adjacent units may be merged into a single
+    /// mapping, so /proc mappings should be matched by overlap rather than
+    /// exact range.
+    fn overlap_matcher(&self) -> impl Fn(usize, usize) -> bool {
+        let mut ranges: Vec<(usize, usize)> = self
+            .units
+            .iter()
+            .map(|unit| (unit.data as usize, unit.data as usize + unit.size))
+            .collect();
+        ranges.sort_unstable();
+
+        move |start, end| {
+            let idx = ranges.partition_point(|r| r.1 <= start);
+            idx < ranges.len() && ranges[idx].0 < end
+        }
+    }
+
+    /// Returns the number of bytes resident on each NUMA node as reported
+    /// by /proc/self/numa_maps.
+    pub fn node_sizes(&self) -> Result<BTreeMap<u32, usize>> {
+        let overlaps = self.overlap_matcher();
+
+        // numa_maps only carries the start addresses. Look up the ends in
+        // /proc/self/maps so that mappings which start before the area but
+        // extend into it are counted too.
+        let mut ends = HashMap::new();
+        for line in fs::read_to_string("/proc/self/maps")?.lines() {
+            // "7f0000000000-7f0002000000 rw-p 00000000 00:00 0"
+            let range = line.split_whitespace().next().unwrap_or("");
+            if let Some((start, end)) = range.split_once('-') {
+                if let (Ok(start), Ok(end)) = (
+                    usize::from_str_radix(start, 16),
+                    usize::from_str_radix(end, 16),
+                ) {
+                    ends.insert(start, end);
+                }
+            }
+        }
+
+        let mut sizes = BTreeMap::new();
+        for line in fs::read_to_string("/proc/self/numa_maps")?.lines() {
+            // "7f0000000000 default anon=512 dirty=512 N0=256 N1=256 kernelpagesize_kB=4"
+            let mut toks = line.split_whitespace();
+            let start = match toks.next().map(|v| usize::from_str_radix(v, 16)) {
+                Some(Ok(v)) => v,
+                _ => continue,
+            };
+            let end = ends.get(&start).cloned().unwrap_or(start + 1);
+            if !overlaps(start, end) {
+                continue;
+            }
+
+            let mut pages = vec![];
+            let mut page_size = *PAGE_SIZE;
+            for tok in toks {
+                if let Some((key, val)) = tok.split_once('=') {
+                    if let Some(node) = key.strip_prefix('N') {
+                        if let (Ok(node), Ok(nr)) = (node.parse::<u32>(), val.parse::<usize>()) {
+                            pages.push((node, nr));
+                        }
+                    } else if key == "kernelpagesize_kB" {
+                        page_size = val.parse::<usize>()? << 10;
+                    }
+                }
+            }
+            for (node, nr) in pages {
+                *sizes.entry(node).or_insert(0) += nr * page_size;
+            }
+        }
+        Ok(sizes)
+    }
+
+    /// Returns the number of bytes backed by huge pages, either THP or
+    /// hugetlb, as reported by /proc/self/smaps.
+    pub fn huge_size(&self) -> Result<usize> {
+        let overlaps = self.overlap_matcher();
+
+        let mut huge_size = 0;
+        let mut in_area = false;
+        for line in fs::read_to_string("/proc/self/smaps")?.lines() {
+            let mut toks = line.split_whitespace();
+            let key = match toks.next() {
+                Some(v) => v,
+                None => continue,
+            };
+            if let Some((start, end)) = key.split_once('-') {
+                if let (Ok(start), Ok(end)) = (
+                    usize::from_str_radix(start, 16),
+                    usize::from_str_radix(end, 16),
+                ) {
+                    in_area = overlaps(start, end);
+                    continue;
+                }
+            }
+            if in_area && (key == "AnonHugePages:" || key == "Private_Hugetlb:") {
+                if let Some(Ok(kb)) = toks.next().map(|v| v.parse::<usize>()) {
+                    huge_size += kb << 10;
+                }
+            }
+        }
+        Ok(huge_size)
+    }
+
     /// Determine the page given the relative position `rel` and `size` of
     /// the anon area. `rel` is in the range [-1.0, 1.0] with the position
     /// 0.0 mapping to the first page, positive positions to even slots and
@@ -103,6 +320,11 @@ impl AnonArea {
     }
 
     pub fn fill_page_with_random(&self, page_idx: usize) {
+        if self.seed.is_some() {
+            let mut rng = super::seeded_rng(self.seed, "anon_area", page_idx as u64);
+            super::fill_area_with_random(self.access_page::<u8>(page_idx), self.comp, &mut rng);
+            return;
+        }
         RNG.with(|s| {
             super::fill_area_with_random(
                 self.access_page::<u8>(page_idx),
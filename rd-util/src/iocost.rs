@@ -76,9 +76,11 @@ impl IoCostQoSParams {
     }
 }
 
-/// Save /sys/fs/cgroup/io.cost.model,qos and restore them on drop.
-#[derive(Default)]
-pub struct IoCostSysSave {
+/// /sys/fs/cgroup/io.cost.model,qos settings of a device. Plain data which
+/// can be persisted, see `IoCostSysSave` for restoring on drop.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IoCostSysParams {
     pub devnr: (u32, u32),
     pub enable: bool,
     pub model_ctrl_user: bool,
@@ -87,7 +89,7 @@ pub struct IoCostSysSave {
     pub qos: IoCostQoSParams,
 }
 
-impl IoCostSysSave {
+impl IoCostSysParams {
     pub fn read_from_sys(devnr: (u32, u32)) -> Result<Self> {
         let model = super::read_cgroup_nested_keyed_file("/sys/fs/cgroup/io.cost.model")
             .map_err(|e| anyhow!("failed to read io.cost.model ({})", &e))?;
@@ -95,8 +97,10 @@ impl IoCostSysSave {
             .map_err(|e| anyhow!("failed to read io.cost.model ({})", &e))?;
         let devnr_str = format!("{}:{}", devnr.0, devnr.1);
 
-        let mut params = IoCostSysSave::default();
-        params.devnr = devnr;
+        let mut params = IoCostSysParams {
+            devnr,
+            ..Default::default()
+        };
 
         let model = match model.get(&devnr_str) {
             Some(v) => v,
@@ -171,9 +175,27 @@ impl IoCostSysSave {
     }
 }
 
+/// Save /sys/fs/cgroup/io.cost.model,qos and restore them on drop.
+#[derive(Default)]
+pub struct IoCostSysSave(IoCostSysParams);
+
+impl IoCostSysSave {
+    pub fn read_from_sys(devnr: (u32, u32)) -> Result<Self> {
+        Ok(Self(IoCostSysParams::read_from_sys(devnr)?))
+    }
+}
+
+impl std::ops::Deref for IoCostSysSave {
+    type Target = IoCostSysParams;
+
+    fn deref(&self) -> &IoCostSysParams {
+        &self.0
+    }
+}
+
 impl Drop for IoCostSysSave {
     fn drop(&mut self) {
-        if let Err(e) = self.write_to_sys() {
+        if let Err(e) = self.0.write_to_sys() {
             error!("Failed to restore io.cost.model,qos ({})", &e);
         }
     }
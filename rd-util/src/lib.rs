@@ -4,6 +4,8 @@ use chrono::{DateTime, Local};
 use crossbeam::channel::Sender;
 use glob::glob;
 use log::{error, info, warn};
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
 use scan_fmt::scan_fmt;
 use serde::{Deserialize, Serialize};
 use simplelog as sl;
@@ -31,10 +33,11 @@ pub mod anon_area;
 pub mod iocost;
 pub mod journal_tailer;
 pub mod json_file;
+pub mod numa;
 pub mod storage_info;
 pub mod systemd;
 
-pub use iocost::{IoCostModelParams, IoCostQoSParams, IoCostSysSave};
+pub use iocost::{IoCostModelParams, IoCostQoSParams, IoCostSysParams, IoCostSysSave};
 pub use journal_tailer::*;
 pub use json_file::{
     JsonArgs, JsonArgsHelper, JsonConfigFile, JsonLoad, JsonRawFile, JsonReportFile, JsonSave,
@@ -788,6 +791,23 @@ pub fn run_command(cmd: &mut Command, emsg: &str) -> Result<()> {
     }
 }
 
+/// Returns an rng for `idx`'th instance of `stream` derived from `seed`, or
+/// seeded from entropy if `seed` is None. Each stream and index gets an
+/// independent sequence so that consumers don't perturb each other.
+pub fn seeded_rng(seed: Option<u64>, stream: &str, idx: u64) -> SmallRng {
+    match seed {
+        Some(seed) => {
+            // FNV-1a of the stream name
+            let mut hash: u64 = 0xcbf29ce484222325;
+            for byte in stream.bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+            SmallRng::seed_from_u64(seed ^ hash ^ idx.wrapping_mul(0x9e3779b97f4a7c15))
+        }
+        None => SmallRng::from_entropy(),
+    }
+}
+
 pub fn fill_area_with_random<T, R: rand::Rng + ?Sized>(area: &mut [T], comp: f64, rng: &mut R) {
     let area = unsafe {
         std::slice::from_raw_parts_mut(
@@ -989,4 +1009,20 @@ mod tests {
             println!("{} -> {} ({})", pair.1, result, pair.0);
         }
     }
+
+    #[test]
+    fn test_seeded_rng() {
+        use rand::Rng;
+
+        let seq = |seed, stream, idx| -> Vec<u64> {
+            let mut rng = super::seeded_rng(seed, stream, idx);
+            (0..8).map(|_| rng.gen()).collect()
+        };
+
+        assert_eq!(seq(Some(42), "testfiles", 3), seq(Some(42), "testfiles", 3));
+        assert_ne!(seq(Some(42), "testfiles", 3), seq(Some(43), "testfiles", 3));
+        assert_ne!(seq(Some(42), "testfiles", 3), seq(Some(42), "anon_area", 3));
+        assert_ne!(seq(Some(42), "testfiles", 3), seq(Some(42), "testfiles", 4));
+        assert_ne!(seq(None, "testfiles", 3), seq(None, "testfiles", 3));
+    }
 }
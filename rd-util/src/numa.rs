@@ -0,0 +1,205 @@
+// Copyright (c) Facebook, Inc. and its affiliates.
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const NODE_DIR: &str = "/sys/devices/system/node";
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NumaNode {
+    pub id: u32,
+    pub cpus: Vec<usize>,
+    pub mem_total: usize,
+}
+
+/// Parse a kernel list format string, e.g. "0-3,8,10-11".
+pub fn parse_list(input: &str) -> Result<Vec<usize>> {
+    let mut list = vec![];
+    for tok in input.trim().split(',').filter(|t| !t.is_empty()) {
+        match tok.split_once('-') {
+            Some((first, last)) => list.extend(first.parse::<usize>()?..=last.parse::<usize>()?),
+            None => list.push(tok.parse::<usize>()?),
+        }
+    }
+    Ok(list)
+}
+
+fn node_mem_total(node_dir: &Path) -> Result<usize> {
+    for line in fs::read_to_string(node_dir.join("meminfo"))?.lines() {
+        // "Node 0 MemTotal:       65536000 kB"
+        let toks: Vec<&str> = line.split_whitespace().collect();
+        if toks.len() >= 4 && toks[2] == "MemTotal:" {
+            return Ok(toks[3].parse::<usize>()? << 10);
+        }
+    }
+    bail!("MemTotal not found in {:?}", node_dir)
+}
+
+/// Enumerate the online NUMA nodes. Systems without NUMA support are
+/// reported as a single node containing everything.
+pub fn numa_nodes() -> Result<Vec<NumaNode>> {
+    if !Path::new(NODE_DIR).exists() {
+        return Ok(vec![NumaNode {
+            id: 0,
+            cpus: (0..*super::NR_SYSTEM_CPUS).collect(),
+            mem_total: *super::TOTAL_SYSTEM_MEMORY,
+        }]);
+    }
+
+    let mut nodes = vec![];
+    for id in parse_list(&fs::read_to_string(format!("{}/online", NODE_DIR))?)? {
+        let node_dir = Path::new(NODE_DIR).join(format!("node{}", id));
+        nodes.push(NumaNode {
+            id: id as u32,
+            cpus: parse_list(&fs::read_to_string(node_dir.join("cpulist"))?)?,
+            mem_total: node_mem_total(&node_dir)?,
+        });
+    }
+    Ok(nodes)
+}
+
+/// NUMA memory placement policy. All but `Default` also set the thread
+/// affinity to match, see `bind_cpus()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumaPolicy {
+    #[default]
+    Default,
+    Local,
+    Interleave,
+    Bind(u32),
+}
+
+impl std::str::FromStr for NumaPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        Ok(match input {
+            "default" => Self::Default,
+            "local" => Self::Local,
+            "interleave" => Self::Interleave,
+            _ => match input.strip_prefix("bind:") {
+                Some(node) => Self::Bind(node.parse::<u32>()?),
+                None => bail!("invalid NUMA policy {:?}", input),
+            },
+        })
+    }
+}
+
+impl NumaPolicy {
+    fn nodemask(nodes: &[u32]) -> Vec<libc::c_ulong> {
+        let bits = libc::c_ulong::BITS;
+        let max = nodes.iter().max().cloned().unwrap_or(0);
+        let mut mask = vec![0; (max / bits + 1) as usize];
+        for &node in nodes {
+            mask[(node / bits) as usize] |= 1 << (node % bits);
+        }
+        mask
+    }
+
+    /// Apply the policy to the memory area at `addr` with `len` bytes with
+    /// mbind(2). Must be called before the pages are faulted in.
+    pub(crate) fn mbind(&self, addr: *mut u8, len: usize) -> Result<()> {
+        let (mode, nodes) = match self {
+            Self::Default => return Ok(()),
+            Self::Local => (libc::MPOL_LOCAL, vec![]),
+            Self::Interleave => (
+                libc::MPOL_INTERLEAVE,
+                numa_nodes()?.iter().map(|node| node.id).collect(),
+            ),
+            Self::Bind(node) => (libc::MPOL_BIND, vec![*node]),
+        };
+        let mask = match nodes.len() {
+            0 => vec![],
+            _ => Self::nodemask(&nodes),
+        };
+        let maxnode = mask.len() * libc::c_ulong::BITS as usize + 1;
+
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                addr,
+                len,
+                mode,
+                if mask.is_empty() {
+                    std::ptr::null()
+                } else {
+                    mask.as_ptr()
+                },
+                if mask.is_empty() { 0 } else { maxnode },
+                0,
+            )
+        };
+        if ret < 0 {
+            bail!("mbind failed ({:?})", std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Set the calling thread's CPU affinity to match the policy. Threads
+    /// created afterwards inherit the affinity.
+    ///
+    /// `Bind` and `Local` restrict the threads to the CPUs of the bound node
+    /// and the node the thread is currently running on respectively, so
+    /// that memory accesses stay node-local. `Interleave` allows all nodes'
+    /// CPUs so that the threads spread out like the memory does.
+    pub fn bind_cpus(&self) -> Result<()> {
+        let nodes = numa_nodes()?;
+        let cpus: Vec<usize> = match self {
+            Self::Default => return Ok(()),
+            Self::Local => {
+                let cpu = unsafe { libc::sched_getcpu() };
+                if cpu < 0 {
+                    bail!(
+                        "sched_getcpu failed ({:?})",
+                        std::io::Error::last_os_error()
+                    );
+                }
+                nodes
+                    .iter()
+                    .find(|node| node.cpus.contains(&(cpu as usize)))
+                    .ok_or_else(|| anyhow!("NUMA node of CPU {} not found", cpu))?
+                    .cpus
+                    .clone()
+            }
+            Self::Interleave => nodes.iter().flat_map(|node| node.cpus.clone()).collect(),
+            Self::Bind(id) => nodes
+                .iter()
+                .find(|node| node.id == *id)
+                .ok_or_else(|| anyhow!("NUMA node {} not found", id))?
+                .cpus
+                .clone(),
+        };
+        if cpus.is_empty() {
+            bail!("no CPUs to run on for NUMA policy {:?}", self);
+        }
+
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for &cpu in cpus.iter() {
+                libc::CPU_SET(cpu, &mut set);
+            }
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) < 0 {
+                bail!(
+                    "sched_setaffinity failed ({:?})",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(super::parse_list("0\n").unwrap(), vec![0]);
+        assert_eq!(
+            super::parse_list("0-3,8,10-11").unwrap(),
+            vec![0, 1, 2, 3, 8, 10, 11]
+        );
+        assert!(super::parse_list("").unwrap().is_empty());
+        assert!(super::parse_list("a-b").is_err());
+    }
+}
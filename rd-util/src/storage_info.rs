@@ -177,15 +177,20 @@ pub fn devname_to_model_fwrev_size<D: AsRef<OsStr>>(name_in: D) -> Result<(Strin
     Ok((model, fwrev, size))
 }
 
+/// Find the device hosting a swap partition or file
+pub fn swap_devname<P: AsRef<Path>>(source: P, is_partition: bool) -> Result<OsString> {
+    if is_partition {
+        devnr_to_devname(fs::metadata(source.as_ref())?.st_rdev())
+    } else {
+        path_to_devname(source)
+    }
+}
+
 /// Find all devices hosting swap
 pub fn swap_devnames() -> Result<Vec<OsString>> {
     let mut devnames = Vec::new();
     for swap in SwapIter::new()?.filter_map(|sw| sw.ok()) {
-        if swap.kind == "partition" {
-            devnames.push(devnr_to_devname(fs::metadata(&swap.source)?.st_rdev())?);
-        } else {
-            devnames.push(path_to_devname(&swap.source)?);
-        }
+        devnames.push(swap_devname(&swap.source, swap.kind == "partition")?);
     }
     Ok(devnames)
 }
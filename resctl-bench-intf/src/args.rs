@@ -37,6 +37,7 @@ lazy_static::lazy_static! {
                  --keep-reports           'Prevents deleting expired report files'
                  --clear-reports          'Removes existing report files'
                  --force                  'Ignore missing system requirements and proceed'
+                 --delegated              'Run rd-agent inside a delegated cgroup subtree (e.g. container)'
                  --force-shadow-inode-prot-test 'Force shadow inode protection test'
                  --skip-shadow-inode-prot-test 'Assume shadow inodes are protected without testing'
                  --test                   'Test mode for development'
@@ -153,6 +154,8 @@ pub struct Args {
     #[serde(skip)]
     pub force: bool,
     #[serde(skip)]
+    pub delegated: bool,
+    #[serde(skip)]
     pub force_shadow_inode_prot_test: bool,
     #[serde(skip)]
     pub skip_shadow_inode_prot_test: bool,
@@ -205,6 +208,7 @@ impl Default for Args {
             keep_reports: false,
             clear_reports: false,
             force: false,
+            delegated: false,
             force_shadow_inode_prot_test: false,
             skip_shadow_inode_prot_test: false,
             test: false,
@@ -650,6 +654,7 @@ impl JsonArgs for Args {
         self.keep_reports = matches.is_present("keep-reports");
         self.clear_reports = matches.is_present("clear-reports");
         self.force = matches.is_present("force");
+        self.delegated = matches.is_present("delegated");
         self.force_shadow_inode_prot_test = matches.is_present("force-shadow-inode-prot-test");
         self.skip_shadow_inode_prot_test = matches.is_present("skip-shadow-inode-prot-test");
         self.test = matches.is_present("test");
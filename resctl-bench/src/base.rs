@@ -52,7 +52,7 @@ impl<'a> Base<'a> {
     fn prep_bench(
         args: &'a Args,
         scr_devname: &str,
-        iocost_sys_save: &IoCostSysSave,
+        iocost_sys_save: Option<&IoCostSysSave>,
     ) -> Result<rd_agent_intf::BenchKnobs> {
         let (dev_model, dev_fwrev, dev_size) =
             devname_to_model_fwrev_size(&scr_devname).map_err(|e| {
@@ -109,12 +109,13 @@ impl<'a> Base<'a> {
         bench.iocost_dev_size = dev_size;
 
         if args.iocost_from_sys {
-            if !iocost_sys_save.enable {
-                bail!(
+            let iocost_sys_save = match iocost_sys_save {
+                Some(v) if v.enable => v,
+                _ => bail!(
                     "--iocost-from-sys specified but iocost is disabled for {:?}",
                     &scr_devname
-                );
-            }
+                ),
+            };
             bench.iocost_seq = 1;
             bench.iocost.model = iocost_sys_save.model.clone();
             bench.iocost.qos = iocost_sys_save.qos.clone();
@@ -164,10 +165,15 @@ impl<'a> Base<'a> {
         };
         let scr_devnr = devname_to_devnr(&scr_devname)
             .expect("failed to resolve device number for scratch device");
-        let iocost_sys_save =
-            IoCostSysSave::read_from_sys(scr_devnr).expect("failed to read iocost.model,qos");
+        // io.cost.model,qos aren't visible from a delegated subtree.
+        let iocost_sys_save = match args.delegated {
+            false => Some(
+                IoCostSysSave::read_from_sys(scr_devnr).expect("failed to read iocost.model,qos"),
+            ),
+            true => None,
+        };
 
-        let bench_knobs = match Self::prep_bench(args, &scr_devname, &iocost_sys_save) {
+        let bench_knobs = match Self::prep_bench(args, &scr_devname, iocost_sys_save.as_ref()) {
             Ok(v) => v,
             Err(e) => {
                 error!("Failed to prepare bench files ({})", &e);
@@ -2359,6 +2359,7 @@ impl IoCostTuneJob {
             .filter_map(|s| s)
             .collect();
 
+        writeln!(out, "  IOCOST_FSTYPE={}", data.sysinfo.fstype()).unwrap();
         write!(
             out,
             "  IOCOST_SOLUTIONS={}",
@@ -1,6 +1,7 @@
 // Copyright (c) Facebook, Inc. and its affiliates.
 use super::super::*;
 use rd_agent_intf::{bandit_report::BanditMemHogReport, Report, Slice};
+use rd_hashd_intf::ClassStat;
 use std::cell::RefCell;
 use std::collections::{BTreeMap, VecDeque};
 
@@ -150,6 +151,18 @@ pub struct MemHogResult {
     pub hog_io_loss: f64,
     pub hog_bytes: u64,
     pub hog_lost_bytes: u64,
+    #[serde(default)]
+    pub oom_kills: u64,
+    #[serde(default)]
+    pub work_oom_kills: u64,
+    #[serde(default)]
+    pub oomd_kills: u64,
+    #[serde(default)]
+    pub class_base_lat: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub class_lat_imp: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub hog_lat: PctsMap,
 }
 
 impl MemHog {
@@ -376,6 +389,43 @@ impl MemHog {
         (lat / base_lat - 1.0).max(0.0)
     }
 
+    /// Accumulate per request class (sum, count) pairs of `calc(ctl_lat)`.
+    fn class_lat_acc<F>(acc: &mut BTreeMap<String, (f64, usize)>, arg: &SelArg, calc: F)
+    where
+        F: Fn(&str, f64) -> Option<f64>,
+    {
+        for (name, cs) in arg.rep.hashd[0].classes.iter() {
+            if let Some(v) = calc(name, hashd_class_ctl_lat(cs)) {
+                let ent = acc.entry(name.clone()).or_insert((0.0, 0));
+                ent.0 += v * arg.cnt as f64;
+                ent.1 += arg.cnt;
+            }
+        }
+    }
+
+    fn class_lat_means(acc: BTreeMap<String, (f64, usize)>) -> BTreeMap<String, f64> {
+        acc.into_iter()
+            .filter(|(_, (_, cnt))| *cnt > 0)
+            .map(|(name, (sum, cnt))| (name, sum / cnt as f64))
+            .collect()
+    }
+
+    /// Merge per request class stats. The merged latencies are calculated
+    /// from the merged histograms.
+    fn class_stat_acc(acc: &mut BTreeMap<String, ClassStat>, arg: &SelArg) {
+        for (name, cs) in arg.rep.hashd[0].classes.iter() {
+            *acc.entry(name.clone()).or_default() += cs;
+        }
+    }
+
+    /// Override `lats` with the control latencies of the merged class stats
+    /// which carry histograms.
+    fn class_hist_lats(lats: &mut BTreeMap<String, f64>, stats: &BTreeMap<String, ClassStat>) {
+        for (name, cs) in stats.iter().filter(|(_, cs)| !cs.lat_hist.is_empty()) {
+            lats.insert(name.clone(), cs.lat.ctl);
+        }
+    }
+
     pub fn study(rctx: &RunCtx, rec: &MemHogRecord) -> Result<MemHogResult> {
         // We might already have run before as a part of the run phase. If
         // so, return the cached result.
@@ -389,14 +439,33 @@ impl MemHog {
         // possible.
 
         // Determine the baseline latency. We need it for the latency impact
-        // study. Run it first.
-        let mut study_base_lat = StudyMean::new(|arg| [arg.rep.hashd[0].lat.ctl].repeat(arg.cnt));
+        // study. Run it first. The latency is calculated from the merged
+        // histograms. The per-report mean is used for stdev and as the
+        // fallback if the reports don't carry histograms.
+        let mut study_base_lat =
+            StudyMean::new(|arg| [hashd_ctl_lat(&arg.rep.hashd[0])].repeat(arg.cnt));
+        let mut study_base_lat_hist = StudyHashdLatHist::new();
+
+        // If rd-hashd is configured with multiple request classes, track
+        // each class's baseline too so that class-specific degradations
+        // are visible.
+        let mut class_base_acc = BTreeMap::new();
+        let mut class_base_stats = BTreeMap::new();
+        let mut study_class_base_lat = StudyMutFn::new(|arg| {
+            Self::class_lat_acc(&mut class_base_acc, arg, |_, lat| Some(lat));
+            Self::class_stat_acc(&mut class_base_stats, arg);
+        });
 
         Studies::new()
             .add(&mut study_base_lat)
+            .add(&mut study_base_lat_hist)
+            .add(&mut study_class_base_lat)
             .run(rctx, rec.base_period)?;
 
-        let (base_lat, base_lat_stdev, _, _) = study_base_lat.result();
+        let (base_lat_mean, base_lat_stdev, _, _) = study_base_lat.result();
+        let base_lat = study_base_lat_hist.ctl().unwrap_or(base_lat_mean);
+        let mut class_base_lat = Self::class_lat_means(class_base_acc);
+        Self::class_hist_lats(&mut class_base_lat, &class_base_stats);
 
         // Study work isolation and latency impact. The former is defined as
         // observed rps divided by the baseline, [0.0, 1.0] with 1.0
@@ -415,7 +484,7 @@ impl MemHog {
         let mut study_lat_imp = StudyMeanPcts::new(
             |arg| {
                 [Self::calc_lat_imp(
-                    arg.rep.hashd[0].lat.ctl.max(base_lat),
+                    hashd_ctl_lat(&arg.rep.hashd[0]).max(base_lat),
                     base_lat,
                 )]
                 .repeat(arg.cnt)
@@ -423,6 +492,16 @@ impl MemHog {
             None,
         );
 
+        let mut class_lat_imp_acc = BTreeMap::new();
+        let mut study_class_lat_imp = StudyMutFn::new(|arg| {
+            Self::class_lat_acc(&mut class_lat_imp_acc, arg, |name, lat| {
+                class_base_lat
+                    .get(name)
+                    .filter(|base| **base > 0.0)
+                    .map(|base| Self::calc_lat_imp(lat.max(*base), *base))
+            })
+        });
+
         // If the run failed because agent or hashd couldn't be kept healthy
         // enoungh, consider the rest of the run to have completely failed
         // isolation - 0% isol, 100% lat-imp.
@@ -466,6 +545,28 @@ impl MemHog {
             }
         });
 
+        // Count kernel OOM kills and oomd kills. The counters are
+        // cumulative but oomd's resets when the agent restarts, so only
+        // count the increments.
+        let (mut oom_kills, mut work_oom_kills, mut oomd_kills) = (0_u64, 0_u64, 0_u64);
+        let last_kills = RefCell::new(None);
+
+        let mut study_kills = StudyMutFn::new(|arg| {
+            let cur = (
+                arg.rep.usages[ROOT_SLICE].mem_events.oom_kill,
+                match arg.rep.usages.get(Slice::Work.name()) {
+                    Some(u) => u.mem_events.oom_kill,
+                    None => 0,
+                },
+                arg.rep.oomd.nr_kills,
+            );
+            if let Some(last) = last_kills.replace(Some(cur)) {
+                oom_kills += cur.0.saturating_sub(last.0);
+                work_oom_kills += cur.1.saturating_sub(last.1);
+                oomd_kills += cur.2.saturating_sub(last.2);
+            }
+        });
+
         let root_rstat_study_ctx = ResourceStatStudyCtx::new();
         let work_rstat_study_ctx = ResourceStatStudyCtx::new();
         let sys_rstat_study_ctx = ResourceStatStudyCtx::new();
@@ -474,10 +575,17 @@ impl MemHog {
             ResourceStatStudy::new(Slice::Work.name(), &work_rstat_study_ctx);
         let mut sys_rstat_study = ResourceStatStudy::new(Slice::Sys.name(), &sys_rstat_study_ctx);
 
+        // Request latency percentiles over all hog periods from the merged
+        // rd-hashd latency histograms.
+        let mut study_hog_lat = StudyHashdLatHist::new();
+
         let mut studies = Studies::new()
             .add(&mut study_isol)
             .add(&mut study_lat_imp)
+            .add(&mut study_class_lat_imp)
+            .add(&mut study_hog_lat)
             .add(&mut study_io_usages)
+            .add(&mut study_kills)
             .add_multiple(&mut root_rstat_study.studies())
             .add_multiple(&mut work_rstat_study.studies())
             .add_multiple(&mut sys_rstat_study.studies());
@@ -497,6 +605,7 @@ impl MemHog {
             last_nr_done.replace(None);
             last_root_io_usage.replace(None);
             last_hog_io_usage.replace(None);
+            last_kills.replace(None);
             work_rstat_study_ctx.reset();
             sys_rstat_study_ctx.reset();
 
@@ -506,6 +615,8 @@ impl MemHog {
         let isol = study_isol.result(None);
         let lat_imp = study_lat_imp.result(None);
         let root_rstat = root_rstat_study.result(None);
+        let class_lat_imp = Self::class_lat_means(class_lat_imp_acc);
+        let hog_lat = study_hog_lat.result(None);
         let work_rstat = work_rstat_study.result(None);
         let sys_rstat = sys_rstat_study.result(None);
 
@@ -589,6 +700,12 @@ impl MemHog {
             hog_io_loss,
             hog_bytes,
             hog_lost_bytes,
+            oom_kills,
+            work_oom_kills,
+            oomd_kills,
+            class_base_lat,
+            class_lat_imp,
+            hog_lat,
         })
     }
 
@@ -606,9 +723,23 @@ impl MemHog {
         // stdevs: Sqrt of pooled variance by the number of runs.
         // sums: Simple sum.
         let mut total_runs = 0;
+        let mut class_base_lat_acc = BTreeMap::new();
+        let mut class_lat_imp_acc = BTreeMap::new();
         for (rec, res) in rrs.iter() {
             total_runs += rec.runs.len();
 
+            // Classes may differ between results. Weight per class.
+            let class_wsum = |acc: &mut BTreeMap<String, (f64, usize)>,
+                              src: &BTreeMap<String, f64>| {
+                for (name, v) in src.iter() {
+                    let ent = acc.entry(name.clone()).or_insert((0.0, 0));
+                    ent.0 += v * rec.runs.len() as f64;
+                    ent.1 += rec.runs.len();
+                }
+            };
+            class_wsum(&mut class_base_lat_acc, &res.class_base_lat);
+            class_wsum(&mut class_lat_imp_acc, &res.class_lat_imp);
+
             // Weighted sum for weighted avg calculation.
             let wsum = |c: &mut f64, v: f64| *c += v * (rec.runs.len() as f64);
             wsum(&mut cmb.base_rps, res.base_rps);
@@ -631,6 +762,9 @@ impl MemHog {
             cmb.hog_io_loss += res.hog_io_loss;
             cmb.hog_bytes += res.hog_bytes;
             cmb.hog_lost_bytes += res.hog_lost_bytes;
+            cmb.oom_kills += res.oom_kills;
+            cmb.work_oom_kills += res.work_oom_kills;
+            cmb.oomd_kills += res.oomd_kills;
 
             cmb.periods.append(&mut res.periods.clone());
             cmb.hog_periods.append(&mut res.hog_periods.clone());
@@ -641,6 +775,25 @@ impl MemHog {
         cmb.base_lat /= base;
         cmb.work_csv /= base;
         cmb.vrate /= base;
+        cmb.class_base_lat = Self::class_lat_means(class_base_lat_acc);
+        cmb.class_lat_imp = Self::class_lat_means(class_lat_imp_acc);
+
+        // Averaged percentiles aren't percentiles. If histograms are
+        // available, recalculate the baseline latencies from the merged ones.
+        let mut study_base_lat_hist = StudyHashdLatHist::new();
+        let mut class_base_stats = BTreeMap::new();
+        let mut study_class_base_stats =
+            StudyMutFn::new(|arg| Self::class_stat_acc(&mut class_base_stats, arg));
+        let mut studies = Studies::new()
+            .add(&mut study_base_lat_hist)
+            .add(&mut study_class_base_stats);
+        for (rec, _) in rrs.iter() {
+            studies.run(rctx, rec.base_period)?;
+        }
+        if let Some(lat) = study_base_lat_hist.ctl() {
+            cmb.base_lat = lat;
+        }
+        Self::class_hist_lats(&mut cmb.class_base_lat, &class_base_stats);
 
         if total_runs > rrs.len() {
             let base = (total_runs - rrs.len()) as f64;
@@ -675,7 +828,7 @@ impl MemHog {
         let mut study_lat_imp = StudyMeanPcts::new(
             |arg| {
                 [Self::calc_lat_imp(
-                    arg.rep.hashd[0].lat.ctl.max(*base_lat.borrow()),
+                    hashd_ctl_lat(&arg.rep.hashd[0]).max(*base_lat.borrow()),
                     *base_lat.borrow(),
                 )]
                 .repeat(arg.cnt)
@@ -708,9 +861,12 @@ impl MemHog {
             ResourceStatStudy::new(Slice::Work.name(), &work_rstat_study_ctx);
         let mut sys_rstat_study = ResourceStatStudy::new(Slice::Sys.name(), &sys_rstat_study_ctx);
 
+        let mut study_hog_lat = StudyHashdLatHist::new();
+
         let mut studies = Studies::new()
             .add(&mut study_isol)
             .add(&mut study_lat_imp)
+            .add(&mut study_hog_lat)
             .add_multiple(&mut root_rstat_study.studies())
             .add_multiple(&mut work_rstat_study.studies())
             .add_multiple(&mut sys_rstat_study.studies());
@@ -727,6 +883,7 @@ impl MemHog {
 
         cmb.isol = study_isol.result(None);
         cmb.lat_imp = study_lat_imp.result(None);
+        cmb.hog_lat = study_hog_lat.result(None);
         cmb.root_rstat = root_rstat_study.result(None);
         cmb.work_rstat = work_rstat_study.result(None);
         cmb.sys_rstat = sys_rstat_study.result(None);
@@ -778,11 +935,24 @@ impl MemHog {
         .unwrap();
         writeln!(
             out,
-            "      hog_bytes={} hog_lost_bytes={}\n",
+            "      hog_bytes={} hog_lost_bytes={}",
             format_size(result.hog_bytes),
             format_size(result.hog_lost_bytes)
         )
         .unwrap();
+        if !result.class_base_lat.is_empty() {
+            let mut buf = String::new();
+            for (name, lat) in result.class_base_lat.iter() {
+                write!(buf, " {}={}", name, format_duration(*lat)).unwrap();
+            }
+            writeln!(out, "      class_baseline_lat:{}", buf).unwrap();
+        }
+        writeln!(
+            out,
+            "      oom_kills={} work_oom_kills={} oomd_kills={}\n",
+            result.oom_kills, result.work_oom_kills, result.oomd_kills,
+        )
+        .unwrap();
     }
 
     pub fn format_result<'a>(
@@ -815,6 +985,21 @@ impl MemHog {
         print_pcts_line(out, 8, "isol%", &result.isol, format_pct, None);
         print_pcts_line(out, 8, "lat-imp%", &result.lat_imp, format_pct, None);
 
+        if !result.class_lat_imp.is_empty() {
+            let mut buf = String::new();
+            for (name, imp) in result.class_lat_imp.iter() {
+                write!(buf, " {}={}%", name, format_pct(*imp)).unwrap();
+            }
+            writeln!(out, "\nRequest class latency impact:{}", buf).unwrap();
+        }
+
+        if !result.hog_lat.is_empty() {
+            writeln!(out, "\nRequest Latency Distribution During Hog Periods:\n").unwrap();
+            let pcts = Some(StudyHashdLatHist::PCTS);
+            print_pcts_header(out, 8, "", pcts);
+            print_pcts_line(out, 8, "lat", &result.hog_lat, format_duration, pcts);
+        }
+
         let fail_str = if result.fail_ratio != 0.0 {
             format!("FAIL={}% ", format_pct(result.fail_ratio))
         } else {
@@ -67,6 +67,20 @@ pub struct SysInfo {
     pub mem: MemInfo,
     pub swappiness: u32,
     pub zswap_enabled: bool,
+    #[serde(default)]
+    pub fstype: String,
+    #[serde(default)]
+    pub numa_nodes: Vec<rd_util::numa::NumaNode>,
+}
+
+impl SysInfo {
+    /// Results from before ext4 and xfs support are all on btrfs.
+    pub fn fstype(&self) -> &str {
+        match self.fstype.len() {
+            0 => "btrfs",
+            _ => &self.fstype,
+        }
+    }
 }
 
 impl Default for SysInfo {
@@ -81,6 +95,8 @@ impl Default for SysInfo {
             mem: Default::default(),
             swappiness: 60,
             zswap_enabled: false,
+            fstype: String::new(),
+            numa_nodes: vec![],
         }
     }
 }
@@ -160,6 +176,28 @@ impl JobData {
                 if si.zswap_enabled { " zswap" } else { "" },
             )
             .unwrap();
+            for sd in rep.swap_devs.iter() {
+                write!(
+                    out,
+                    "             swap={}({:?}) dev={} prio={} size={}",
+                    &sd.path,
+                    sd.kind,
+                    &sd.dev,
+                    sd.priority,
+                    format_size(sd.size)
+                )
+                .unwrap();
+                if let Some(zram) = sd.zram.as_ref() {
+                    write!(
+                        out,
+                        " comp={} mem_limit={}",
+                        &zram.comp_algorithm,
+                        format_size_dashed(zram.mem_limit)
+                    )
+                    .unwrap();
+                }
+                writeln!(out, "").unwrap();
+            }
             if si.mem.profile > 0 {
                 writeln!(
                     out,
@@ -171,21 +209,41 @@ impl JobData {
                 )
                 .unwrap();
             }
+            if si.numa_nodes.len() > 1 {
+                for node in si.numa_nodes.iter() {
+                    writeln!(
+                        out,
+                        "             numa_node={} nr_cpus={} memory={}",
+                        node.id,
+                        node.cpus.len(),
+                        format_size(node.mem_total)
+                    )
+                    .unwrap();
+                }
+            }
             let passive = rep.enforce.to_passive_string();
             if passive.len() > 0 {
                 writeln!(out, "             passive={}", &passive).unwrap();
             }
+            if rep.delegated {
+                writeln!(
+                    out,
+                    "             delegated (host-level sysreqs not checked)"
+                )
+                .unwrap();
+            }
             writeln!(out, "").unwrap();
 
             writeln!(
                 out,
-                "IO info: dev={}({}:{}) model=\"{}\" firmware =\"{}\" size={}",
+                "IO info: dev={}({}:{}) model=\"{}\" firmware =\"{}\" size={} fs={}",
                 &rep.scr_dev,
                 rep.scr_devnr.0,
                 rep.scr_devnr.1,
                 &rep.scr_dev_model,
                 &rep.scr_dev_fwrev,
-                format_size(rep.scr_dev_size)
+                format_size(rep.scr_dev_size),
+                si.fstype()
             )
             .unwrap();
 
@@ -374,6 +432,7 @@ impl JobCtx {
 
     fn fill_sysinfo_from_rctx(si: &mut SysInfo, rctx: &RunCtx) {
         si.sysreqs_report = Some((*rctx.sysreqs_report().unwrap()).clone());
+        si.fstype = si.sysreqs_report.as_ref().unwrap().scr_fstype.clone();
         si.sysreqs_missed = rctx.missed_sysreqs();
         if let Some(rep) = rctx.report_sample() {
             si.iocost = rep.iocost.clone();
@@ -382,6 +441,10 @@ impl JobCtx {
         }
         si.hashd = rctx.hashd_knobs.clone();
         si.mem = rctx.mem_info().clone();
+        match rd_util::numa::numa_nodes() {
+            Ok(v) => si.numa_nodes = v,
+            Err(e) => warn!("job: Failed to detect NUMA topology ({:#})", &e),
+        }
     }
 
     pub fn run(&mut self, rctx: &mut RunCtx) -> Result<()> {
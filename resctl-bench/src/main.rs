@@ -71,6 +71,7 @@ impl Program {
         dir: &str,
         systemd_timeout: f64,
         dev: Option<&str>,
+        delegated: bool,
     ) -> Result<Vec<String>> {
         let mut args = vec![
             "--dir".into(),
@@ -86,6 +87,9 @@ impl Program {
             args.push("--dev".into());
             args.push(dev.unwrap().into());
         }
+        if delegated {
+            args.push("--delegated".into());
+        }
         Ok(args)
     }
 
@@ -100,6 +104,7 @@ impl Program {
             &args.dir,
             args.systemd_timeout,
             args.dev.as_deref(),
+            args.delegated,
         )?)
         .args(&["--linux-tar", "__SKIP__"])
         .args(&["--bypass", "--prepare"])
@@ -20,6 +20,8 @@ pub struct MergeId {
     pub mem_profile: u32,
     pub storage_model: Option<String>,
     pub storage_fwver: Option<String>,
+    #[serde(default)]
+    pub fstype: Option<String>,
     pub classifier: Option<String>,
 }
 
@@ -79,6 +81,11 @@ impl MergeSrc {
                 true => Some(srep.scr_dev_fwrev.clone()),
                 false => None,
             },
+            // Filesystem affects IO behavior, don't mix storage results.
+            fstype: match desc.merge_by_storage_model {
+                true => Some(si.fstype().to_string()),
+                false => None,
+            },
             classifier: self.bench.merge_classifier(&self.data),
         }
     }
@@ -92,6 +92,9 @@ impl MergeEntry {
         if let Some(fwver) = self.mid.storage_fwver.as_ref() {
             writeln!(out, "  fwver: {}", fwver).unwrap();
         }
+        if let Some(fstype) = self.mid.fstype.as_ref() {
+            writeln!(out, "  filesystem: {}", fstype).unwrap();
+        }
         if let Some(cl) = self.mid.classifier.as_ref() {
             writeln!(out, "  classifier: {}", &cl).unwrap();
         }
@@ -147,6 +147,7 @@ struct RunCtxInner {
     dir: String,
     systemd_timeout: f64,
     dev: Option<String>,
+    delegated: bool,
     linux_tar: Option<String>,
     verbosity: u32,
     sysreqs: BTreeSet<SysReq>,
@@ -171,6 +172,7 @@ impl RunCtxInner {
             &self.dir,
             self.systemd_timeout,
             self.dev.as_deref(),
+            self.delegated,
         )?);
         args.push("--reset".into());
         args.push("--keep-reports".into());
@@ -286,6 +288,7 @@ impl<'a, 'b> RunCtx<'a, 'b> {
                 dir: args.dir.clone(),
                 systemd_timeout: args.systemd_timeout,
                 dev: args.dev.clone(),
+                delegated: args.delegated,
                 linux_tar: args.linux_tar.clone(),
                 verbosity: args.verbosity,
                 sysreqs: Default::default(),
@@ -925,7 +928,10 @@ impl<'a, 'b> RunCtx<'a, 'b> {
         // On consecutive runs, some memory charges can shift to
         // workload.slice causing inaccuracies. Let's start with a clean
         // state.
-        write_one_line("/proc/sys/vm/drop_caches", "3").unwrap();
+        // /proc/sys is read-only in containers.
+        if !self.args.delegated {
+            write_one_line("/proc/sys/vm/drop_caches", "3").unwrap();
+        }
 
         if self.base.mem_initialized {
             extra_args.push(format!("--total-memory={}", self.base.mem.share));
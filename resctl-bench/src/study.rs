@@ -11,9 +11,11 @@ use super::run::RunCtx;
 use rd_agent_intf::Report;
 use rd_util::*;
 
+mod hashd_lat;
 mod iolat;
 mod rstat;
 
+pub use hashd_lat::{hashd_class_ctl_lat, hashd_ctl_lat, StudyHashdLatHist};
 pub use iolat::StudyIoLatPcts;
 pub use rstat::{ResourceStat, ResourceStatStudy, ResourceStatStudyCtx};
 
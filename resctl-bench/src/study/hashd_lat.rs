@@ -0,0 +1,78 @@
+use anyhow::Result;
+
+use super::{PctsMap, SelArg, Study};
+use rd_agent_intf::HashdReport;
+use rd_hashd_intf::{ClassStat, LatHist};
+
+/// The control percentile latency of a single report. Calculated from the
+/// report's histogram if available so that it's comparable to
+/// [`StudyHashdLatHist::ctl`].
+pub fn hashd_ctl_lat(rep: &HashdReport) -> f64 {
+    if rep.lat_hist.is_empty() {
+        rep.lat.ctl
+    } else {
+        rep.lat_hist.quantile(rep.lat_pct)
+    }
+}
+
+/// Per request class counterpart of [`hashd_ctl_lat`].
+pub fn hashd_class_ctl_lat(cs: &ClassStat) -> f64 {
+    if cs.lat_hist.is_empty() {
+        cs.lat.ctl
+    } else {
+        cs.lat_hist.quantile(cs.lat_target_pct)
+    }
+}
+
+/// Merges rd-hashd latency histograms across reports so that true request
+/// latency percentiles can be calculated over arbitrary periods.
+#[derive(Default)]
+pub struct StudyHashdLatHist {
+    hist: LatHist,
+    lat_pct: f64,
+}
+
+impl StudyHashdLatHist {
+    pub const PCTS: &'static [&'static str] = &[
+        "00", "01", "05", "10", "25", "50", "75", "90", "95", "99", "99.9", "99.99", "100",
+    ];
+
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The control percentile latency over all studied reports. None if no
+    /// histogram was available.
+    pub fn ctl(&self) -> Option<f64> {
+        match self.hist.is_empty() {
+            true => None,
+            false => Some(self.hist.quantile(self.lat_pct)),
+        }
+    }
+
+    /// Returns an empty map if no histogram was available, e.g. when the
+    /// reports were generated by an older rd-hashd.
+    pub fn result(&self, pcts: Option<&[&str]>) -> PctsMap {
+        let mut result = PctsMap::new();
+        if self.hist.is_empty() {
+            return result;
+        }
+        for pct in pcts.unwrap_or(Self::PCTS).iter() {
+            let q = pct.parse::<f64>().unwrap() / 100.0;
+            result.insert(pct.to_string(), self.hist.quantile(q));
+        }
+        result
+    }
+}
+
+impl Study for StudyHashdLatHist {
+    fn study(&mut self, arg: &SelArg) -> Result<()> {
+        self.hist += &arg.rep.hashd[0].lat_hist;
+        self.lat_pct = arg.rep.hashd[0].lat_pct;
+        Ok(())
+    }
+
+    fn as_study_mut(&mut self) -> &mut dyn Study {
+        self
+    }
+}
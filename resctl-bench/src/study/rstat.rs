@@ -280,6 +280,12 @@ pub struct ResourceStat {
     pub psi_cpu: PctsMap,
     pub psi_mem: (PctsMap, PctsMap),
     pub psi_io: (PctsMap, PctsMap),
+    #[serde(default)]
+    pub cpu_throttled: PctsMap,
+    #[serde(default)]
+    pub cpu_burst: PctsMap,
+    #[serde(default)]
+    pub cpu_runq_lat: PctsMap, // Populated only on root
 
     pub mem_stat: BTreeMap<String, PctsMap>,
     pub io_stat: BTreeMap<String, PctsMap>,
@@ -358,6 +364,33 @@ impl ResourceStat {
         print_pcts_line(out, fn_len, "mem-full%", &self.psi_mem.1, format_pct, None);
         print_pcts_line(out, fn_len, "io-some%", &self.psi_io.0, format_pct, None);
         print_pcts_line(out, fn_len, "io-full%", &self.psi_io.1, format_pct, None);
+        print_pcts_line(
+            out,
+            fn_len,
+            "cpu-thr%",
+            &self.cpu_throttled,
+            format_pct,
+            None,
+        );
+        // Burst CPU time in seconds per second.
+        print_pcts_line(
+            out,
+            fn_len,
+            "cpuburst/s",
+            &self.cpu_burst,
+            format_duration,
+            None,
+        );
+        if self.cpu_runq_lat.len() > 0 {
+            print_pcts_line(
+                out,
+                fn_len,
+                "runq-lat",
+                &self.cpu_runq_lat,
+                format_duration,
+                None,
+            );
+        }
 
         if opts.rstat == 0 {
             return;
@@ -400,6 +433,9 @@ pub struct ResourceStatStudyCtx {
     cpu_stall: RefCell<Option<f64>>,
     mem_stalls: (RefCell<Option<f64>>, RefCell<Option<f64>>),
     io_stalls: (RefCell<Option<f64>>, RefCell<Option<f64>>),
+    cpu_throttled: RefCell<Option<(u64, u64)>>,
+    cpu_burst: RefCell<Option<f64>>,
+    cpu_runq: RefCell<Option<(f64, u64)>>,
     stats: Vec<RefCell<Option<f64>>>,
 }
 
@@ -423,6 +459,9 @@ impl ResourceStatStudyCtx {
         self.mem_stalls.1.replace(None);
         self.io_stalls.0.replace(None);
         self.io_stalls.1.replace(None);
+        self.cpu_throttled.replace(None);
+        self.cpu_burst.replace(None);
+        self.cpu_runq.replace(None);
 
         for v in self.stats.iter() {
             v.replace(None);
@@ -449,6 +488,9 @@ pub struct ResourceStatStudy<'a> {
         Box<dyn StudyMeanPctsTrait + 'a>,
         Box<dyn StudyMeanPctsTrait + 'a>,
     ),
+    cpu_throttled_study: Box<dyn StudyMeanPctsTrait + 'a>,
+    cpu_burst_study: Box<dyn StudyMeanPctsTrait + 'a>,
+    cpu_runq_lat_study: Option<Box<dyn StudyMeanPctsTrait + 'a>>,
     mem_stat_studies: Vec<Box<dyn StudyMeanPctsTrait + 'a>>,
     io_stat_studies: Vec<Box<dyn StudyMeanPctsTrait + 'a>>,
     vmstat_studies: Vec<Box<dyn StudyMeanPctsTrait + 'a>>,
@@ -464,6 +506,15 @@ impl<'a> ResourceStatStudy<'a> {
         }
     }
 
+    fn calc_ratio(cur: (f64, f64), last: (f64, f64)) -> f64 {
+        let base = cur.1 - last.1;
+        if base > 0.0 {
+            ((cur.0 - last.0) / base).max(0.0)
+        } else {
+            0.0
+        }
+    }
+
     fn stat_study<F>(
         stat_sel: F,
         key: StatKey,
@@ -582,6 +633,51 @@ impl<'a> ResourceStatStudy<'a> {
                     None,
                 )),
             ),
+            cpu_throttled_study: Box::new(StudyMeanPcts::new(
+                sel_delta_calc(
+                    move |arg| {
+                        (
+                            arg.rep.usages[name].cpu_nr_throttled,
+                            arg.rep.usages[name].cpu_nr_periods,
+                        )
+                    },
+                    |_, cur, last| {
+                        Self::calc_ratio(
+                            (cur.0 as f64, cur.1 as f64),
+                            (last.0 as f64, last.1 as f64),
+                        )
+                        .min(1.0)
+                    },
+                    &ctx.cpu_throttled,
+                ),
+                None,
+            )),
+            cpu_burst_study: Box::new(StudyMeanPcts::new(
+                sel_delta(
+                    move |arg| arg.rep.usages[name].cpu_burst_usage,
+                    &ctx.cpu_burst,
+                ),
+                None,
+            )),
+            cpu_runq_lat_study: if name == ROOT_SLICE {
+                Some(Box::new(StudyMeanPcts::new(
+                    sel_delta_calc(
+                        move |arg| {
+                            (
+                                arg.rep.usages[name].cpu_runq_delay,
+                                arg.rep.usages[name].cpu_nr_timeslices,
+                            )
+                        },
+                        |_, cur, last| {
+                            Self::calc_ratio((cur.0, cur.1 as f64), (last.0, last.1 as f64))
+                        },
+                        &ctx.cpu_runq,
+                    ),
+                    None,
+                )))
+            } else {
+                None
+            },
             mem_stat_studies: MEM_STAT_KEYS
                 .iter()
                 .map(|key| {
@@ -633,7 +729,12 @@ impl<'a> ResourceStatStudy<'a> {
             self.psi_mem_studies.1.as_study_mut(),
             self.psi_io_studies.0.as_study_mut(),
             self.psi_io_studies.1.as_study_mut(),
+            self.cpu_throttled_study.as_study_mut(),
+            self.cpu_burst_study.as_study_mut(),
         ];
+        if let Some(study) = self.cpu_runq_lat_study.as_mut() {
+            studies.push(study.as_study_mut());
+        }
         for study in self
             .mem_stat_studies
             .iter_mut()
@@ -665,6 +766,12 @@ impl<'a> ResourceStatStudy<'a> {
                 self.psi_io_studies.0.result(pcts),
                 self.psi_io_studies.1.result(pcts),
             ),
+            cpu_throttled: self.cpu_throttled_study.result(pcts),
+            cpu_burst: self.cpu_burst_study.result(pcts),
+            cpu_runq_lat: match self.cpu_runq_lat_study.as_ref() {
+                Some(study) => study.result(pcts),
+                None => Default::default(),
+            },
             mem_stat: MEM_STAT_KEYS
                 .iter()
                 .zip(self.mem_stat_studies.iter())
@@ -20,6 +20,9 @@ use markup_rd::{RdCmd, RdDoc, RdKnob, RdPara, RdReset, RdSwitch};
 use rd_agent_intf::{Cmd, HashdCmd, SliceConfig, SysReq};
 use rd_util::*;
 
+// hashd-load-period knob range in seconds.
+const HASHD_LOAD_PERIOD_MAX: f64 = 600.0;
+
 lazy_static::lazy_static! {
     pub static ref DOCS: BTreeMap<String, &'static str> = load_docs();
     pub static ref CUR_DOC: RwLock<RdDoc> = RwLock::new(RdDoc {
@@ -280,6 +283,16 @@ fn exec_one_cmd(siv: &mut Cursive, cmd: &RdCmd) {
             RdKnob::HashdBLogBps => cs.hashd[1].log_bps = (wbps * *val).round() as u64,
             RdKnob::HashdAWeight => cs.hashd[0].weight = *val,
             RdKnob::HashdBWeight => cs.hashd[1].weight = *val,
+            RdKnob::HashdALoadAmp => cs.hashd[0].load_amplitude = *val,
+            RdKnob::HashdBLoadAmp => cs.hashd[1].load_amplitude = *val,
+            RdKnob::HashdALoadPeriod => {
+                cs.hashd[0].load_period = (*val * HASHD_LOAD_PERIOD_MAX).max(1.0)
+            }
+            RdKnob::HashdBLoadPeriod => {
+                cs.hashd[1].load_period = (*val * HASHD_LOAD_PERIOD_MAX).max(1.0)
+            }
+            RdKnob::HashdALoadSpike => cs.hashd[0].spike_ratio = *val,
+            RdKnob::HashdBLoadSpike => cs.hashd[1].spike_ratio = *val,
             RdKnob::SysCpuRatio => cs.sys_cpu_ratio = *val,
             RdKnob::SysIoRatio => cs.sys_io_ratio = *val,
             RdKnob::MemMargin => cs.mem_margin = *val,
@@ -442,6 +455,9 @@ fn format_knob_val(knob: &RdKnob, ratio: f64) -> String {
         RdKnob::HashdALogBps | RdKnob::HashdBLogBps => {
             format_size(ratio * bench.iocost.model.wbps as f64)
         }
+        RdKnob::HashdALoadPeriod | RdKnob::HashdBLoadPeriod => {
+            format_duration((ratio * HASHD_LOAD_PERIOD_MAX).max(1.0))
+        }
         RdKnob::MemMargin => format_size(ratio * total_memory() as f64),
         RdKnob::Balloon => format_size(ratio * total_memory() as f64),
         _ => format4_pct(ratio) + "%",
@@ -560,6 +576,12 @@ fn refresh_knobs(siv: &mut Cursive, doc: &RdDoc, cs: &CmdState) {
             RdKnob::HashdBLogBps => cs.hashd[1].log_bps as f64 / wbps,
             RdKnob::HashdAWeight => cs.hashd[0].weight,
             RdKnob::HashdBWeight => cs.hashd[1].weight,
+            RdKnob::HashdALoadAmp => cs.hashd[0].load_amplitude,
+            RdKnob::HashdBLoadAmp => cs.hashd[1].load_amplitude,
+            RdKnob::HashdALoadPeriod => cs.hashd[0].load_period / HASHD_LOAD_PERIOD_MAX,
+            RdKnob::HashdBLoadPeriod => cs.hashd[1].load_period / HASHD_LOAD_PERIOD_MAX,
+            RdKnob::HashdALoadSpike => cs.hashd[0].spike_ratio,
+            RdKnob::HashdBLoadSpike => cs.hashd[1].spike_ratio,
             RdKnob::SysCpuRatio => cs.sys_cpu_ratio,
             RdKnob::SysIoRatio => cs.sys_io_ratio,
             RdKnob::MemMargin => cs.mem_margin,
@@ -59,6 +59,12 @@ pub enum RdKnob {
     HashdBLogBps,
     HashdAWeight,
     HashdBWeight,
+    HashdALoadAmp,
+    HashdBLoadAmp,
+    HashdALoadPeriod,
+    HashdBLoadPeriod,
+    HashdALoadSpike,
+    HashdBLoadSpike,
     SysCpuRatio,
     SysIoRatio,
     MemMargin,
@@ -318,6 +324,9 @@ impl RdCmd {
                     "hashd-file-max" | "hashd-A-file-max" => RdKnob::HashdAFileMax,
                     "hashd-log-bps" | "hashd-A-write" => RdKnob::HashdALogBps,
                     "hashd-weight" | "hashd-A-weight" => RdKnob::HashdAWeight,
+                    "hashd-load-amp" | "hashd-A-load-amp" => RdKnob::HashdALoadAmp,
+                    "hashd-load-period" | "hashd-A-load-period" => RdKnob::HashdALoadPeriod,
+                    "hashd-load-spike" | "hashd-A-load-spike" => RdKnob::HashdALoadSpike,
                     "hashd-B-load" => RdKnob::HashdBLoad,
                     "hashd-B-lat-target-pct" => RdKnob::HashdBLatTargetPct,
                     "hashd-B-lat-target" => RdKnob::HashdBLatTarget,
@@ -328,6 +337,9 @@ impl RdCmd {
                     "hashd-B-file-max" => RdKnob::HashdBFileMax,
                     "hashd-B-log-bps" => RdKnob::HashdBLogBps,
                     "hashd-B-weight" => RdKnob::HashdBWeight,
+                    "hashd-B-load-amp" => RdKnob::HashdBLoadAmp,
+                    "hashd-B-load-period" => RdKnob::HashdBLoadPeriod,
+                    "hashd-B-load-spike" => RdKnob::HashdBLoadSpike,
                     "sys-cpu-ratio" => RdKnob::SysCpuRatio,
                     "sys-io-ratio" => RdKnob::SysIoRatio,
                     "mem-margin" => RdKnob::MemMargin,